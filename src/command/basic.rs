@@ -4,7 +4,8 @@ use redis_proc_macros::RedisCommand;
 
 use crate::{
     command::AsyncCommand,
-    redis_stream::ParseStream,
+    database::{ClientId, UnblockReason},
+    redis_stream::{ParseStream, RedisStream, StreamParseError},
     resp::{RedisWrite, RespType},
 };
 
@@ -82,9 +83,9 @@ impl AsyncCommand for TypeCmd {
 }
 
 #[derive(RedisCommand)]
-#[redis_command(syntax = "INFO ...")]
+#[redis_command(syntax = "INFO [section]")]
 pub struct Info {
-    query: Bytes,
+    query: Option<Bytes>,
 }
 
 #[async_trait]
@@ -94,12 +95,102 @@ impl AsyncCommand for Info {
         ctx: &crate::context::Context,
         buf: &mut bytes::BytesMut,
     ) -> Result<(), crate::redis::RedisError> {
-        match self.query.to_ascii_lowercase().as_slice() {
+        let query = self
+            .query
+            .as_ref()
+            .map(|query| query.to_ascii_lowercase())
+            .unwrap_or(b"default".to_vec());
+        match query.as_slice() {
             b"replication" => {
                 let info = ctx.app_data.replication.read().await;
                 info.write_to_buf(buf);
             }
-            _ => todo!(),
+            b"stats" => {
+                RespType::bulk_string(ctx.app_data.db.stats_info().await).write_to_buf(buf);
+            }
+            b"keyspace" => {
+                RespType::bulk_string(ctx.app_data.db.keyspace_info().await).write_to_buf(buf);
+            }
+            b"clients" => {
+                RespType::bulk_string(ctx.app_data.db.clients_info()).write_to_buf(buf);
+            }
+            b"default" | b"all" | b"everything" => {
+                let clients = ctx.app_data.db.clients_info();
+                let replication = ctx.app_data.replication.read().await.info();
+                let stats = ctx.app_data.db.stats_info().await;
+                let keyspace = ctx.app_data.db.keyspace_info().await;
+                RespType::bulk_string(format!(
+                    "# Clients\n{clients}\n# Replication\n{replication}\n# Stats\n{stats}\n# Keyspace\n{keyspace}"
+                ))
+                .write_to_buf(buf);
+            }
+            _ => RespType::bulk_string("").write_to_buf(buf),
+        }
+        Ok(())
+    }
+}
+
+#[derive(RedisCommand)]
+#[redis_command(syntax = "CLIENT <ID | UNBLOCK client-id [TIMEOUT | ERROR]>", no_parse)]
+pub struct Client {
+    subcommand: ClientSubcommand,
+}
+
+enum ClientSubcommand {
+    Id,
+    Unblock(ClientId, UnblockReason),
+}
+
+impl ParseStream for Client {
+    fn parse_stream(stream: &mut RedisStream) -> Result<Self, StreamParseError> {
+        let name = stream.next().ok_or(StreamParseError::EmptyArg)?;
+        let subcommand = match name.to_ascii_lowercase().as_slice() {
+            b"id" => ClientSubcommand::Id,
+            b"unblock" => {
+                let id = stream.parse::<u64>().map_err(|_| {
+                    StreamParseError::Other("value is not an integer or out of range".into())
+                })?;
+                let reason = match stream.next() {
+                    None => UnblockReason::Timeout,
+                    Some(reason) if reason.eq_ignore_ascii_case(b"timeout") => {
+                        UnblockReason::Timeout
+                    }
+                    Some(reason) if reason.eq_ignore_ascii_case(b"error") => UnblockReason::Error,
+                    Some(_) => {
+                        return Err(StreamParseError::Other(
+                            "CLIENT UNBLOCK reason should be TIMEOUT or ERROR".into(),
+                        ));
+                    }
+                };
+                ClientSubcommand::Unblock(id, reason)
+            }
+            _ => {
+                return Err(StreamParseError::Other(format!(
+                    "unknown subcommand '{}'. Try CLIENT HELP.",
+                    String::from_utf8_lossy(&name)
+                )));
+            }
+        };
+        if stream.peek().is_some() {
+            return Err(StreamParseError::Other("syntax error".into()));
+        }
+        Ok(Self { subcommand })
+    }
+}
+
+#[async_trait]
+impl AsyncCommand for Client {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        match self.subcommand {
+            ClientSubcommand::Id => RespType::Integer(ctx.client_id as i64).write_to_buf(buf),
+            ClientSubcommand::Unblock(id, reason) => {
+                let unblocked = ctx.app_data.db.unblock_client(id, reason);
+                RespType::Integer(unblocked as i64).write_to_buf(buf);
+            }
         }
         Ok(())
     }
@@ -7,10 +7,22 @@ use tokio::io::AsyncWriteExt;
 use crate::{
     account::AccountError,
     command::{
-        Acl, Auth, Blpop, ConfigGet, Discard, Echo, Exec, Geoadd, Geodist, Geopos, Geosearch, Get,
-        Incr, Info, Keys, LLen, Lpop, Lpush, Lrange, Multi, Ping, Psync, Publish, Replconf, Rpush,
-        Set, Subscribe, TypeCmd, Unsubscribe, Wait, Xadd, Xrange, Xread, Zadd, Zcard, Zrange,
-        Zrank, Zrem, Zscore,
+        Acl, Append, Auth, Bitcount, Bitfield, Bitop, Bitpos, Blmove, Blmpop, Blpop, Brpop,
+        Brpoplpush, Bzmpop, Bzpopmax, Bzpopmin, Client, ConfigGet, CopyCmd, Decr, Decrby, Del,
+        Discard, Echo, Exec, Exists, Expire, Expireat, Expiretime, Geoadd, Geodist, Geopos,
+        Geosearch, Get, Getbit, Getdel, Getex, Getrange, Hdel, Hexists, Hexpire, Hexpireat,
+        Hexpiretime, Hget, Hgetall, Hgetex, Hincrby, Hincrbyfloat, Hkeys, Hlen, Hmget, Hpersist,
+        Hpexpire, Hpexpireat, Hpexpiretime, Hpttl, Hrandfield, Hset, Hsetnx, Hstrlen, Httl, Hvals,
+        Incr, Incrby, Incrbyfloat, Info, Keys, LLen, Lindex, Linsert, Lmove, Lmpop, Lpop, Lpos,
+        Lpush, Lpushx, Lrange, Lrem, Lset, Ltrim, Mget, Mset, Msetnx, Multi, Persist, Pexpire,
+        Pexpireat, Pexpiretime, Pfadd, Pfcount, Pfmerge, Ping, Psync, Pttl, Publish, Rename,
+        Renamenx, Replconf, Rpop, Rpoplpush, Rpush, Rpushx, Sadd, Scard, Sdiff, Sdiffstore, Set,
+        Setbit, Setrange, Sinter, Sintercard, Sinterstore, Sismember, Smembers, Smismember, Smove,
+        Spop, Srandmember, Srem, Strlen, Subscribe, Sunion, Sunionstore, Touch, Ttl, TypeCmd,
+        Unlink, Unsubscribe, Wait, Xadd, Xrange, Xread, Zadd, Zcard, Zcount, Zdiff, Zdiffstore,
+        Zincrby, Zinter, Zintercard, Zinterstore, Zlexcount, Zmpop, Zmscore, Zpopmax, Zpopmin,
+        Zrandmember, Zrange, Zrangebylex, Zrangebyscore, Zrangestore, Zrank, Zrem, Zremrangebylex,
+        Zremrangebyrank, Zremrangebyscore, Zrevrangebyscore, Zrevrank, Zscore, Zunion, Zunionstore,
     },
     context::Context,
     redis::RedisError,
@@ -90,15 +102,109 @@ pub fn get_command(value: Bytes, stream: &mut RedisStream) -> Result<RedisComman
         b"echo" => Ok(Box::new(Echo::parse_stream(stream)?)),
         b"type" => Ok(Box::new(TypeCmd::parse_stream(stream)?)),
         b"keys" => Ok(Box::new(Keys::parse_stream(stream)?)),
+        b"del" => Ok(Box::new(Del::parse_stream(stream)?)),
+        b"unlink" => Ok(Box::new(Unlink::parse_stream(stream)?)),
+        b"exists" => Ok(Box::new(Exists::parse_stream(stream)?)),
+        b"touch" => Ok(Box::new(Touch::parse_stream(stream)?)),
+        b"rename" => Ok(Box::new(Rename::parse_stream(stream)?)),
+        b"renamenx" => Ok(Box::new(Renamenx::parse_stream(stream)?)),
+        b"copy" => Ok(Box::new(CopyCmd::parse_stream(stream)?)),
+        b"expire" => Ok(Box::new(Expire::parse_stream(stream)?)),
+        b"pexpire" => Ok(Box::new(Pexpire::parse_stream(stream)?)),
+        b"expireat" => Ok(Box::new(Expireat::parse_stream(stream)?)),
+        b"pexpireat" => Ok(Box::new(Pexpireat::parse_stream(stream)?)),
+        b"ttl" => Ok(Box::new(Ttl::parse_stream(stream)?)),
+        b"pttl" => Ok(Box::new(Pttl::parse_stream(stream)?)),
+        b"expiretime" => Ok(Box::new(Expiretime::parse_stream(stream)?)),
+        b"pexpiretime" => Ok(Box::new(Pexpiretime::parse_stream(stream)?)),
+        b"persist" => Ok(Box::new(Persist::parse_stream(stream)?)),
         b"set" => Ok(Box::new(Set::parse_stream(stream)?)),
         b"get" => Ok(Box::new(Get::parse_stream(stream)?)),
         b"incr" => Ok(Box::new(Incr::parse_stream(stream)?)),
+        b"hset" => Ok(Box::new(Hset::parse_stream(stream)?)),
+        b"hsetnx" => Ok(Box::new(Hsetnx::parse_stream(stream)?)),
+        b"hget" => Ok(Box::new(Hget::parse_stream(stream)?)),
+        b"hmget" => Ok(Box::new(Hmget::parse_stream(stream)?)),
+        b"hdel" => Ok(Box::new(Hdel::parse_stream(stream)?)),
+        b"hexists" => Ok(Box::new(Hexists::parse_stream(stream)?)),
+        b"hlen" => Ok(Box::new(Hlen::parse_stream(stream)?)),
+        b"hstrlen" => Ok(Box::new(Hstrlen::parse_stream(stream)?)),
+        b"hkeys" => Ok(Box::new(Hkeys::parse_stream(stream)?)),
+        b"hvals" => Ok(Box::new(Hvals::parse_stream(stream)?)),
+        b"hgetall" => Ok(Box::new(Hgetall::parse_stream(stream)?)),
+        b"hincrby" => Ok(Box::new(Hincrby::parse_stream(stream)?)),
+        b"hincrbyfloat" => Ok(Box::new(Hincrbyfloat::parse_stream(stream)?)),
+        b"hrandfield" => Ok(Box::new(Hrandfield::parse_stream(stream)?)),
+        b"hexpire" => Ok(Box::new(Hexpire::parse_stream(stream)?)),
+        b"hpexpire" => Ok(Box::new(Hpexpire::parse_stream(stream)?)),
+        b"hexpireat" => Ok(Box::new(Hexpireat::parse_stream(stream)?)),
+        b"hpexpireat" => Ok(Box::new(Hpexpireat::parse_stream(stream)?)),
+        b"httl" => Ok(Box::new(Httl::parse_stream(stream)?)),
+        b"hpttl" => Ok(Box::new(Hpttl::parse_stream(stream)?)),
+        b"hexpiretime" => Ok(Box::new(Hexpiretime::parse_stream(stream)?)),
+        b"hpexpiretime" => Ok(Box::new(Hpexpiretime::parse_stream(stream)?)),
+        b"hpersist" => Ok(Box::new(Hpersist::parse_stream(stream)?)),
+        b"hgetex" => Ok(Box::new(Hgetex::parse_stream(stream)?)),
+        b"sadd" => Ok(Box::new(Sadd::parse_stream(stream)?)),
+        b"srem" => Ok(Box::new(Srem::parse_stream(stream)?)),
+        b"smembers" => Ok(Box::new(Smembers::parse_stream(stream)?)),
+        b"sismember" => Ok(Box::new(Sismember::parse_stream(stream)?)),
+        b"smismember" => Ok(Box::new(Smismember::parse_stream(stream)?)),
+        b"scard" => Ok(Box::new(Scard::parse_stream(stream)?)),
+        b"spop" => Ok(Box::new(Spop::parse_stream(stream)?)),
+        b"srandmember" => Ok(Box::new(Srandmember::parse_stream(stream)?)),
+        b"smove" => Ok(Box::new(Smove::parse_stream(stream)?)),
+        b"sinter" => Ok(Box::new(Sinter::parse_stream(stream)?)),
+        b"sunion" => Ok(Box::new(Sunion::parse_stream(stream)?)),
+        b"sdiff" => Ok(Box::new(Sdiff::parse_stream(stream)?)),
+        b"sinterstore" => Ok(Box::new(Sinterstore::parse_stream(stream)?)),
+        b"sunionstore" => Ok(Box::new(Sunionstore::parse_stream(stream)?)),
+        b"sdiffstore" => Ok(Box::new(Sdiffstore::parse_stream(stream)?)),
+        b"sintercard" => Ok(Box::new(Sintercard::parse_stream(stream)?)),
+        b"pfadd" => Ok(Box::new(Pfadd::parse_stream(stream)?)),
+        b"pfcount" => Ok(Box::new(Pfcount::parse_stream(stream)?)),
+        b"pfmerge" => Ok(Box::new(Pfmerge::parse_stream(stream)?)),
+        b"setbit" => Ok(Box::new(Setbit::parse_stream(stream)?)),
+        b"getbit" => Ok(Box::new(Getbit::parse_stream(stream)?)),
+        b"bitcount" => Ok(Box::new(Bitcount::parse_stream(stream)?)),
+        b"bitpos" => Ok(Box::new(Bitpos::parse_stream(stream)?)),
+        b"bitop" => Ok(Box::new(Bitop::parse_stream(stream)?)),
+        b"bitfield" => Ok(Box::new(Bitfield::parse_stream(stream)?)),
+        b"incrby" => Ok(Box::new(Incrby::parse_stream(stream)?)),
+        b"decr" => Ok(Box::new(Decr::parse_stream(stream)?)),
+        b"decrby" => Ok(Box::new(Decrby::parse_stream(stream)?)),
+        b"incrbyfloat" => Ok(Box::new(Incrbyfloat::parse_stream(stream)?)),
+        b"mget" => Ok(Box::new(Mget::parse_stream(stream)?)),
+        b"mset" => Ok(Box::new(Mset::parse_stream(stream)?)),
+        b"msetnx" => Ok(Box::new(Msetnx::parse_stream(stream)?)),
+        b"append" => Ok(Box::new(Append::parse_stream(stream)?)),
+        b"strlen" => Ok(Box::new(Strlen::parse_stream(stream)?)),
+        b"getrange" => Ok(Box::new(Getrange::parse_stream(stream)?)),
+        b"setrange" => Ok(Box::new(Setrange::parse_stream(stream)?)),
+        b"getdel" => Ok(Box::new(Getdel::parse_stream(stream)?)),
+        b"getex" => Ok(Box::new(Getex::parse_stream(stream)?)),
         b"rpush" => Ok(Box::new(Rpush::parse_stream(stream)?)),
         b"lrange" => Ok(Box::new(Lrange::parse_stream(stream)?)),
         b"lpush" => Ok(Box::new(Lpush::parse_stream(stream)?)),
         b"llen" => Ok(Box::new(LLen::parse_stream(stream)?)),
         b"lpop" => Ok(Box::new(Lpop::parse_stream(stream)?)),
         b"blpop" => Ok(Box::new(Blpop::parse_stream(stream)?)),
+        b"brpop" => Ok(Box::new(Brpop::parse_stream(stream)?)),
+        b"lmpop" => Ok(Box::new(Lmpop::parse_stream(stream)?)),
+        b"blmpop" => Ok(Box::new(Blmpop::parse_stream(stream)?)),
+        b"rpop" => Ok(Box::new(Rpop::parse_stream(stream)?)),
+        b"lindex" => Ok(Box::new(Lindex::parse_stream(stream)?)),
+        b"lset" => Ok(Box::new(Lset::parse_stream(stream)?)),
+        b"linsert" => Ok(Box::new(Linsert::parse_stream(stream)?)),
+        b"lrem" => Ok(Box::new(Lrem::parse_stream(stream)?)),
+        b"ltrim" => Ok(Box::new(Ltrim::parse_stream(stream)?)),
+        b"lpos" => Ok(Box::new(Lpos::parse_stream(stream)?)),
+        b"lpushx" => Ok(Box::new(Lpushx::parse_stream(stream)?)),
+        b"rpushx" => Ok(Box::new(Rpushx::parse_stream(stream)?)),
+        b"lmove" => Ok(Box::new(Lmove::parse_stream(stream)?)),
+        b"rpoplpush" => Ok(Box::new(Rpoplpush::parse_stream(stream)?)),
+        b"blmove" => Ok(Box::new(Blmove::parse_stream(stream)?)),
+        b"brpoplpush" => Ok(Box::new(Brpoplpush::parse_stream(stream)?)),
         b"xadd" => Ok(Box::new(Xadd::parse_stream(stream)?)),
         b"xrange" => Ok(Box::new(Xrange::parse_stream(stream)?)),
         b"xread" => Ok(Box::new(Xread::parse_stream(stream)?)),
@@ -106,6 +212,7 @@ pub fn get_command(value: Bytes, stream: &mut RedisStream) -> Result<RedisComman
         b"exec" => Ok(Box::new(Exec {})),
         b"discard" => Ok(Box::new(Discard {})),
         b"info" => Ok(Box::new(Info::parse_stream(stream)?)),
+        b"client" => Ok(Box::new(Client::parse_stream(stream)?)),
         b"replconf" => Ok(Box::new(Replconf::parse_stream(stream)?)),
         b"psync" => Ok(Box::new(Psync::parse_stream(stream)?)),
         b"wait" => Ok(Box::new(Wait::parse_stream(stream)?)),
@@ -114,11 +221,37 @@ pub fn get_command(value: Bytes, stream: &mut RedisStream) -> Result<RedisComman
         b"unsubscribe" => Ok(Box::new(Unsubscribe::parse_stream(stream)?)),
         b"publish" => Ok(Box::new(Publish::parse_stream(stream)?)),
         b"zadd" => Ok(Box::new(Zadd::parse_stream(stream)?)),
+        b"zincrby" => Ok(Box::new(Zincrby::parse_stream(stream)?)),
+        b"zpopmin" => Ok(Box::new(Zpopmin::parse_stream(stream)?)),
+        b"zpopmax" => Ok(Box::new(Zpopmax::parse_stream(stream)?)),
+        b"bzpopmin" => Ok(Box::new(Bzpopmin::parse_stream(stream)?)),
+        b"bzpopmax" => Ok(Box::new(Bzpopmax::parse_stream(stream)?)),
+        b"zmpop" => Ok(Box::new(Zmpop::parse_stream(stream)?)),
+        b"bzmpop" => Ok(Box::new(Bzmpop::parse_stream(stream)?)),
         b"zrank" => Ok(Box::new(Zrank::parse_stream(stream)?)),
+        b"zrevrank" => Ok(Box::new(Zrevrank::parse_stream(stream)?)),
         b"zrange" => Ok(Box::new(Zrange::parse_stream(stream)?)),
+        b"zrangebyscore" => Ok(Box::new(Zrangebyscore::parse_stream(stream)?)),
+        b"zrevrangebyscore" => Ok(Box::new(Zrevrangebyscore::parse_stream(stream)?)),
+        b"zrangebylex" => Ok(Box::new(Zrangebylex::parse_stream(stream)?)),
+        b"zrangestore" => Ok(Box::new(Zrangestore::parse_stream(stream)?)),
+        b"zcount" => Ok(Box::new(Zcount::parse_stream(stream)?)),
+        b"zlexcount" => Ok(Box::new(Zlexcount::parse_stream(stream)?)),
+        b"zunion" => Ok(Box::new(Zunion::parse_stream(stream)?)),
+        b"zinter" => Ok(Box::new(Zinter::parse_stream(stream)?)),
+        b"zdiff" => Ok(Box::new(Zdiff::parse_stream(stream)?)),
+        b"zunionstore" => Ok(Box::new(Zunionstore::parse_stream(stream)?)),
+        b"zinterstore" => Ok(Box::new(Zinterstore::parse_stream(stream)?)),
+        b"zdiffstore" => Ok(Box::new(Zdiffstore::parse_stream(stream)?)),
+        b"zintercard" => Ok(Box::new(Zintercard::parse_stream(stream)?)),
         b"zcard" => Ok(Box::new(Zcard::parse_stream(stream)?)),
         b"zscore" => Ok(Box::new(Zscore::parse_stream(stream)?)),
         b"zrem" => Ok(Box::new(Zrem::parse_stream(stream)?)),
+        b"zmscore" => Ok(Box::new(Zmscore::parse_stream(stream)?)),
+        b"zremrangebyrank" => Ok(Box::new(Zremrangebyrank::parse_stream(stream)?)),
+        b"zremrangebyscore" => Ok(Box::new(Zremrangebyscore::parse_stream(stream)?)),
+        b"zremrangebylex" => Ok(Box::new(Zremrangebylex::parse_stream(stream)?)),
+        b"zrandmember" => Ok(Box::new(Zrandmember::parse_stream(stream)?)),
         b"geoadd" => Ok(Box::new(Geoadd::parse_stream(stream)?)),
         b"geopos" => Ok(Box::new(Geopos::parse_stream(stream)?)),
         b"geodist" => Ok(Box::new(Geodist::parse_stream(stream)?)),
@@ -144,10 +277,18 @@ pub enum CommandError {
     ExecWithoutMulti,
     #[error("DISCARD without MULTI")]
     DiscardWithoutMulti,
-    #[error("value is not an integer or out of range")]
-    IncrInvalid,
     #[error("{0}")]
     IncorrectArgument(String),
+    #[error("bit offset is not an integer or out of range")]
+    BitOffsetInvalid,
+    #[error("bit is not an integer or out of range")]
+    BitValueInvalid,
+    #[error("offset is out of range")]
+    OffsetOutOfRange,
+    #[error("string exceeds maximum allowed size (proto-max-bulk-len)")]
+    StringTooLong,
+    #[error("invalid expire time in '{0}' command")]
+    InvalidExpireTime(&'static str),
     #[error(
         "Can't execute '{0}': only (P|S)SUBSCRIBE / (P|S)UNSUBSCRIBE / PING / QUIT / RESET are allowed in this context"
     )]
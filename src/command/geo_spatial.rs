@@ -3,8 +3,9 @@ use bytes::Bytes;
 use redis_proc_macros::RedisCommand;
 
 use crate::{
-    command::{AsyncCommand, SymbolByRadius, SymbolFromLonLat},
-    database::Coordinates,
+    command::AsyncCommand,
+    database::{Coordinates, GeoOrder, GeoOrigin, GeoSearch, GeoShape, GeoUnit},
+    redis_stream::{ParseStream, RedisStream, StreamParseError},
     resp::{NullBulkString, RedisWrite, RespType},
 };
 
@@ -29,7 +30,7 @@ impl AsyncCommand for Geoadd {
             .app_data
             .db
             .insert_set_member(self.key.clone(), self.member.clone(), location as f64)
-            .await;
+            .await?;
         RespType::Integer(num as i64).write_to_buf(buf);
         Ok(())
     }
@@ -54,7 +55,7 @@ impl AsyncCommand for Geopos {
                 .app_data
                 .db
                 .get_set_member_score(&self.key, member)
-                .await
+                .await?
             {
                 results.push(RespType::from(Coordinates::decode(geo_code as u64)));
             } else {
@@ -85,7 +86,7 @@ impl AsyncCommand for Geodist {
             .app_data
             .db
             .get_distance(&self.key, &self.first, &self.second)
-            .await
+            .await?
         {
             RespType::bulk_string(distance.to_string()).write_to_buf(buf);
         } else {
@@ -95,19 +96,140 @@ impl AsyncCommand for Geodist {
     }
 }
 
+impl ParseStream for GeoUnit {
+    fn parse_stream(stream: &mut RedisStream) -> Result<Self, StreamParseError> {
+        let unit = stream.next().ok_or(StreamParseError::EmptyArg)?;
+        match unit.to_ascii_lowercase().as_slice() {
+            b"m" => Ok(GeoUnit::Metres),
+            b"km" => Ok(GeoUnit::Kilometres),
+            b"ft" => Ok(GeoUnit::Feet),
+            b"mi" => Ok(GeoUnit::Miles),
+            _ => Err(StreamParseError::Other(
+                "unsupported unit provided. please use M, KM, FT, MI".into(),
+            )),
+        }
+    }
+}
+
 #[derive(RedisCommand)]
-#[redis_command(syntax = "GEOSEARCH key FROMONLAT longitude latitude BYRADIUS radius m")]
+#[redis_command(
+    syntax = "GEOSEARCH key <FROMMEMBER member | FROMLONLAT longitude latitude> <BYRADIUS radius <M | KM | FT | MI> | BYBOX width height <M | KM | FT | MI>> [ASC | DESC] [COUNT count [ANY]] [WITHCOORD] [WITHDIST] [WITHHASH]",
+    no_parse
+)]
 pub struct Geosearch {
     key: Bytes,
-    #[allow(unused)]
-    fromlonlat: SymbolFromLonLat,
-    longitude: f64,
-    latitude: f64,
-    #[allow(unused)]
-    byradius: SymbolByRadius,
-    radius: f64,
-    #[allow(unused)]
-    metre: Bytes,
+    query: GeoSearch,
+    /// What distances are replied in
+    unit: GeoUnit,
+    with_coord: bool,
+    with_dist: bool,
+    with_hash: bool,
+}
+
+impl ParseStream for Geosearch {
+    fn parse_stream(stream: &mut RedisStream) -> Result<Self, StreamParseError> {
+        let key = stream.parse()?;
+        let float = |stream: &mut RedisStream| {
+            let value: Bytes = stream.parse()?;
+            std::str::from_utf8(&value)
+                .ok()
+                .and_then(|value| value.parse::<f64>().ok())
+                .filter(|value| !value.is_nan())
+                .ok_or_else(|| StreamParseError::Other("value is not a valid float".into()))
+        };
+        let mut origin = None;
+        let mut shape = None;
+        let mut unit = GeoUnit::default();
+        let mut order = None;
+        let mut count = None;
+        let mut any = false;
+        let (mut with_coord, mut with_dist, mut with_hash) = (false, false, false);
+        let mut origins = 0;
+        let mut shapes = 0;
+        while let Some(option) = stream.next() {
+            match option.to_ascii_lowercase().as_slice() {
+                b"frommember" => {
+                    origin = Some(GeoOrigin::Member(stream.parse()?));
+                    origins += 1;
+                }
+                b"fromlonlat" => {
+                    let longitude = float(stream)?;
+                    let latitude = float(stream)?;
+                    let position = Coordinates::new(latitude, longitude)
+                        .map_err(|err| StreamParseError::Other(err.to_string()))?;
+                    origin = Some(GeoOrigin::Position(position));
+                    origins += 1;
+                }
+                b"byradius" => {
+                    let radius = float(stream)?;
+                    if radius < 0.0 {
+                        return Err(StreamParseError::Other("radius cannot be negative".into()));
+                    }
+                    unit = stream.parse()?;
+                    shape = Some(GeoShape::Radius(radius * unit.metres()));
+                    shapes += 1;
+                }
+                b"bybox" => {
+                    let width = float(stream)?;
+                    let height = float(stream)?;
+                    if width < 0.0 || height < 0.0 {
+                        return Err(StreamParseError::Other(
+                            "height or width cannot be negative".into(),
+                        ));
+                    }
+                    unit = stream.parse()?;
+                    shape = Some(GeoShape::Box {
+                        width: width * unit.metres(),
+                        height: height * unit.metres(),
+                    });
+                    shapes += 1;
+                }
+                b"asc" => order = Some(GeoOrder::Asc),
+                b"desc" => order = Some(GeoOrder::Desc),
+                b"count" => {
+                    count = match stream.parse::<i64>() {
+                        Ok(count) if count > 0 => Some(count as usize),
+                        _ => return Err(StreamParseError::Other("COUNT must be > 0".into())),
+                    };
+                    if stream
+                        .peek()
+                        .is_some_and(|next| next.eq_ignore_ascii_case(b"any"))
+                    {
+                        stream.next();
+                        any = true;
+                    }
+                }
+                b"withcoord" => with_coord = true,
+                b"withdist" => with_dist = true,
+                b"withhash" => with_hash = true,
+                _ => return Err(StreamParseError::Other("syntax error".into())),
+            }
+        }
+        let (Some(origin), 1) = (origin, origins) else {
+            return Err(StreamParseError::Other(
+                "exactly one of FROMMEMBER or FROMLONLAT can be specified for geosearch".into(),
+            ));
+        };
+        let (Some(shape), 1) = (shape, shapes) else {
+            return Err(StreamParseError::Other(
+                "exactly one of BYRADIUS and BYBOX can be specified for geosearch".into(),
+            ));
+        };
+        Ok(Self {
+            key,
+            query: GeoSearch {
+                origin,
+                shape,
+                order,
+                count,
+                any,
+            },
+            unit,
+            with_coord,
+            with_dist,
+            with_hash,
+        })
+    }
 }
 
 #[async_trait]
@@ -117,16 +239,32 @@ impl AsyncCommand for Geosearch {
         ctx: &crate::context::Context,
         buf: &mut bytes::BytesMut,
     ) -> Result<(), crate::redis::RedisError> {
-        let results = ctx
-            .app_data
-            .db
-            .search_area(
-                &self.key,
-                Coordinates::new(self.latitude, self.longitude)?,
-                self.radius,
-            )
-            .await;
-        results.write_to_buf(buf);
+        let matches = ctx.app_data.db.geo_search(&self.key, &self.query).await?;
+        // Like Redis, a plain search replies with just the members, and
+        // otherwise each member comes with its distance, hash and position
+        // in that order
+        let reply = matches
+            .into_iter()
+            .map(|found| {
+                let member = RespType::BulkString(found.member);
+                if !(self.with_coord || self.with_dist || self.with_hash) {
+                    return member;
+                }
+                let mut item = vec![member];
+                if self.with_dist {
+                    let distance = found.distance / self.unit.metres();
+                    item.push(RespType::bulk_string(format!("{distance:.4}")));
+                }
+                if self.with_hash {
+                    item.push(RespType::Integer(found.hash as i64));
+                }
+                if self.with_coord {
+                    item.push(RespType::from(found.position));
+                }
+                RespType::Array(item)
+            })
+            .collect();
+        RespType::Array(reply).write_to_buf(buf);
         Ok(())
     }
 }
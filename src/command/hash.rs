@@ -0,0 +1,767 @@
+use std::time::SystemTime;
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use indexmap::IndexMap;
+use redis_proc_macros::RedisCommand;
+
+use crate::{
+    command::{AsyncCommand, CommandError, expire_deadline},
+    database::{ExpireCondition, unix_millis},
+    redis_stream::{ParseStream, RedisStream, StreamParseError},
+    resp::{NullBulkString, RedisWrite, RespType},
+};
+
+#[derive(RedisCommand)]
+#[redis_command(syntax = "HSET key field value [field value ...]", write)]
+pub struct Hset {
+    key: Bytes,
+    fields: IndexMap<Bytes, Bytes>,
+}
+
+#[async_trait]
+impl AsyncCommand for Hset {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        if self.fields.is_empty() {
+            return Err(StreamParseError::EmptyArg.into());
+        }
+        let added = ctx.app_data.db.hash_set(&self.key, &self.fields).await?;
+        RespType::Integer(added as i64).write_to_buf(buf);
+        Ok(())
+    }
+}
+
+#[derive(RedisCommand)]
+#[redis_command(syntax = "HSETNX key field value", write)]
+pub struct Hsetnx {
+    key: Bytes,
+    field: Bytes,
+    value: Bytes,
+}
+
+#[async_trait]
+impl AsyncCommand for Hsetnx {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        let set = ctx
+            .app_data
+            .db
+            .hash_set_nx(&self.key, &self.field, &self.value)
+            .await?;
+        RespType::Integer(set as i64).write_to_buf(buf);
+        Ok(())
+    }
+}
+
+#[derive(RedisCommand)]
+#[redis_command(syntax = "HGET key field")]
+pub struct Hget {
+    key: Bytes,
+    field: Bytes,
+}
+
+#[async_trait]
+impl AsyncCommand for Hget {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        match ctx.app_data.db.hash_get(&self.key, &self.field).await? {
+            Some(value) => RespType::BulkString(value).write_to_buf(buf),
+            None => NullBulkString.write_to_buf(buf),
+        }
+        Ok(())
+    }
+}
+
+#[derive(RedisCommand)]
+#[redis_command(syntax = "HMGET key field [field ...]")]
+pub struct Hmget {
+    key: Bytes,
+    fields: Vec<Bytes>,
+}
+
+#[async_trait]
+impl AsyncCommand for Hmget {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        let values = ctx
+            .app_data
+            .db
+            .hash_get_many(&self.key, &self.fields)
+            .await?;
+        RespType::Array(
+            values
+                .into_iter()
+                .map(|value| match value {
+                    Some(value) => RespType::BulkString(value),
+                    None => RespType::NullBulkString,
+                })
+                .collect(),
+        )
+        .write_to_buf(buf);
+        Ok(())
+    }
+}
+
+#[derive(RedisCommand)]
+#[redis_command(syntax = "HDEL key field [field ...]", write)]
+pub struct Hdel {
+    key: Bytes,
+    fields: Vec<Bytes>,
+}
+
+#[async_trait]
+impl AsyncCommand for Hdel {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        let removed = ctx.app_data.db.hash_del(&self.key, &self.fields).await?;
+        RespType::Integer(removed as i64).write_to_buf(buf);
+        Ok(())
+    }
+}
+
+#[derive(RedisCommand)]
+#[redis_command(syntax = "HEXISTS key field")]
+pub struct Hexists {
+    key: Bytes,
+    field: Bytes,
+}
+
+#[async_trait]
+impl AsyncCommand for Hexists {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        let exists = ctx.app_data.db.hash_exists(&self.key, &self.field).await?;
+        RespType::Integer(exists as i64).write_to_buf(buf);
+        Ok(())
+    }
+}
+
+#[derive(RedisCommand)]
+#[redis_command(syntax = "HLEN key")]
+pub struct Hlen {
+    key: Bytes,
+}
+
+#[async_trait]
+impl AsyncCommand for Hlen {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        let len = ctx.app_data.db.hash_len(&self.key).await?;
+        RespType::Integer(len as i64).write_to_buf(buf);
+        Ok(())
+    }
+}
+
+#[derive(RedisCommand)]
+#[redis_command(syntax = "HSTRLEN key field")]
+pub struct Hstrlen {
+    key: Bytes,
+    field: Bytes,
+}
+
+#[async_trait]
+impl AsyncCommand for Hstrlen {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        let len = ctx.app_data.db.hash_str_len(&self.key, &self.field).await?;
+        RespType::Integer(len as i64).write_to_buf(buf);
+        Ok(())
+    }
+}
+
+#[derive(RedisCommand)]
+#[redis_command(syntax = "HKEYS key")]
+pub struct Hkeys {
+    key: Bytes,
+}
+
+#[async_trait]
+impl AsyncCommand for Hkeys {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        let entries = ctx.app_data.db.hash_get_all(&self.key).await?;
+        RespType::Array(
+            entries
+                .into_iter()
+                .map(|(field, _)| RespType::BulkString(field))
+                .collect(),
+        )
+        .write_to_buf(buf);
+        Ok(())
+    }
+}
+
+#[derive(RedisCommand)]
+#[redis_command(syntax = "HVALS key")]
+pub struct Hvals {
+    key: Bytes,
+}
+
+#[async_trait]
+impl AsyncCommand for Hvals {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        let entries = ctx.app_data.db.hash_get_all(&self.key).await?;
+        RespType::Array(
+            entries
+                .into_iter()
+                .map(|(_, value)| RespType::BulkString(value))
+                .collect(),
+        )
+        .write_to_buf(buf);
+        Ok(())
+    }
+}
+
+#[derive(RedisCommand)]
+#[redis_command(syntax = "HGETALL key")]
+pub struct Hgetall {
+    key: Bytes,
+}
+
+#[async_trait]
+impl AsyncCommand for Hgetall {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        let entries = ctx.app_data.db.hash_get_all(&self.key).await?;
+        RespType::Array(
+            entries
+                .into_iter()
+                .flat_map(|(field, value)| {
+                    [RespType::BulkString(field), RespType::BulkString(value)]
+                })
+                .collect(),
+        )
+        .write_to_buf(buf);
+        Ok(())
+    }
+}
+
+#[derive(RedisCommand)]
+#[redis_command(syntax = "HINCRBY key field increment", write)]
+pub struct Hincrby {
+    key: Bytes,
+    field: Bytes,
+    increment: i64,
+}
+
+#[async_trait]
+impl AsyncCommand for Hincrby {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        let value = ctx
+            .app_data
+            .db
+            .hash_incr(&self.key, &self.field, self.increment)
+            .await?;
+        RespType::Integer(value).write_to_buf(buf);
+        Ok(())
+    }
+}
+
+#[derive(RedisCommand)]
+#[redis_command(syntax = "HINCRBYFLOAT key field increment", write)]
+pub struct Hincrbyfloat {
+    key: Bytes,
+    field: Bytes,
+    increment: f64,
+}
+
+#[async_trait]
+impl AsyncCommand for Hincrbyfloat {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        let value = ctx
+            .app_data
+            .db
+            .hash_incr_float(&self.key, &self.field, self.increment)
+            .await?;
+        RespType::BulkString(value).write_to_buf(buf);
+        Ok(())
+    }
+}
+
+#[derive(RedisCommand)]
+#[redis_command(syntax = "HRANDFIELD key [count [WITHVALUES]]", no_parse)]
+pub struct Hrandfield {
+    key: Bytes,
+    count: Option<i64>,
+    with_values: bool,
+}
+
+impl ParseStream for Hrandfield {
+    fn parse_stream(stream: &mut RedisStream) -> Result<Self, StreamParseError> {
+        let key = stream.parse()?;
+        let count = stream.parse::<Option<i64>>()?;
+        let with_values = match stream.next() {
+            Some(next) if count.is_some() && next.eq_ignore_ascii_case(b"withvalues") => true,
+            Some(_) => return Err(StreamParseError::Other("syntax error".into())),
+            None => false,
+        };
+        if stream.peek().is_some() {
+            return Err(StreamParseError::Other("syntax error".into()));
+        }
+        Ok(Self {
+            key,
+            count,
+            with_values,
+        })
+    }
+}
+
+#[async_trait]
+impl AsyncCommand for Hrandfield {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        let Some(count) = self.count else {
+            match ctx.app_data.db.hash_rand_fields(&self.key, 1).await?.pop() {
+                Some((field, _)) => RespType::BulkString(field).write_to_buf(buf),
+                None => NullBulkString.write_to_buf(buf),
+            }
+            return Ok(());
+        };
+        let entries = ctx.app_data.db.hash_rand_fields(&self.key, count).await?;
+        RespType::Array(
+            entries
+                .into_iter()
+                .flat_map(|(field, value)| {
+                    let value = self.with_values.then_some(RespType::BulkString(value));
+                    std::iter::once(RespType::BulkString(field)).chain(value)
+                })
+                .collect(),
+        )
+        .write_to_buf(buf);
+        Ok(())
+    }
+}
+
+/// The `FIELDS numfields field [field ...]` block ending the commands that
+/// work on field TTLs
+pub struct HashFields(Vec<Bytes>);
+
+impl ParseStream for HashFields {
+    fn parse_stream(stream: &mut RedisStream) -> Result<Self, StreamParseError> {
+        if !stream
+            .next()
+            .is_some_and(|next| next.eq_ignore_ascii_case(b"fields"))
+        {
+            return Err(StreamParseError::Other(
+                "Mandatory argument FIELDS is missing or not at the right position".into(),
+            ));
+        }
+        let num_fields = match stream.parse::<i64>() {
+            Ok(num_fields) if num_fields > 0 => num_fields as usize,
+            _ => {
+                return Err(StreamParseError::Other(
+                    "Parameter `numFields` should be greater than 0".into(),
+                ));
+            }
+        };
+        let fields: Vec<Bytes> = stream.by_ref().collect();
+        if fields.len() != num_fields {
+            return Err(StreamParseError::Other(
+                "The `numfields` parameter must match the number of arguments".into(),
+            ));
+        }
+        Ok(Self(fields))
+    }
+}
+
+/// The optional condition of `HEXPIRE` and friends followed by the fields
+pub struct HashExpireFields {
+    condition: ExpireCondition,
+    fields: HashFields,
+}
+
+impl ParseStream for HashExpireFields {
+    fn parse_stream(stream: &mut RedisStream) -> Result<Self, StreamParseError> {
+        let condition = match stream.peek() {
+            Some(next) if !next.eq_ignore_ascii_case(b"fields") => {
+                let condition = match next.to_ascii_lowercase().as_slice() {
+                    b"nx" => ExpireCondition::Nx,
+                    b"xx" => ExpireCondition::Xx,
+                    b"gt" => ExpireCondition::Gt,
+                    b"lt" => ExpireCondition::Lt,
+                    _ => {
+                        return Err(StreamParseError::Other(
+                            "Mandatory argument FIELDS is missing or not at the right position"
+                                .into(),
+                        ));
+                    }
+                };
+                stream.next();
+                condition
+            }
+            _ => ExpireCondition::Always,
+        };
+        Ok(Self {
+            condition,
+            fields: stream.parse()?,
+        })
+    }
+}
+
+/// Shared by the commands setting field TTLs, `scale` converts `time` to
+/// milliseconds
+async fn hash_expire(
+    ctx: &crate::context::Context,
+    buf: &mut bytes::BytesMut,
+    key: &Bytes,
+    (time, scale, relative): (i64, i64, bool),
+    args: &HashExpireFields,
+    command: &'static str,
+) -> Result<(), crate::redis::RedisError> {
+    if time < 0 {
+        return Err(CommandError::InvalidExpireTime(command).into());
+    }
+    let deadline = expire_deadline(time, scale, relative, command)?;
+    let replies = ctx
+        .app_data
+        .db
+        .hash_expire(key, &args.fields.0, deadline, args.condition)
+        .await?;
+    RespType::Array(replies.into_iter().map(RespType::Integer).collect()).write_to_buf(buf);
+    Ok(())
+}
+
+#[derive(RedisCommand)]
+#[redis_command(
+    syntax = "HEXPIRE key seconds [NX | XX | GT | LT] FIELDS numfields field [field ...]",
+    write
+)]
+pub struct Hexpire {
+    key: Bytes,
+    seconds: i64,
+    args: HashExpireFields,
+}
+
+#[async_trait]
+impl AsyncCommand for Hexpire {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        let time = (self.seconds, 1000, true);
+        hash_expire(ctx, buf, &self.key, time, &self.args, "hexpire").await
+    }
+}
+
+#[derive(RedisCommand)]
+#[redis_command(
+    syntax = "HPEXPIRE key milliseconds [NX | XX | GT | LT] FIELDS numfields field [field ...]",
+    write
+)]
+pub struct Hpexpire {
+    key: Bytes,
+    milliseconds: i64,
+    args: HashExpireFields,
+}
+
+#[async_trait]
+impl AsyncCommand for Hpexpire {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        let time = (self.milliseconds, 1, true);
+        hash_expire(ctx, buf, &self.key, time, &self.args, "hpexpire").await
+    }
+}
+
+#[derive(RedisCommand)]
+#[redis_command(
+    syntax = "HEXPIREAT key unix-time-seconds [NX | XX | GT | LT] FIELDS numfields field [field ...]",
+    write
+)]
+pub struct Hexpireat {
+    key: Bytes,
+    seconds: i64,
+    args: HashExpireFields,
+}
+
+#[async_trait]
+impl AsyncCommand for Hexpireat {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        let time = (self.seconds, 1000, false);
+        hash_expire(ctx, buf, &self.key, time, &self.args, "hexpireat").await
+    }
+}
+
+#[derive(RedisCommand)]
+#[redis_command(
+    syntax = "HPEXPIREAT key unix-time-milliseconds [NX | XX | GT | LT] FIELDS numfields field [field ...]",
+    write
+)]
+pub struct Hpexpireat {
+    key: Bytes,
+    milliseconds: i64,
+    args: HashExpireFields,
+}
+
+#[async_trait]
+impl AsyncCommand for Hpexpireat {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        let time = (self.milliseconds, 1, false);
+        hash_expire(ctx, buf, &self.key, time, &self.args, "hpexpireat").await
+    }
+}
+
+/// Shared by the commands reading field TTLs, `reply` turns a deadline into
+/// the wanted unit
+async fn hash_ttl(
+    ctx: &crate::context::Context,
+    buf: &mut bytes::BytesMut,
+    key: &Bytes,
+    fields: &HashFields,
+    reply: impl Fn(SystemTime) -> i64,
+) -> Result<(), crate::redis::RedisError> {
+    let ttls = ctx.app_data.db.hash_field_ttls(key, &fields.0).await?;
+    RespType::Array(
+        ttls.iter()
+            .map(|ttl| RespType::Integer(ttl.reply(&reply)))
+            .collect(),
+    )
+    .write_to_buf(buf);
+    Ok(())
+}
+
+fn remaining_millis(time: SystemTime) -> i64 {
+    (unix_millis(time) - unix_millis(SystemTime::now())).max(0)
+}
+
+#[derive(RedisCommand)]
+#[redis_command(syntax = "HTTL key FIELDS numfields field [field ...]")]
+pub struct Httl {
+    key: Bytes,
+    fields: HashFields,
+}
+
+#[async_trait]
+impl AsyncCommand for Httl {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        hash_ttl(ctx, buf, &self.key, &self.fields, |time| {
+            (remaining_millis(time) + 999) / 1000
+        })
+        .await
+    }
+}
+
+#[derive(RedisCommand)]
+#[redis_command(syntax = "HPTTL key FIELDS numfields field [field ...]")]
+pub struct Hpttl {
+    key: Bytes,
+    fields: HashFields,
+}
+
+#[async_trait]
+impl AsyncCommand for Hpttl {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        hash_ttl(ctx, buf, &self.key, &self.fields, remaining_millis).await
+    }
+}
+
+#[derive(RedisCommand)]
+#[redis_command(syntax = "HEXPIRETIME key FIELDS numfields field [field ...]")]
+pub struct Hexpiretime {
+    key: Bytes,
+    fields: HashFields,
+}
+
+#[async_trait]
+impl AsyncCommand for Hexpiretime {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        hash_ttl(ctx, buf, &self.key, &self.fields, |time| {
+            unix_millis(time) / 1000
+        })
+        .await
+    }
+}
+
+#[derive(RedisCommand)]
+#[redis_command(syntax = "HPEXPIRETIME key FIELDS numfields field [field ...]")]
+pub struct Hpexpiretime {
+    key: Bytes,
+    fields: HashFields,
+}
+
+#[async_trait]
+impl AsyncCommand for Hpexpiretime {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        hash_ttl(ctx, buf, &self.key, &self.fields, unix_millis).await
+    }
+}
+
+#[derive(RedisCommand)]
+#[redis_command(syntax = "HPERSIST key FIELDS numfields field [field ...]", write)]
+pub struct Hpersist {
+    key: Bytes,
+    fields: HashFields,
+}
+
+#[async_trait]
+impl AsyncCommand for Hpersist {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        let replies = ctx
+            .app_data
+            .db
+            .hash_persist(&self.key, &self.fields.0)
+            .await?;
+        RespType::Array(replies.into_iter().map(RespType::Integer).collect()).write_to_buf(buf);
+        Ok(())
+    }
+}
+
+/// How `HGETEX` changes the TTL of the fields it reads
+#[derive(Debug)]
+pub enum HashGetExOptions {
+    Ex(i64),
+    Px(i64),
+    Exat(i64),
+    Pxat(i64),
+    Persist,
+}
+
+impl ParseStream for HashGetExOptions {
+    fn parse_stream(stream: &mut RedisStream) -> Result<Self, StreamParseError> {
+        let Some(next) = stream.next() else {
+            return Err(StreamParseError::EmptyArg);
+        };
+        match next.to_ascii_lowercase().as_slice() {
+            b"ex" => Ok(HashGetExOptions::Ex(stream.parse()?)),
+            b"px" => Ok(HashGetExOptions::Px(stream.parse()?)),
+            b"exat" => Ok(HashGetExOptions::Exat(stream.parse()?)),
+            b"pxat" => Ok(HashGetExOptions::Pxat(stream.parse()?)),
+            b"persist" => Ok(HashGetExOptions::Persist),
+            _ => Err(StreamParseError::Other("syntax error".into())),
+        }
+    }
+}
+
+impl HashGetExOptions {
+    /// The new deadline, `None` when the TTL is cleared
+    fn deadline(&self) -> Result<Option<SystemTime>, CommandError> {
+        let (time, scale, relative) = match *self {
+            HashGetExOptions::Ex(seconds) => (seconds, 1000, true),
+            HashGetExOptions::Px(milliseconds) => (milliseconds, 1, true),
+            HashGetExOptions::Exat(seconds) => (seconds, 1000, false),
+            HashGetExOptions::Pxat(milliseconds) => (milliseconds, 1, false),
+            HashGetExOptions::Persist => return Ok(None),
+        };
+        if time < 0 {
+            return Err(CommandError::InvalidExpireTime("hgetex"));
+        }
+        expire_deadline(time, scale, relative, "hgetex").map(Some)
+    }
+}
+
+#[derive(RedisCommand)]
+#[redis_command(
+    syntax = "HGETEX key [EX seconds | PX milliseconds | EXAT unix-time-seconds | PXAT unix-time-milliseconds | PERSIST] FIELDS numfields field [field ...]",
+    write
+)]
+pub struct Hgetex {
+    key: Bytes,
+    expiry: Option<HashGetExOptions>,
+    fields: HashFields,
+}
+
+#[async_trait]
+impl AsyncCommand for Hgetex {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        let expiry = self
+            .expiry
+            .as_ref()
+            .map(HashGetExOptions::deadline)
+            .transpose()?;
+        let values = ctx
+            .app_data
+            .db
+            .hash_get_ex(&self.key, &self.fields.0, expiry)
+            .await?;
+        RespType::Array(
+            values
+                .into_iter()
+                .map(|value| value.map_or(RespType::NullBulkString, RespType::BulkString))
+                .collect(),
+        )
+        .write_to_buf(buf);
+        Ok(())
+    }
+}
@@ -0,0 +1,74 @@
+use async_trait::async_trait;
+use bytes::Bytes;
+use redis_proc_macros::RedisCommand;
+
+use crate::{
+    command::AsyncCommand,
+    redis_stream::StreamParseError,
+    resp::{RedisWrite, RespType},
+};
+
+#[derive(RedisCommand)]
+#[redis_command(syntax = "PFADD key [element [element ...]]", write)]
+pub struct Pfadd {
+    key: Bytes,
+    elements: Vec<Bytes>,
+}
+
+#[async_trait]
+impl AsyncCommand for Pfadd {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        let updated = ctx.app_data.db.pf_add(&self.key, &self.elements).await?;
+        RespType::Integer(updated as i64).write_to_buf(buf);
+        Ok(())
+    }
+}
+
+#[derive(RedisCommand)]
+#[redis_command(syntax = "PFCOUNT key [key ...]")]
+pub struct Pfcount {
+    keys: Vec<Bytes>,
+}
+
+#[async_trait]
+impl AsyncCommand for Pfcount {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        if self.keys.is_empty() {
+            return Err(StreamParseError::EmptyArg.into());
+        }
+        let count = ctx.app_data.db.pf_count(&self.keys).await?;
+        RespType::Integer(count as i64).write_to_buf(buf);
+        Ok(())
+    }
+}
+
+#[derive(RedisCommand)]
+#[redis_command(syntax = "PFMERGE destkey [sourcekey [sourcekey ...]]", write)]
+pub struct Pfmerge {
+    destination: Bytes,
+    sources: Vec<Bytes>,
+}
+
+#[async_trait]
+impl AsyncCommand for Pfmerge {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        ctx.app_data
+            .db
+            .pf_merge(&self.destination, &self.sources)
+            .await?;
+        RespType::simple_string("OK").write_to_buf(buf);
+        Ok(())
+    }
+}
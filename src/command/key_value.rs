@@ -8,6 +8,11 @@ use tokio::time::Instant;
 
 use crate::{
     command::{AsyncCommand, CommandError},
+    database::{
+        BitFieldOp, BitFieldType, BitOperation, BitOverflow, BitRange, BitUnit, DatabaseError,
+        SetCondition, SetOptions,
+    },
+    pair::Pair,
     redis_stream::{ParseStream, RedisStream, StreamParseError},
     resp::{NullBulkString, RedisWrite, RespType},
 };
@@ -24,7 +29,7 @@ impl AsyncCommand for Get {
         ctx: &crate::context::Context,
         buf: &mut bytes::BytesMut,
     ) -> Result<(), crate::redis::RedisError> {
-        match ctx.app_data.db.get_string(&self.key).await {
+        match ctx.app_data.db.get_string(&self.key).await? {
             Some(val) => RespType::BulkString(val).write_to_buf(buf),
             None => NullBulkString.write_to_buf(buf),
         }
@@ -36,14 +41,60 @@ impl AsyncCommand for Get {
 #[redis_command(
     syntax = "SET key value [NX | XX] [GET] [EX seconds | PX milliseconds |\
     EXAT unix-time-seconds | PXAT unix-time-milliseconds | KEEPTTL]",
+    no_parse,
     write
 )]
 pub struct Set {
     key: Bytes,
     value: Bytes,
+    condition: Option<SetCondition>,
+    get: bool,
     expiry: Option<SetExpiryOptions>,
 }
 
+impl ParseStream for Set {
+    fn parse_stream(stream: &mut RedisStream) -> Result<Self, StreamParseError> {
+        let key = stream.parse()?;
+        let value = stream.parse()?;
+        let mut condition = None;
+        let mut get = false;
+        let mut expiry = None;
+        // The options can come in any order but each group only once
+        while let Some(next) = stream.peek() {
+            match next.to_ascii_lowercase().as_slice() {
+                option @ (b"nx" | b"xx") => {
+                    if condition.is_some() {
+                        return Err(StreamParseError::Other("syntax error".into()));
+                    }
+                    condition = Some(if option == b"nx" {
+                        SetCondition::Nx
+                    } else {
+                        SetCondition::Xx
+                    });
+                    stream.next();
+                }
+                b"get" => {
+                    get = true;
+                    stream.next();
+                }
+                _ => {
+                    if expiry.is_some() {
+                        return Err(StreamParseError::Other("syntax error".into()));
+                    }
+                    expiry = Some(stream.parse()?);
+                }
+            }
+        }
+        Ok(Self {
+            key,
+            value,
+            condition,
+            get,
+            expiry,
+        })
+    }
+}
+
 #[derive(Debug)]
 pub enum SetExpiryOptions {
     // Seconds
@@ -75,6 +126,39 @@ impl ParseStream for SetExpiryOptions {
     }
 }
 
+impl SetExpiryOptions {
+    /// The deadline the option asks for, `KEEPTTL` has none
+    fn deadline(
+        &self,
+        command: &'static str,
+    ) -> Result<Option<Either<Instant, SystemTime>>, CommandError> {
+        let deadline = match self {
+            SetExpiryOptions::Ex(0)
+            | SetExpiryOptions::Px(0)
+            | SetExpiryOptions::Exat(0)
+            | SetExpiryOptions::Pxat(0) => {
+                return Err(CommandError::InvalidExpireTime(command));
+            }
+            SetExpiryOptions::Ex(seconds) => Instant::now()
+                .checked_add(Duration::from_secs(*seconds))
+                .map(Either::Left),
+            SetExpiryOptions::Px(milliseconds) => Instant::now()
+                .checked_add(Duration::from_millis(*milliseconds))
+                .map(Either::Left),
+            SetExpiryOptions::Exat(seconds) => UNIX_EPOCH
+                .checked_add(Duration::from_secs(*seconds))
+                .map(Either::Right),
+            SetExpiryOptions::Pxat(milliseconds) => UNIX_EPOCH
+                .checked_add(Duration::from_millis(*milliseconds))
+                .map(Either::Right),
+            SetExpiryOptions::KeepTTL => return Ok(None),
+        };
+        deadline
+            .map(Some)
+            .ok_or(CommandError::InvalidExpireTime(command))
+    }
+}
+
 #[async_trait::async_trait]
 impl AsyncCommand for Set {
     async fn run_command(
@@ -82,36 +166,32 @@ impl AsyncCommand for Set {
         ctx: &crate::context::Context,
         buf: &mut bytes::BytesMut,
     ) -> Result<(), crate::redis::RedisError> {
-        let mut expires = None::<Either<Instant, SystemTime>>;
-        let mut keepttl = false;
-        if let Some(expiry) = &self.expiry {
-            match expiry {
-                SetExpiryOptions::Ex(seconds) => {
-                    expires = Some(Either::Left(Instant::now() + Duration::from_secs(*seconds)));
-                }
-                SetExpiryOptions::Px(milliseconds) => {
-                    expires = Some(Either::Left(
-                        Instant::now() + Duration::from_millis(*milliseconds),
-                    ));
-                }
-                SetExpiryOptions::Exat(seconds) => {
-                    expires = Some(Either::Right(UNIX_EPOCH + Duration::from_secs(*seconds)));
-                }
-                SetExpiryOptions::Pxat(milliseconds) => {
-                    expires = Some(Either::Right(
-                        UNIX_EPOCH + Duration::from_millis(*milliseconds),
-                    ));
-                }
-                SetExpiryOptions::KeepTTL => {
-                    keepttl = true;
-                }
+        let expiry = match &self.expiry {
+            Some(expiry) => expiry.deadline("set")?,
+            None => None,
+        };
+        let keep_ttl = matches!(self.expiry, Some(SetExpiryOptions::KeepTTL));
+        let options = SetOptions {
+            expiry,
+            keep_ttl,
+            condition: self.condition,
+            get: self.get,
+        };
+        let (written, previous) = ctx
+            .app_data
+            .db
+            .set_kv(self.key.clone(), self.value.clone(), options)
+            .await?;
+        if self.get {
+            match previous {
+                Some(val) => RespType::BulkString(val).write_to_buf(buf),
+                None => NullBulkString.write_to_buf(buf),
             }
+        } else if written {
+            RespType::simple_string("OK").write_to_buf(buf);
+        } else {
+            NullBulkString.write_to_buf(buf);
         }
-        ctx.app_data
-            .db
-            .set_kv(self.key.clone(), self.value.clone(), expires, keepttl)
-            .await;
-        RespType::simple_string("OK").write_to_buf(buf);
         Ok(())
     }
 }
@@ -129,11 +209,732 @@ impl AsyncCommand for Incr {
         ctx: &crate::context::Context,
         buf: &mut bytes::BytesMut,
     ) -> Result<(), crate::redis::RedisError> {
-        if let Some(val) = ctx.app_data.db.incr_value(self.key.clone()).await {
-            RespType::Integer(val).write_to_buf(buf);
-            Ok(())
-        } else {
-            Err(CommandError::IncrInvalid.into())
+        let val = ctx.app_data.db.incr_value(self.key.clone(), 1).await?;
+        RespType::Integer(val).write_to_buf(buf);
+        Ok(())
+    }
+}
+
+#[derive(RedisCommand)]
+#[redis_command(syntax = "INCRBY key increment", write)]
+pub struct Incrby {
+    key: Bytes,
+    increment: i64,
+}
+
+#[async_trait]
+impl AsyncCommand for Incrby {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        let val = ctx
+            .app_data
+            .db
+            .incr_value(self.key.clone(), self.increment)
+            .await?;
+        RespType::Integer(val).write_to_buf(buf);
+        Ok(())
+    }
+}
+
+#[derive(RedisCommand)]
+#[redis_command(syntax = "DECR key", write)]
+pub struct Decr {
+    key: Bytes,
+}
+
+#[async_trait]
+impl AsyncCommand for Decr {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        let val = ctx.app_data.db.incr_value(self.key.clone(), -1).await?;
+        RespType::Integer(val).write_to_buf(buf);
+        Ok(())
+    }
+}
+
+#[derive(RedisCommand)]
+#[redis_command(syntax = "DECRBY key decrement", write)]
+pub struct Decrby {
+    key: Bytes,
+    decrement: i64,
+}
+
+#[async_trait]
+impl AsyncCommand for Decrby {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        let delta = self
+            .decrement
+            .checked_neg()
+            .ok_or(DatabaseError::IncrOverflow)?;
+        let val = ctx.app_data.db.incr_value(self.key.clone(), delta).await?;
+        RespType::Integer(val).write_to_buf(buf);
+        Ok(())
+    }
+}
+
+#[derive(RedisCommand)]
+#[redis_command(syntax = "INCRBYFLOAT key increment", write)]
+pub struct Incrbyfloat {
+    key: Bytes,
+    increment: f64,
+}
+
+#[async_trait]
+impl AsyncCommand for Incrbyfloat {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        let val = ctx
+            .app_data
+            .db
+            .incr_float_value(self.key.clone(), self.increment)
+            .await?;
+        RespType::BulkString(val).write_to_buf(buf);
+        Ok(())
+    }
+}
+
+#[derive(RedisCommand)]
+#[redis_command(syntax = "MGET key [key ...]")]
+pub struct Mget {
+    keys: Vec<Bytes>,
+}
+
+#[async_trait]
+impl AsyncCommand for Mget {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        let values = ctx.app_data.db.get_strings(&self.keys).await;
+        RespType::Array(
+            values
+                .into_iter()
+                .map(|value| match value {
+                    Some(value) => RespType::BulkString(value),
+                    None => RespType::NullBulkString,
+                })
+                .collect(),
+        )
+        .write_to_buf(buf);
+        Ok(())
+    }
+}
+
+#[derive(RedisCommand)]
+#[redis_command(syntax = "MSET key value [key value ...]", write)]
+pub struct Mset {
+    pairs: Vec<Pair<Bytes, Bytes>>,
+}
+
+#[async_trait]
+impl AsyncCommand for Mset {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        let pairs = self
+            .pairs
+            .iter()
+            .map(|pair| (pair.left.clone(), pair.right.clone()))
+            .collect::<Vec<_>>();
+        ctx.app_data.db.set_strings(&pairs, false).await;
+        RespType::simple_string("OK").write_to_buf(buf);
+        Ok(())
+    }
+}
+
+#[derive(RedisCommand)]
+#[redis_command(syntax = "MSETNX key value [key value ...]", write)]
+pub struct Msetnx {
+    pairs: Vec<Pair<Bytes, Bytes>>,
+}
+
+#[async_trait]
+impl AsyncCommand for Msetnx {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        let pairs = self
+            .pairs
+            .iter()
+            .map(|pair| (pair.left.clone(), pair.right.clone()))
+            .collect::<Vec<_>>();
+        let written = ctx.app_data.db.set_strings(&pairs, true).await;
+        RespType::Integer(written as i64).write_to_buf(buf);
+        Ok(())
+    }
+}
+
+#[derive(RedisCommand)]
+#[redis_command(syntax = "APPEND key value", write)]
+pub struct Append {
+    key: Bytes,
+    value: Bytes,
+}
+
+#[async_trait]
+impl AsyncCommand for Append {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        let len = ctx.app_data.db.append(&self.key, &self.value).await?;
+        RespType::Integer(len as i64).write_to_buf(buf);
+        Ok(())
+    }
+}
+
+#[derive(RedisCommand)]
+#[redis_command(syntax = "STRLEN key")]
+pub struct Strlen {
+    key: Bytes,
+}
+
+#[async_trait]
+impl AsyncCommand for Strlen {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        let len = ctx.app_data.db.str_len(&self.key).await?;
+        RespType::Integer(len as i64).write_to_buf(buf);
+        Ok(())
+    }
+}
+
+#[derive(RedisCommand)]
+#[redis_command(syntax = "GETRANGE key start end")]
+pub struct Getrange {
+    key: Bytes,
+    start: i64,
+    end: i64,
+}
+
+#[async_trait]
+impl AsyncCommand for Getrange {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        let range = ctx
+            .app_data
+            .db
+            .get_range(&self.key, self.start, self.end)
+            .await?;
+        RespType::BulkString(range).write_to_buf(buf);
+        Ok(())
+    }
+}
+
+/// The largest string Redis accepts, 512MB
+const MAX_STRING_LEN: usize = 512 * 1024 * 1024;
+
+#[derive(RedisCommand)]
+#[redis_command(syntax = "SETRANGE key offset value", write)]
+pub struct Setrange {
+    key: Bytes,
+    offset: i64,
+    value: Bytes,
+}
+
+#[async_trait]
+impl AsyncCommand for Setrange {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        if self.offset < 0 {
+            return Err(CommandError::OffsetOutOfRange.into());
+        }
+        let offset = self.offset as usize;
+        if offset + self.value.len() > MAX_STRING_LEN {
+            return Err(CommandError::StringTooLong.into());
         }
+        let len = ctx
+            .app_data
+            .db
+            .set_range(&self.key, offset, &self.value)
+            .await?;
+        RespType::Integer(len as i64).write_to_buf(buf);
+        Ok(())
+    }
+}
+
+#[derive(RedisCommand)]
+#[redis_command(syntax = "GETDEL key", write)]
+pub struct Getdel {
+    key: Bytes,
+}
+
+#[async_trait]
+impl AsyncCommand for Getdel {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        match ctx.app_data.db.get_del(&self.key).await? {
+            Some(val) => RespType::BulkString(val).write_to_buf(buf),
+            None => NullBulkString.write_to_buf(buf),
+        }
+        Ok(())
+    }
+}
+
+#[derive(RedisCommand, Debug)]
+#[redis_command(
+    syntax = "GETEX key [EX seconds | PX milliseconds | EXAT unix-time-seconds |\
+    PXAT unix-time-milliseconds | PERSIST]",
+    no_parse,
+    write
+)]
+pub struct Getex {
+    key: Bytes,
+    expiry: Option<GetExOptions>,
+}
+
+#[derive(Debug)]
+pub enum GetExOptions {
+    Expiry(SetExpiryOptions),
+    Persist,
+}
+
+impl ParseStream for Getex {
+    fn parse_stream(stream: &mut RedisStream) -> Result<Self, StreamParseError> {
+        let key = stream.parse()?;
+        let expiry = match stream.peek() {
+            Some(next) if next.eq_ignore_ascii_case(b"persist") => {
+                stream.next();
+                Some(GetExOptions::Persist)
+            }
+            Some(_) => match stream.parse()? {
+                SetExpiryOptions::KeepTTL => {
+                    return Err(StreamParseError::Other("syntax error".into()));
+                }
+                expiry => Some(GetExOptions::Expiry(expiry)),
+            },
+            None => None,
+        };
+        if stream.peek().is_some() {
+            return Err(StreamParseError::Other("syntax error".into()));
+        }
+        Ok(Self { key, expiry })
+    }
+}
+
+#[async_trait]
+impl AsyncCommand for Getex {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        let expiry = match &self.expiry {
+            Some(GetExOptions::Expiry(expiry)) => Some(expiry.deadline("getex")?),
+            Some(GetExOptions::Persist) => Some(None),
+            None => None,
+        };
+        match ctx.app_data.db.get_ex(&self.key, expiry).await? {
+            Some(val) => RespType::BulkString(val).write_to_buf(buf),
+            None => NullBulkString.write_to_buf(buf),
+        }
+        Ok(())
+    }
+}
+
+/// Bitmaps are capped at the 512MB a string can hold
+const MAX_BIT_OFFSET: i64 = 8 * MAX_STRING_LEN as i64 - 1;
+
+fn bit_offset(offset: i64) -> Result<usize, CommandError> {
+    if (0..=MAX_BIT_OFFSET).contains(&offset) {
+        Ok(offset as usize)
+    } else {
+        Err(CommandError::BitOffsetInvalid)
+    }
+}
+
+#[derive(RedisCommand)]
+#[redis_command(syntax = "SETBIT key offset value", write)]
+pub struct Setbit {
+    key: Bytes,
+    offset: i64,
+    value: i64,
+}
+
+#[async_trait]
+impl AsyncCommand for Setbit {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        let offset = bit_offset(self.offset)?;
+        let bit = match self.value {
+            0 => false,
+            1 => true,
+            _ => return Err(CommandError::BitValueInvalid.into()),
+        };
+        let old = ctx.app_data.db.set_bit(&self.key, offset, bit).await?;
+        RespType::Integer(old as i64).write_to_buf(buf);
+        Ok(())
+    }
+}
+
+#[derive(RedisCommand)]
+#[redis_command(syntax = "GETBIT key offset")]
+pub struct Getbit {
+    key: Bytes,
+    offset: i64,
+}
+
+#[async_trait]
+impl AsyncCommand for Getbit {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        let offset = bit_offset(self.offset)?;
+        let bit = ctx.app_data.db.get_bit(&self.key, offset).await?;
+        RespType::Integer(bit as i64).write_to_buf(buf);
+        Ok(())
+    }
+}
+
+impl ParseStream for BitUnit {
+    fn parse_stream(stream: &mut RedisStream) -> Result<Self, StreamParseError> {
+        let next = stream.next().ok_or(StreamParseError::EmptyArg)?;
+        match next.to_ascii_lowercase().as_slice() {
+            b"byte" => Ok(BitUnit::Byte),
+            b"bit" => Ok(BitUnit::Bit),
+            _ => Err(StreamParseError::Other("syntax error".into())),
+        }
+    }
+}
+
+#[derive(RedisCommand)]
+#[redis_command(syntax = "BITCOUNT key [start end [BYTE | BIT]]", no_parse)]
+pub struct Bitcount {
+    key: Bytes,
+    range: Option<BitRange>,
+}
+
+impl ParseStream for Bitcount {
+    fn parse_stream(stream: &mut RedisStream) -> Result<Self, StreamParseError> {
+        let key = stream.parse()?;
+        let range = match stream.remaining() {
+            0 => None,
+            1 => return Err(StreamParseError::Other("syntax error".into())),
+            _ => Some(BitRange {
+                start: stream.parse()?,
+                end: Some(stream.parse()?),
+                unit: stream.parse::<Option<BitUnit>>()?.unwrap_or_default(),
+            }),
+        };
+        if stream.peek().is_some() {
+            return Err(StreamParseError::Other("syntax error".into()));
+        }
+        Ok(Self { key, range })
+    }
+}
+
+#[async_trait]
+impl AsyncCommand for Bitcount {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        let count = ctx.app_data.db.bit_count(&self.key, self.range).await?;
+        RespType::Integer(count as i64).write_to_buf(buf);
+        Ok(())
+    }
+}
+
+#[derive(RedisCommand)]
+#[redis_command(syntax = "BITPOS key bit [start [end [BYTE | BIT]]]", no_parse)]
+pub struct Bitpos {
+    key: Bytes,
+    bit: bool,
+    range: Option<BitRange>,
+}
+
+impl ParseStream for Bitpos {
+    fn parse_stream(stream: &mut RedisStream) -> Result<Self, StreamParseError> {
+        let key = stream.parse()?;
+        let bit = match stream.parse::<i64>()? {
+            0 => false,
+            1 => true,
+            _ => {
+                return Err(StreamParseError::Other(
+                    "The bit argument must be 1 or 0.".into(),
+                ));
+            }
+        };
+        let range = match stream.parse::<Option<i64>>()? {
+            Some(start) => Some(BitRange {
+                start,
+                end: stream.parse()?,
+                unit: stream.parse::<Option<BitUnit>>()?.unwrap_or_default(),
+            }),
+            None => None,
+        };
+        if stream.peek().is_some() {
+            return Err(StreamParseError::Other("syntax error".into()));
+        }
+        Ok(Self { key, bit, range })
+    }
+}
+
+#[async_trait]
+impl AsyncCommand for Bitpos {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        let pos = ctx
+            .app_data
+            .db
+            .bit_pos(&self.key, self.bit, self.range)
+            .await?;
+        RespType::Integer(pos).write_to_buf(buf);
+        Ok(())
+    }
+}
+
+impl ParseStream for BitOperation {
+    fn parse_stream(stream: &mut RedisStream) -> Result<Self, StreamParseError> {
+        let next = stream.next().ok_or(StreamParseError::EmptyArg)?;
+        match next.to_ascii_lowercase().as_slice() {
+            b"and" => Ok(BitOperation::And),
+            b"or" => Ok(BitOperation::Or),
+            b"xor" => Ok(BitOperation::Xor),
+            b"not" => Ok(BitOperation::Not),
+            _ => Err(StreamParseError::Other("syntax error".into())),
+        }
+    }
+}
+
+#[derive(RedisCommand)]
+#[redis_command(syntax = "BITOP <AND | OR | XOR | NOT> destkey key [key ...]", write)]
+pub struct Bitop {
+    operation: BitOperation,
+    destination: Bytes,
+    keys: Vec<Bytes>,
+}
+
+#[async_trait]
+impl AsyncCommand for Bitop {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        if self.keys.is_empty() {
+            return Err(StreamParseError::EmptyArg.into());
+        }
+        if self.operation == BitOperation::Not && self.keys.len() != 1 {
+            return Err(CommandError::IncorrectArgument(
+                "BITOP NOT must be called with a single source key.".into(),
+            )
+            .into());
+        }
+        let len = ctx
+            .app_data
+            .db
+            .bit_op(self.operation, &self.destination, &self.keys)
+            .await?;
+        RespType::Integer(len as i64).write_to_buf(buf);
+        Ok(())
+    }
+}
+
+impl ParseStream for BitFieldType {
+    fn parse_stream(stream: &mut RedisStream) -> Result<Self, StreamParseError> {
+        let next = stream.next().ok_or(StreamParseError::EmptyArg)?;
+        let invalid = || {
+            StreamParseError::Other(
+                "Invalid bitfield type. Use something like i16 u8. \
+                Note that u64 is not supported but i64 is."
+                    .into(),
+            )
+        };
+        let signed = match next.first().map(u8::to_ascii_lowercase) {
+            Some(b'i') => true,
+            Some(b'u') => false,
+            _ => return Err(invalid()),
+        };
+        let bits = str::from_utf8(&next[1..])
+            .ok()
+            .and_then(|bits| bits.parse::<u32>().ok())
+            .ok_or_else(invalid)?;
+        let max_bits = if signed { 64 } else { 63 };
+        if !(1..=max_bits).contains(&bits) {
+            return Err(invalid());
+        }
+        Ok(BitFieldType { signed, bits })
+    }
+}
+
+impl ParseStream for BitOverflow {
+    fn parse_stream(stream: &mut RedisStream) -> Result<Self, StreamParseError> {
+        let next = stream.next().ok_or(StreamParseError::EmptyArg)?;
+        match next.to_ascii_lowercase().as_slice() {
+            b"wrap" => Ok(BitOverflow::Wrap),
+            b"sat" => Ok(BitOverflow::Sat),
+            b"fail" => Ok(BitOverflow::Fail),
+            _ => Err(StreamParseError::Other(
+                "Invalid OVERFLOW type specified".into(),
+            )),
+        }
+    }
+}
+
+/// A `BITFIELD` offset, either in bits or as `#n` counting in multiples of
+/// the type's width
+fn parse_bitfield_offset(
+    stream: &mut RedisStream,
+    ty: BitFieldType,
+) -> Result<usize, StreamParseError> {
+    let next = stream.next().ok_or(StreamParseError::EmptyArg)?;
+    let (multiplier, digits) = match next.strip_prefix(b"#") {
+        Some(digits) => (ty.bits as i64, digits),
+        None => (1, &next[..]),
+    };
+    str::from_utf8(digits)
+        .ok()
+        .and_then(|digits| digits.parse::<i64>().ok())
+        .and_then(|offset| offset.checked_mul(multiplier))
+        .filter(|offset| *offset >= 0 && offset + ty.bits as i64 - 1 <= MAX_BIT_OFFSET)
+        .map(|offset| offset as usize)
+        .ok_or_else(|| StreamParseError::Other(CommandError::BitOffsetInvalid.to_string()))
+}
+
+#[derive(RedisCommand)]
+#[redis_command(
+    syntax = "BITFIELD key [GET encoding offset | [OVERFLOW <WRAP | SAT | FAIL>]\
+    <SET encoding offset value | INCRBY encoding offset increment>\
+    [GET encoding offset | [OVERFLOW <WRAP | SAT | FAIL>]\
+    <SET encoding offset value | INCRBY encoding offset increment> ...]]",
+    no_parse,
+    write
+)]
+pub struct Bitfield {
+    key: Bytes,
+    ops: Vec<BitFieldOp>,
+}
+
+impl ParseStream for Bitfield {
+    fn parse_stream(stream: &mut RedisStream) -> Result<Self, StreamParseError> {
+        let key = stream.parse()?;
+        let mut ops = vec![];
+        while let Some(next) = stream.next() {
+            let op = match next.to_ascii_lowercase().as_slice() {
+                b"get" => {
+                    let ty = stream.parse()?;
+                    BitFieldOp::Get(ty, parse_bitfield_offset(stream, ty)?)
+                }
+                b"set" => {
+                    let ty = stream.parse()?;
+                    let offset = parse_bitfield_offset(stream, ty)?;
+                    BitFieldOp::Set(ty, offset, stream.parse()?)
+                }
+                b"incrby" => {
+                    let ty = stream.parse()?;
+                    let offset = parse_bitfield_offset(stream, ty)?;
+                    BitFieldOp::Incrby(ty, offset, stream.parse()?)
+                }
+                b"overflow" => BitFieldOp::Overflow(stream.parse()?),
+                _ => return Err(StreamParseError::Other("syntax error".into())),
+            };
+            ops.push(op);
+        }
+        Ok(Self { key, ops })
+    }
+}
+
+#[async_trait]
+impl AsyncCommand for Bitfield {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        let replies = ctx.app_data.db.bit_field(&self.key, &self.ops).await?;
+        RespType::Array(
+            replies
+                .into_iter()
+                .map(|reply| match reply {
+                    Some(value) => RespType::Integer(value),
+                    None => RespType::NullBulkString,
+                })
+                .collect(),
+        )
+        .write_to_buf(buf);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+
+    fn parse_set(args: &[&'static str]) -> Result<Set, StreamParseError> {
+        let mut stream = RedisStream {
+            stream: Arc::new(args.iter().copied().map(Bytes::from).collect()),
+            cursor: 0,
+        };
+        Set::parse_stream(&mut stream)
+    }
+
+    #[test]
+    fn test_set_options_any_order() {
+        let set = parse_set(&["key", "value", "PX", "100", "GET", "nx"]).unwrap();
+        assert_eq!(set.condition, Some(SetCondition::Nx));
+        assert!(set.get);
+        assert!(matches!(set.expiry, Some(SetExpiryOptions::Px(100))));
+
+        let set = parse_set(&["key", "value", "KEEPTTL", "XX"]).unwrap();
+        assert_eq!(set.condition, Some(SetCondition::Xx));
+        assert!(matches!(set.expiry, Some(SetExpiryOptions::KeepTTL)));
+
+        assert!(parse_set(&["key", "value", "NX", "XX"]).is_err());
+        assert!(parse_set(&["key", "value", "EX", "1", "KEEPTTL"]).is_err());
+        assert!(parse_set(&["key", "value", "EX"]).is_err());
+    }
+
+    #[test]
+    fn test_expiry_overflow() {
+        let set = parse_set(&["key", "value", "EX", "18446744073709551615"]).unwrap();
+        let expiry = set.expiry.unwrap();
+        assert!(matches!(
+            expiry.deadline("set"),
+            Err(CommandError::InvalidExpireTime("set"))
+        ));
+        assert!(SetExpiryOptions::Exat(u64::MAX).deadline("getex").is_err());
     }
 }
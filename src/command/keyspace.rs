@@ -0,0 +1,530 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use redis_proc_macros::RedisCommand;
+
+use crate::{
+    command::{AsyncCommand, CommandError},
+    database::{ExpireCondition, unix_millis},
+    redis_stream::{ParseStream, RedisStream, StreamParseError},
+    resp::{RedisWrite, RespType},
+};
+
+#[derive(RedisCommand)]
+#[redis_command(syntax = "DEL key [key ...]", write)]
+pub struct Del {
+    keys: Vec<Bytes>,
+}
+
+#[async_trait]
+impl AsyncCommand for Del {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        let num = ctx.app_data.db.delete_keys(&self.keys).await;
+        RespType::Integer(num as i64).write_to_buf(buf);
+        Ok(())
+    }
+}
+
+#[derive(RedisCommand)]
+#[redis_command(syntax = "UNLINK key [key ...]", write)]
+pub struct Unlink {
+    keys: Vec<Bytes>,
+}
+
+#[async_trait]
+impl AsyncCommand for Unlink {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        let num = ctx.app_data.db.delete_keys(&self.keys).await;
+        RespType::Integer(num as i64).write_to_buf(buf);
+        Ok(())
+    }
+}
+
+#[derive(RedisCommand)]
+#[redis_command(syntax = "EXISTS key [key ...]")]
+pub struct Exists {
+    keys: Vec<Bytes>,
+}
+
+#[async_trait]
+impl AsyncCommand for Exists {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        let num = ctx.app_data.db.count_existing(&self.keys).await;
+        RespType::Integer(num as i64).write_to_buf(buf);
+        Ok(())
+    }
+}
+
+#[derive(RedisCommand)]
+#[redis_command(syntax = "TOUCH key [key ...]")]
+pub struct Touch {
+    keys: Vec<Bytes>,
+}
+
+#[async_trait]
+impl AsyncCommand for Touch {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        // Access times aren't tracked, so touching only reports which keys exist
+        let num = ctx.app_data.db.count_existing(&self.keys).await;
+        RespType::Integer(num as i64).write_to_buf(buf);
+        Ok(())
+    }
+}
+
+#[derive(RedisCommand)]
+#[redis_command(syntax = "RENAME key newkey", write)]
+pub struct Rename {
+    key: Bytes,
+    new_key: Bytes,
+}
+
+#[async_trait]
+impl AsyncCommand for Rename {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        ctx.app_data
+            .db
+            .rename_key(&self.key, &self.new_key, false)
+            .await?;
+        RespType::simple_string("OK").write_to_buf(buf);
+        Ok(())
+    }
+}
+
+#[derive(RedisCommand)]
+#[redis_command(syntax = "RENAMENX key newkey", write)]
+pub struct Renamenx {
+    key: Bytes,
+    new_key: Bytes,
+}
+
+#[async_trait]
+impl AsyncCommand for Renamenx {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        let renamed = ctx
+            .app_data
+            .db
+            .rename_key(&self.key, &self.new_key, true)
+            .await?;
+        RespType::Integer(renamed as i64).write_to_buf(buf);
+        Ok(())
+    }
+}
+
+#[derive(RedisCommand, Debug, PartialEq)]
+#[redis_command(
+    syntax = "COPY source destination [DB destination-db] [REPLACE]",
+    no_parse,
+    write
+)]
+pub struct CopyCmd {
+    source: Bytes,
+    destination: Bytes,
+    replace: bool,
+}
+
+impl ParseStream for CopyCmd {
+    fn parse_stream(stream: &mut RedisStream) -> Result<Self, StreamParseError> {
+        let source = stream.parse()?;
+        let destination = stream.parse()?;
+        let mut replace = false;
+        while let Some(next) = stream.next() {
+            match next.to_ascii_lowercase().as_slice() {
+                b"replace" => replace = true,
+                // Only a single database exists
+                b"db" => {
+                    if stream.parse::<u64>()? != 0 {
+                        return Err(StreamParseError::Other("DB index is out of range".into()));
+                    }
+                }
+                _ => {
+                    return Err(StreamParseError::Expected(
+                        "DB or REPLACE".into(),
+                        String::from_utf8_lossy(&next).into(),
+                    ));
+                }
+            }
+        }
+        Ok(Self {
+            source,
+            destination,
+            replace,
+        })
+    }
+}
+
+#[async_trait]
+impl AsyncCommand for CopyCmd {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        let copied = ctx
+            .app_data
+            .db
+            .copy_key(&self.source, &self.destination, self.replace)
+            .await?;
+        RespType::Integer(copied as i64).write_to_buf(buf);
+        Ok(())
+    }
+}
+
+impl ParseStream for ExpireCondition {
+    fn parse_stream(stream: &mut RedisStream) -> Result<Self, StreamParseError> {
+        let mut condition = ExpireCondition::Always;
+        for next in stream.by_ref() {
+            let option = match next.to_ascii_lowercase().as_slice() {
+                b"nx" => ExpireCondition::Nx,
+                b"xx" => ExpireCondition::Xx,
+                b"gt" => ExpireCondition::Gt,
+                b"lt" => ExpireCondition::Lt,
+                _ => {
+                    return Err(StreamParseError::Other(format!(
+                        "Unsupported option {}",
+                        String::from_utf8_lossy(&next)
+                    )));
+                }
+            };
+            condition = match (condition, option) {
+                (ExpireCondition::Always, option) => option,
+                (current, option) if current == option => current,
+                (ExpireCondition::Nx, _) | (_, ExpireCondition::Nx) => {
+                    return Err(StreamParseError::Other(
+                        "NX and XX, GT or LT options at the same time are not compatible".into(),
+                    ));
+                }
+                (ExpireCondition::Gt, ExpireCondition::Lt)
+                | (ExpireCondition::Lt, ExpireCondition::Gt)
+                | (ExpireCondition::XxLt, ExpireCondition::Gt) => {
+                    return Err(StreamParseError::Other(
+                        "GT and LT options at the same time are not compatible".into(),
+                    ));
+                }
+                // XX is implied by GT as a missing TTL never passes it, but
+                // LT lets a missing TTL through so XX has to be kept with it
+                (ExpireCondition::Xx, ExpireCondition::Gt)
+                | (ExpireCondition::Gt, ExpireCondition::Xx) => ExpireCondition::Gt,
+                (ExpireCondition::Xx, ExpireCondition::Lt)
+                | (ExpireCondition::Lt, ExpireCondition::Xx) => ExpireCondition::XxLt,
+                (current, _) => current,
+            };
+        }
+        Ok(condition)
+    }
+}
+
+/// Turns a relative or absolute time into a deadline, in whatever unit
+/// `scale` converts to milliseconds
+pub fn expire_deadline(
+    time: i64,
+    scale: i64,
+    relative: bool,
+    command: &'static str,
+) -> Result<SystemTime, CommandError> {
+    let base = if relative {
+        unix_millis(SystemTime::now())
+    } else {
+        0
+    };
+    let millis = time
+        .checked_mul(scale)
+        .and_then(|millis| millis.checked_add(base))
+        .ok_or(CommandError::InvalidExpireTime(command))?;
+    if millis >= 0 {
+        Ok(UNIX_EPOCH + Duration::from_millis(millis as u64))
+    } else {
+        Ok(UNIX_EPOCH - Duration::from_millis(millis.unsigned_abs()))
+    }
+}
+
+#[derive(RedisCommand)]
+#[redis_command(syntax = "EXPIRE key seconds [NX | XX | GT | LT]", write)]
+pub struct Expire {
+    key: Bytes,
+    seconds: i64,
+    condition: ExpireCondition,
+}
+
+#[async_trait]
+impl AsyncCommand for Expire {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        let deadline = expire_deadline(self.seconds, 1000, true, "expire")?;
+        let set = ctx
+            .app_data
+            .db
+            .set_expiry(&self.key, deadline, self.condition)
+            .await;
+        RespType::Integer(set as i64).write_to_buf(buf);
+        Ok(())
+    }
+}
+
+#[derive(RedisCommand)]
+#[redis_command(syntax = "PEXPIRE key milliseconds [NX | XX | GT | LT]", write)]
+pub struct Pexpire {
+    key: Bytes,
+    milliseconds: i64,
+    condition: ExpireCondition,
+}
+
+#[async_trait]
+impl AsyncCommand for Pexpire {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        let deadline = expire_deadline(self.milliseconds, 1, true, "pexpire")?;
+        let set = ctx
+            .app_data
+            .db
+            .set_expiry(&self.key, deadline, self.condition)
+            .await;
+        RespType::Integer(set as i64).write_to_buf(buf);
+        Ok(())
+    }
+}
+
+#[derive(RedisCommand)]
+#[redis_command(syntax = "EXPIREAT key unix-time-seconds [NX | XX | GT | LT]", write)]
+pub struct Expireat {
+    key: Bytes,
+    seconds: i64,
+    condition: ExpireCondition,
+}
+
+#[async_trait]
+impl AsyncCommand for Expireat {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        let deadline = expire_deadline(self.seconds, 1000, false, "expireat")?;
+        let set = ctx
+            .app_data
+            .db
+            .set_expiry(&self.key, deadline, self.condition)
+            .await;
+        RespType::Integer(set as i64).write_to_buf(buf);
+        Ok(())
+    }
+}
+
+#[derive(RedisCommand)]
+#[redis_command(
+    syntax = "PEXPIREAT key unix-time-milliseconds [NX | XX | GT | LT]",
+    write
+)]
+pub struct Pexpireat {
+    key: Bytes,
+    milliseconds: i64,
+    condition: ExpireCondition,
+}
+
+#[async_trait]
+impl AsyncCommand for Pexpireat {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        let deadline = expire_deadline(self.milliseconds, 1, false, "pexpireat")?;
+        let set = ctx
+            .app_data
+            .db
+            .set_expiry(&self.key, deadline, self.condition)
+            .await;
+        RespType::Integer(set as i64).write_to_buf(buf);
+        Ok(())
+    }
+}
+
+#[derive(RedisCommand)]
+#[redis_command(syntax = "TTL key")]
+pub struct Ttl {
+    key: Bytes,
+}
+
+#[async_trait]
+impl AsyncCommand for Ttl {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        let ttl = ctx.app_data.db.key_ttl(&self.key).await.reply(|time| {
+            let remaining = (unix_millis(time) - unix_millis(SystemTime::now())).max(0);
+            (remaining + 500) / 1000
+        });
+        RespType::Integer(ttl).write_to_buf(buf);
+        Ok(())
+    }
+}
+
+#[derive(RedisCommand)]
+#[redis_command(syntax = "PTTL key")]
+pub struct Pttl {
+    key: Bytes,
+}
+
+#[async_trait]
+impl AsyncCommand for Pttl {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        let ttl = ctx
+            .app_data
+            .db
+            .key_ttl(&self.key)
+            .await
+            .reply(|time| (unix_millis(time) - unix_millis(SystemTime::now())).max(0));
+        RespType::Integer(ttl).write_to_buf(buf);
+        Ok(())
+    }
+}
+
+#[derive(RedisCommand)]
+#[redis_command(syntax = "EXPIRETIME key")]
+pub struct Expiretime {
+    key: Bytes,
+}
+
+#[async_trait]
+impl AsyncCommand for Expiretime {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        let time = ctx
+            .app_data
+            .db
+            .key_ttl(&self.key)
+            .await
+            .reply(|time| unix_millis(time) / 1000);
+        RespType::Integer(time).write_to_buf(buf);
+        Ok(())
+    }
+}
+
+#[derive(RedisCommand)]
+#[redis_command(syntax = "PEXPIRETIME key")]
+pub struct Pexpiretime {
+    key: Bytes,
+}
+
+#[async_trait]
+impl AsyncCommand for Pexpiretime {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        let time = ctx.app_data.db.key_ttl(&self.key).await.reply(unix_millis);
+        RespType::Integer(time).write_to_buf(buf);
+        Ok(())
+    }
+}
+
+#[derive(RedisCommand)]
+#[redis_command(syntax = "PERSIST key", write)]
+pub struct Persist {
+    key: Bytes,
+}
+
+#[async_trait]
+impl AsyncCommand for Persist {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        let removed = ctx.app_data.db.persist(&self.key).await;
+        RespType::Integer(removed as i64).write_to_buf(buf);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+
+    #[test]
+    fn test_copy_parse() {
+        let mut stream = RedisStream {
+            stream: Arc::new(
+                ["src", "dst", "DB", "0", "REPLACE"]
+                    .into_iter()
+                    .map(Bytes::from)
+                    .collect(),
+            ),
+            cursor: 0,
+        };
+        let copy = CopyCmd::parse_stream(&mut stream).unwrap();
+        let expected = CopyCmd {
+            source: Bytes::from("src"),
+            destination: Bytes::from("dst"),
+            replace: true,
+        };
+        assert_eq!(expected, copy);
+    }
+
+    #[test]
+    fn test_expire_condition_parse() {
+        let parse = |args: &[&'static str]| {
+            let mut stream = RedisStream {
+                stream: Arc::new(args.iter().copied().map(Bytes::from).collect()),
+                cursor: 0,
+            };
+            ExpireCondition::parse_stream(&mut stream)
+        };
+        assert_eq!(parse(&[]).unwrap(), ExpireCondition::Always);
+        assert_eq!(parse(&["xx", "GT"]).unwrap(), ExpireCondition::Gt);
+        // XX still fails on a key without a TTL when combined with LT
+        let expiry = SystemTime::now() + Duration::from_secs(10);
+        for args in [["XX", "LT"], ["lt", "xx"]] {
+            let condition = parse(&args).unwrap();
+            assert_eq!(condition, ExpireCondition::XxLt);
+            assert!(!condition.allows(None, expiry));
+            assert!(condition.allows(Some(expiry + Duration::from_secs(1)), expiry));
+        }
+        assert!(parse(&["XX", "LT", "GT"]).is_err());
+        assert!(parse(&["NX", "XX"]).is_err());
+        assert!(parse(&["GT", "LT"]).is_err());
+        assert!(parse(&["NOPE"]).is_err());
+    }
+}
@@ -2,13 +2,15 @@ use std::time::Duration;
 
 use async_trait::async_trait;
 use bytes::Bytes;
+use either::Either;
 use redis_proc_macros::RedisCommand;
 use tokio::time::Instant;
 
 use crate::{
     command::AsyncCommand,
+    database::{BlpopResponse, InsertPosition, ListEnd, ListReceiver, PositionOptions},
     redis::RedisError,
-    redis_stream::ParseStream,
+    redis_stream::{ParseStream, RedisStream, StreamParseError},
     resp::{NullArray, NullBulkString, RedisWrite, RespType},
 };
 
@@ -30,7 +32,7 @@ impl AsyncCommand for Rpush {
             .app_data
             .db
             .push_list(&self.key, self.values.clone())
-            .await;
+            .await?;
         RespType::Integer(len).write_to_buf(buf);
         Ok(())
     }
@@ -55,7 +57,7 @@ impl AsyncCommand for Lrange {
             .app_data
             .db
             .range_list(&self.key, self.start, self.stop)
-            .await;
+            .await?;
         list.write_to_buf(buf);
         Ok(())
     }
@@ -79,7 +81,7 @@ impl AsyncCommand for Lpush {
             .app_data
             .db
             .prepend_list(&self.key, self.values.clone())
-            .await;
+            .await?;
         RespType::Integer(len).write_to_buf(buf);
         Ok(())
     }
@@ -98,14 +100,14 @@ impl AsyncCommand for LLen {
         ctx: &crate::context::Context,
         buf: &mut bytes::BytesMut,
     ) -> Result<(), crate::redis::RedisError> {
-        let len = ctx.app_data.db.list_len(&self.key).await;
+        let len = ctx.app_data.db.list_len(&self.key).await?;
         RespType::Integer(len).write_to_buf(buf);
         Ok(())
     }
 }
 
 #[derive(RedisCommand)]
-#[redis_command(syntax = "LPOP key", write)]
+#[redis_command(syntax = "LPOP key [count]", write)]
 pub struct Lpop {
     key: Bytes,
     count: Option<u64>,
@@ -118,22 +120,52 @@ impl AsyncCommand for Lpop {
         ctx: &crate::context::Context,
         buf: &mut bytes::BytesMut,
     ) -> Result<(), crate::redis::RedisError> {
-        let list = ctx.app_data.db.pop_list(&self.key, self.count).await;
-        if !list.is_empty() {
-            if self.count.is_some() {
-                list.write_to_buf(buf);
-            } else {
-                list[0].write_to_buf(buf);
-            }
-        } else {
-            NullBulkString.write_to_buf(buf);
-        }
-        Ok(())
+        pop_list(ctx, buf, &self.key, ListEnd::Left, self.count).await
+    }
+}
+
+#[derive(RedisCommand)]
+#[redis_command(syntax = "RPOP key [count]", write)]
+pub struct Rpop {
+    key: Bytes,
+    count: Option<u64>,
+}
+
+#[async_trait]
+impl AsyncCommand for Rpop {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        pop_list(ctx, buf, &self.key, ListEnd::Right, self.count).await
+    }
+}
+
+/// Replies with a single element, or an array of them when a count was given
+async fn pop_list(
+    ctx: &crate::context::Context,
+    buf: &mut bytes::BytesMut,
+    key: &Bytes,
+    end: ListEnd,
+    count: Option<u64>,
+) -> Result<(), crate::redis::RedisError> {
+    let list = ctx.app_data.db.pop_list(key, end, count).await?;
+    // Only a missing key gets a null, a count of 0 pops nothing from one that
+    // exists
+    match (list, count) {
+        (None, Some(_)) => NullArray.write_to_buf(buf),
+        (Some(list), Some(_)) => list.write_to_buf(buf),
+        (list, None) => match list.as_ref().and_then(|list| list.first()) {
+            Some(value) => value.write_to_buf(buf),
+            None => NullBulkString.write_to_buf(buf),
+        },
     }
+    Ok(())
 }
 
 #[derive(RedisCommand, Debug, PartialEq)]
-#[redis_command(syntax = "BLPOP key [key ...] timeout", no_parse, write)]
+#[redis_command(syntax = "BLPOP key [key ...] timeout", no_parse)]
 pub struct Blpop {
     keys: Vec<Bytes>,
     timeout: f64,
@@ -143,11 +175,7 @@ impl ParseStream for Blpop {
     fn parse_stream(
         stream: &mut crate::redis_stream::RedisStream,
     ) -> Result<Self, crate::redis_stream::StreamParseError> {
-        let mut keys: Vec<Bytes> = vec![];
-        while stream.remaining() > 1 {
-            keys.push(stream.parse()?);
-        }
-        let timeout = stream.parse()?;
+        let (keys, timeout) = parse_keys_timeout(stream)?;
         Ok(Self { keys, timeout })
     }
 }
@@ -159,39 +187,703 @@ impl AsyncCommand for Blpop {
         ctx: &crate::context::Context,
         buf: &mut bytes::BytesMut,
     ) -> Result<(), crate::redis::RedisError> {
-        let timeout = if self.timeout == 0.0 {
-            None
-        } else {
-            Some(Instant::now() + Duration::from_secs_f64(self.timeout))
+        let pop = MultiPop {
+            keys: self.keys.clone(),
+            end: ListEnd::Left,
+            count: None,
+        };
+        blocking_pop(ctx, buf, &pop, self.timeout).await
+    }
+}
+
+#[derive(RedisCommand, Debug, PartialEq)]
+#[redis_command(syntax = "BRPOP key [key ...] timeout", no_parse)]
+pub struct Brpop {
+    keys: Vec<Bytes>,
+    timeout: f64,
+}
+
+impl ParseStream for Brpop {
+    fn parse_stream(stream: &mut RedisStream) -> Result<Self, StreamParseError> {
+        let (keys, timeout) = parse_keys_timeout(stream)?;
+        Ok(Self { keys, timeout })
+    }
+}
+
+#[async_trait]
+impl AsyncCommand for Brpop {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        let pop = MultiPop {
+            keys: self.keys.clone(),
+            end: ListEnd::Right,
+            count: None,
+        };
+        blocking_pop(ctx, buf, &pop, self.timeout).await
+    }
+}
+
+/// Reads the keys of `BLPOP` and `BRPOP` followed by the timeout
+pub fn parse_keys_timeout(stream: &mut RedisStream) -> Result<(Vec<Bytes>, f64), StreamParseError> {
+    let mut keys: Vec<Bytes> = vec![];
+    while stream.remaining() > 1 {
+        keys.push(stream.parse()?);
+    }
+    if keys.is_empty() {
+        return Err(StreamParseError::EmptyArg);
+    }
+    let timeout = stream.parse()?;
+    Ok((keys, timeout))
+}
+
+/// The `numkeys key [key ...] <LEFT | RIGHT> [COUNT count]` arguments of
+/// `LMPOP` and `BLMPOP`. The count is only set for those two, which reply
+/// with every popped element rather than a single one.
+#[derive(Debug, PartialEq)]
+pub struct MultiPop {
+    keys: Vec<Bytes>,
+    end: ListEnd,
+    count: Option<usize>,
+}
+
+impl ParseStream for MultiPop {
+    fn parse_stream(stream: &mut RedisStream) -> Result<Self, StreamParseError> {
+        let num_keys = match stream.parse::<i64>() {
+            Ok(num_keys) if num_keys > 0 => num_keys as usize,
+            _ => {
+                return Err(StreamParseError::Other(
+                    "numkeys should be greater than 0".into(),
+                ));
+            }
         };
-        match ctx
+        if num_keys > stream.remaining() {
+            return Err(StreamParseError::Other(
+                "Number of keys can't be greater than number of args".into(),
+            ));
+        }
+        let keys = (0..num_keys)
+            .map(|_| stream.parse())
+            .collect::<Result<_, _>>()?;
+        let end = stream.parse()?;
+        let count = match stream.next() {
+            Some(next) if next.eq_ignore_ascii_case(b"count") => match stream.parse::<i64>() {
+                Ok(count) if count > 0 => count as usize,
+                _ => {
+                    return Err(StreamParseError::Other(
+                        "count should be greater than 0".into(),
+                    ));
+                }
+            },
+            Some(_) => return Err(StreamParseError::Other("syntax error".into())),
+            None => 1,
+        };
+        if stream.peek().is_some() {
+            return Err(StreamParseError::Other("syntax error".into()));
+        }
+        Ok(Self {
+            keys,
+            end,
+            count: Some(count),
+        })
+    }
+}
+
+/// Replies with the key and what was popped from it, `[key, [element ...]]`
+/// when a count was given and `[key, element]` otherwise
+fn write_popped(response: BlpopResponse, count: Option<usize>, buf: &mut bytes::BytesMut) {
+    let key = RespType::BulkString(response.key);
+    let mut values = response.values.into_iter().map(RespType::BulkString);
+    let popped = match count {
+        Some(_) => RespType::Array(values.collect()),
+        None => values
+            .next()
+            .map_or(RespType::NullBulkString, |value| value),
+    };
+    RespType::Array(vec![key, popped]).write_to_buf(buf);
+}
+
+#[derive(RedisCommand)]
+#[redis_command(
+    syntax = "LMPOP numkeys key [key ...] <LEFT | RIGHT> [COUNT count]",
+    no_parse,
+    write
+)]
+pub struct Lmpop {
+    pop: MultiPop,
+}
+
+impl ParseStream for Lmpop {
+    fn parse_stream(stream: &mut RedisStream) -> Result<Self, StreamParseError> {
+        Ok(Self {
+            pop: stream.parse()?,
+        })
+    }
+}
+
+#[async_trait]
+impl AsyncCommand for Lmpop {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        let MultiPop { keys, end, count } = &self.pop;
+        let popped = ctx
+            .app_data
+            .db
+            .pop_lists(keys, *end, count.unwrap_or(1))
+            .await?;
+        match popped {
+            Some(response) => write_popped(response, *count, buf),
+            None => NullArray.write_to_buf(buf),
+        }
+        Ok(())
+    }
+}
+
+#[derive(RedisCommand)]
+#[redis_command(
+    syntax = "BLMPOP timeout numkeys key [key ...] <LEFT | RIGHT> [COUNT count]",
+    no_parse
+)]
+pub struct Blmpop {
+    timeout: f64,
+    pop: MultiPop,
+}
+
+impl ParseStream for Blmpop {
+    fn parse_stream(stream: &mut RedisStream) -> Result<Self, StreamParseError> {
+        Ok(Self {
+            timeout: stream.parse()?,
+            pop: stream.parse()?,
+        })
+    }
+}
+
+#[async_trait]
+impl AsyncCommand for Blmpop {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        blocking_pop(ctx, buf, &self.pop, self.timeout).await
+    }
+}
+
+/// A replica must never block, so a served pop reaches them as the
+/// `LPOP` or `RPOP` it turned into, for the key it was served from
+async fn blocking_pop(
+    ctx: &crate::context::Context,
+    buf: &mut bytes::BytesMut,
+    MultiPop { keys, end, count }: &MultiPop,
+    timeout: f64,
+) -> Result<(), crate::redis::RedisError> {
+    let timeout = block_deadline(timeout)?;
+    let popped = ctx
+        .app_data
+        .db
+        .blocking_pop_list(ctx.client_id, keys, timeout, *end, count.unwrap_or(1), None)
+        .await?;
+    let Some(response) = wait_blocked(ctx, keys, popped, timeout).await? else {
+        NullArray.write_to_buf(buf);
+        return Ok(());
+    };
+    let mut command = vec![
+        match end {
+            ListEnd::Left => RespType::bulk_string("LPOP"),
+            ListEnd::Right => RespType::bulk_string("RPOP"),
+        },
+        RespType::BulkString(response.key.clone()),
+    ];
+    if count.is_some() {
+        command.push(RespType::bulk_string(response.values.len()));
+    }
+    ctx.app_data.propagate(RespType::Array(command)).await;
+    write_popped(response, *count, buf);
+    Ok(())
+}
+
+/// When a blocking command gives up, a timeout of 0 waits forever
+pub fn block_deadline(timeout: f64) -> Result<Option<Instant>, RedisError> {
+    let out_of_range = || RedisError::other("timeout is not a float or out of range");
+    if !timeout.is_finite() {
+        Err(out_of_range())
+    } else if timeout < 0.0 {
+        Err(RedisError::other("timeout is negative"))
+    } else if timeout == 0.0 {
+        Ok(None)
+    } else {
+        Duration::try_from_secs_f64(timeout)
+            .ok()
+            .and_then(|timeout| Instant::now().checked_add(timeout))
+            .map(Some)
+            .ok_or_else(out_of_range)
+    }
+}
+
+/// Waits for a blocked client to be served, `None` once it gives up
+async fn wait_blocked(
+    ctx: &crate::context::Context,
+    keys: &[Bytes],
+    popped: Either<BlpopResponse, ListReceiver>,
+    timeout: Option<Instant>,
+) -> Result<Option<BlpopResponse>, RedisError> {
+    let receiver = match popped {
+        Either::Left(response) => return Ok(Some(response)),
+        Either::Right(receiver) => receiver,
+    };
+    let response = ctx
+        .app_data
+        .db
+        .wait_blocked(ctx.client_id, ctx.closed.clone(), keys, receiver, timeout)
+        .await?;
+    Ok(response.transpose()?)
+}
+
+impl ParseStream for ListEnd {
+    fn parse_stream(stream: &mut RedisStream) -> Result<Self, StreamParseError> {
+        let next = stream.next().ok_or(StreamParseError::EmptyArg)?;
+        match next.to_ascii_lowercase().as_slice() {
+            b"left" => Ok(ListEnd::Left),
+            b"right" => Ok(ListEnd::Right),
+            _ => Err(StreamParseError::Other("syntax error".into())),
+        }
+    }
+}
+
+#[derive(RedisCommand)]
+#[redis_command(
+    syntax = "LMOVE source destination <LEFT | RIGHT> <LEFT | RIGHT>",
+    write
+)]
+pub struct Lmove {
+    source: Bytes,
+    destination: Bytes,
+    from: ListEnd,
+    to: ListEnd,
+}
+
+#[async_trait]
+impl AsyncCommand for Lmove {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        list_move(
+            ctx,
+            buf,
+            &self.source,
+            &self.destination,
+            self.from,
+            self.to,
+        )
+        .await
+    }
+}
+
+#[derive(RedisCommand)]
+#[redis_command(syntax = "RPOPLPUSH source destination", write)]
+pub struct Rpoplpush {
+    source: Bytes,
+    destination: Bytes,
+}
+
+#[async_trait]
+impl AsyncCommand for Rpoplpush {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        let (from, to) = (ListEnd::Right, ListEnd::Left);
+        list_move(ctx, buf, &self.source, &self.destination, from, to).await
+    }
+}
+
+async fn list_move(
+    ctx: &crate::context::Context,
+    buf: &mut bytes::BytesMut,
+    source: &Bytes,
+    destination: &Bytes,
+    from: ListEnd,
+    to: ListEnd,
+) -> Result<(), crate::redis::RedisError> {
+    match ctx
+        .app_data
+        .db
+        .list_move(source, destination, from, to)
+        .await?
+    {
+        Some(value) => RespType::BulkString(value).write_to_buf(buf),
+        None => NullBulkString.write_to_buf(buf),
+    }
+    Ok(())
+}
+
+#[derive(RedisCommand)]
+#[redis_command(syntax = "BLMOVE source destination <LEFT | RIGHT> <LEFT | RIGHT> timeout")]
+pub struct Blmove {
+    source: Bytes,
+    destination: Bytes,
+    from: ListEnd,
+    to: ListEnd,
+    timeout: f64,
+}
+
+#[async_trait]
+impl AsyncCommand for Blmove {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        let moves = (&self.source, &self.destination, self.from, self.to);
+        blocking_list_move(ctx, buf, moves, self.timeout).await
+    }
+}
+
+#[derive(RedisCommand)]
+#[redis_command(syntax = "BRPOPLPUSH source destination timeout")]
+pub struct Brpoplpush {
+    source: Bytes,
+    destination: Bytes,
+    timeout: f64,
+}
+
+#[async_trait]
+impl AsyncCommand for Brpoplpush {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        let moves = (
+            &self.source,
+            &self.destination,
+            ListEnd::Right,
+            ListEnd::Left,
+        );
+        blocking_list_move(ctx, buf, moves, self.timeout).await
+    }
+}
+
+/// A replica must never block, so the move reaches them as the `LMOVE` it
+/// turned into once an element was there
+async fn blocking_list_move(
+    ctx: &crate::context::Context,
+    buf: &mut bytes::BytesMut,
+    (source, destination, from, to): (&Bytes, &Bytes, ListEnd, ListEnd),
+    timeout: f64,
+) -> Result<(), crate::redis::RedisError> {
+    let timeout = block_deadline(timeout)?;
+    let popped = ctx
+        .app_data
+        .db
+        .blocking_pop_list(
+            ctx.client_id,
+            std::slice::from_ref(source),
+            timeout,
+            from,
+            1,
+            Some((destination.clone(), to)),
+        )
+        .await?;
+    let Some(response) = wait_blocked(ctx, std::slice::from_ref(source), popped, timeout).await?
+    else {
+        NullArray.write_to_buf(buf);
+        return Ok(());
+    };
+    let end = |end| match end {
+        ListEnd::Left => RespType::bulk_string("LEFT"),
+        ListEnd::Right => RespType::bulk_string("RIGHT"),
+    };
+    ctx.app_data
+        .propagate(RespType::Array(vec![
+            RespType::bulk_string("LMOVE"),
+            RespType::BulkString(source.clone()),
+            RespType::BulkString(destination.clone()),
+            end(from),
+            end(to),
+        ]))
+        .await;
+    match response.values.into_iter().next() {
+        Some(value) => RespType::BulkString(value).write_to_buf(buf),
+        None => NullBulkString.write_to_buf(buf),
+    }
+    Ok(())
+}
+
+#[derive(RedisCommand)]
+#[redis_command(syntax = "LINDEX key index")]
+pub struct Lindex {
+    key: Bytes,
+    index: i64,
+}
+
+#[async_trait]
+impl AsyncCommand for Lindex {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        match ctx.app_data.db.list_index(&self.key, self.index).await? {
+            Some(value) => RespType::BulkString(value).write_to_buf(buf),
+            None => NullBulkString.write_to_buf(buf),
+        }
+        Ok(())
+    }
+}
+
+#[derive(RedisCommand)]
+#[redis_command(syntax = "LSET key index element", write)]
+pub struct Lset {
+    key: Bytes,
+    index: i64,
+    element: Bytes,
+}
+
+#[async_trait]
+impl AsyncCommand for Lset {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        ctx.app_data
+            .db
+            .list_set(&self.key, self.index, self.element.clone())
+            .await?;
+        RespType::simple_string("OK").write_to_buf(buf);
+        Ok(())
+    }
+}
+
+impl ParseStream for InsertPosition {
+    fn parse_stream(stream: &mut RedisStream) -> Result<Self, StreamParseError> {
+        let next = stream.next().ok_or(StreamParseError::EmptyArg)?;
+        match next.to_ascii_lowercase().as_slice() {
+            b"before" => Ok(InsertPosition::Before),
+            b"after" => Ok(InsertPosition::After),
+            _ => Err(StreamParseError::Other("syntax error".into())),
+        }
+    }
+}
+
+#[derive(RedisCommand)]
+#[redis_command(syntax = "LINSERT key <BEFORE | AFTER> pivot element", write)]
+pub struct Linsert {
+    key: Bytes,
+    position: InsertPosition,
+    pivot: Bytes,
+    element: Bytes,
+}
+
+#[async_trait]
+impl AsyncCommand for Linsert {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        let len = ctx
+            .app_data
+            .db
+            .list_insert(&self.key, self.position, &self.pivot, self.element.clone())
+            .await?;
+        RespType::Integer(len).write_to_buf(buf);
+        Ok(())
+    }
+}
+
+#[derive(RedisCommand)]
+#[redis_command(syntax = "LREM key count element", write)]
+pub struct Lrem {
+    key: Bytes,
+    count: i64,
+    element: Bytes,
+}
+
+#[async_trait]
+impl AsyncCommand for Lrem {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        let removed = ctx
             .app_data
             .db
-            .blocking_pop_list(&self.keys[0], timeout)
-            .await
-        {
-            either::Either::Left(blpop) => blpop.write_to_buf(buf),
-            either::Either::Right(receiver) => {
-                let response = if let Some(timeout) = timeout {
-                    match tokio::time::timeout_at(timeout, receiver).await {
-                        Ok(response) => response,
-                        Err(_) => {
-                            println!("Timed out");
-                            NullArray.write_to_buf(buf);
-                            return Ok(());
-                        }
-                    }
-                } else {
-                    receiver.await
-                };
-                match response {
-                    Ok(value) => value.write_to_buf(buf),
-                    Err(err) => {
-                        return Err(RedisError::other(format!("Ran into RECV error: {err:?}")));
-                    }
+            .list_remove(&self.key, self.count, &self.element)
+            .await?;
+        RespType::Integer(removed as i64).write_to_buf(buf);
+        Ok(())
+    }
+}
+
+#[derive(RedisCommand)]
+#[redis_command(syntax = "LTRIM key start stop", write)]
+pub struct Ltrim {
+    key: Bytes,
+    start: i64,
+    stop: i64,
+}
+
+#[async_trait]
+impl AsyncCommand for Ltrim {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        ctx.app_data
+            .db
+            .list_trim(&self.key, self.start, self.stop)
+            .await?;
+        RespType::simple_string("OK").write_to_buf(buf);
+        Ok(())
+    }
+}
+
+#[derive(RedisCommand)]
+#[redis_command(
+    syntax = "LPOS key element [RANK rank] [COUNT num-matches] [MAXLEN len]",
+    no_parse
+)]
+pub struct Lpos {
+    key: Bytes,
+    element: Bytes,
+    options: PositionOptions,
+    /// The reply is an array when `COUNT` is given
+    with_count: bool,
+}
+
+impl ParseStream for Lpos {
+    fn parse_stream(stream: &mut RedisStream) -> Result<Self, StreamParseError> {
+        let key = stream.parse()?;
+        let element = stream.parse()?;
+        let mut options = PositionOptions::default();
+        let mut with_count = false;
+        while let Some(next) = stream.next() {
+            let value = stream.parse::<i64>()?;
+            match next.to_ascii_lowercase().as_slice() {
+                b"rank" if value == 0 => {
+                    return Err(StreamParseError::Other(
+                        "RANK can't be zero: use 1 to start from the first match, 2 from the second ... or use negative to start from the last match".into(),
+                    ));
+                }
+                b"rank" if value == i64::MIN => {
+                    return Err(StreamParseError::Other("value is out of range".into()));
+                }
+                b"rank" => options.rank = value,
+                b"count" if value < 0 => {
+                    return Err(StreamParseError::Other("COUNT can't be negative".into()));
+                }
+                b"count" => {
+                    options.count = value as usize;
+                    with_count = true;
+                }
+                b"maxlen" if value < 0 => {
+                    return Err(StreamParseError::Other("MAXLEN can't be negative".into()));
                 }
+                b"maxlen" => options.max_len = value as usize,
+                _ => return Err(StreamParseError::Other("syntax error".into())),
             }
         }
+        Ok(Self {
+            key,
+            element,
+            options,
+            with_count,
+        })
+    }
+}
+
+#[async_trait]
+impl AsyncCommand for Lpos {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        let positions = ctx
+            .app_data
+            .db
+            .list_positions(&self.key, &self.element, self.options)
+            .await?;
+        if self.with_count {
+            RespType::Array(
+                positions
+                    .into_iter()
+                    .map(|index| RespType::Integer(index as i64))
+                    .collect(),
+            )
+            .write_to_buf(buf);
+        } else {
+            match positions.first() {
+                Some(index) => RespType::Integer(*index as i64).write_to_buf(buf),
+                None => NullBulkString.write_to_buf(buf),
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(RedisCommand)]
+#[redis_command(syntax = "LPUSHX key element [element ...]", write)]
+pub struct Lpushx {
+    key: Bytes,
+    values: Vec<Bytes>,
+}
+
+#[async_trait]
+impl AsyncCommand for Lpushx {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        if self.values.is_empty() {
+            return Err(StreamParseError::EmptyArg.into());
+        }
+        let len = ctx
+            .app_data
+            .db
+            .push_list_existing(&self.key, self.values.clone(), ListEnd::Left)
+            .await?;
+        RespType::Integer(len).write_to_buf(buf);
+        Ok(())
+    }
+}
+
+#[derive(RedisCommand)]
+#[redis_command(syntax = "RPUSHX key element [element ...]", write)]
+pub struct Rpushx {
+    key: Bytes,
+    values: Vec<Bytes>,
+}
+
+#[async_trait]
+impl AsyncCommand for Rpushx {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        if self.values.is_empty() {
+            return Err(StreamParseError::EmptyArg.into());
+        }
+        let len = ctx
+            .app_data
+            .db
+            .push_list_existing(&self.key, self.values.clone(), ListEnd::Right)
+            .await?;
+        RespType::Integer(len).write_to_buf(buf);
         Ok(())
     }
 }
@@ -200,8 +892,6 @@ impl AsyncCommand for Blpop {
 mod tests {
     use std::sync::Arc;
 
-    use crate::redis_stream::RedisStream;
-
     use super::*;
 
     #[test]
@@ -217,4 +907,30 @@ mod tests {
         let expected = Blpop { keys, timeout: 0.0 };
         assert_eq!(expected, blpop)
     }
+
+    #[test]
+    fn test_multi_pop_parse() {
+        let parse = |args: &[&str]| {
+            let mut stream = RedisStream {
+                stream: Arc::new(
+                    args.iter()
+                        .map(|arg| Bytes::from(arg.to_string()))
+                        .collect(),
+                ),
+                cursor: 0,
+            };
+            MultiPop::parse_stream(&mut stream)
+        };
+        let pop = parse(&["2", "a", "b", "right", "COUNT", "3"]).unwrap();
+        let expected = MultiPop {
+            keys: vec![Bytes::from("a"), Bytes::from("b")],
+            end: ListEnd::Right,
+            count: Some(3),
+        };
+        assert_eq!(expected, pop);
+        assert_eq!(parse(&["1", "a", "LEFT"]).unwrap().count, Some(1));
+        assert!(parse(&["3", "a", "LEFT"]).is_err());
+        assert!(parse(&["1", "a", "LEFT", "COUNT", "-1"]).is_err());
+        assert!(parse(&["1", "a", "UP"]).is_err());
+    }
 }
@@ -1,3 +1,3 @@
 use crate::mod_flat;
 
-mod_flat!(command basic list stream symbol key_value transaction replication config channel sorted_set geo_spatial authentication);
+mod_flat!(command basic list stream symbol key_value transaction replication config channel sorted_set geo_spatial authentication keyspace hyperloglog hash set);
@@ -0,0 +1,480 @@
+use async_trait::async_trait;
+use bytes::Bytes;
+use redis_proc_macros::RedisCommand;
+
+use crate::{
+    command::AsyncCommand,
+    database::SetOperation,
+    redis_stream::{ParseStream, RedisStream, StreamParseError},
+    resp::{NullBulkString, RedisWrite, RespType},
+};
+
+/// Reads the optional count of `SPOP` and `SRANDMEMBER`, nothing may follow it
+fn parse_count(stream: &mut RedisStream) -> Result<Option<i64>, StreamParseError> {
+    let count = match stream.peek() {
+        Some(_) => Some(stream.parse::<i64>().map_err(|_| {
+            StreamParseError::Other("value is not an integer or out of range".into())
+        })?),
+        None => None,
+    };
+    if stream.peek().is_some() {
+        return Err(StreamParseError::Other("syntax error".into()));
+    }
+    Ok(count)
+}
+
+#[derive(RedisCommand)]
+#[redis_command(syntax = "SADD key member [member ...]", write)]
+pub struct Sadd {
+    key: Bytes,
+    members: Vec<Bytes>,
+}
+
+#[async_trait]
+impl AsyncCommand for Sadd {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        if self.members.is_empty() {
+            return Err(StreamParseError::EmptyArg.into());
+        }
+        let added = ctx.app_data.db.set_add(&self.key, &self.members).await?;
+        RespType::Integer(added as i64).write_to_buf(buf);
+        Ok(())
+    }
+}
+
+#[derive(RedisCommand)]
+#[redis_command(syntax = "SREM key member [member ...]", write)]
+pub struct Srem {
+    key: Bytes,
+    members: Vec<Bytes>,
+}
+
+#[async_trait]
+impl AsyncCommand for Srem {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        if self.members.is_empty() {
+            return Err(StreamParseError::EmptyArg.into());
+        }
+        let removed = ctx.app_data.db.set_remove(&self.key, &self.members).await?;
+        RespType::Integer(removed as i64).write_to_buf(buf);
+        Ok(())
+    }
+}
+
+#[derive(RedisCommand)]
+#[redis_command(syntax = "SMEMBERS key")]
+pub struct Smembers {
+    key: Bytes,
+}
+
+#[async_trait]
+impl AsyncCommand for Smembers {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        let members = ctx.app_data.db.set_members(&self.key).await?;
+        RespType::Array(members.into_iter().map(RespType::BulkString).collect()).write_to_buf(buf);
+        Ok(())
+    }
+}
+
+#[derive(RedisCommand)]
+#[redis_command(syntax = "SISMEMBER key member")]
+pub struct Sismember {
+    key: Bytes,
+    member: Bytes,
+}
+
+#[async_trait]
+impl AsyncCommand for Sismember {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        let contains = ctx
+            .app_data
+            .db
+            .set_contains(&self.key, std::slice::from_ref(&self.member))
+            .await?;
+        RespType::Integer(contains[0] as i64).write_to_buf(buf);
+        Ok(())
+    }
+}
+
+#[derive(RedisCommand)]
+#[redis_command(syntax = "SMISMEMBER key member [member ...]")]
+pub struct Smismember {
+    key: Bytes,
+    members: Vec<Bytes>,
+}
+
+#[async_trait]
+impl AsyncCommand for Smismember {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        if self.members.is_empty() {
+            return Err(StreamParseError::EmptyArg.into());
+        }
+        let contains = ctx
+            .app_data
+            .db
+            .set_contains(&self.key, &self.members)
+            .await?;
+        RespType::Array(
+            contains
+                .into_iter()
+                .map(|contains| RespType::Integer(contains as i64))
+                .collect(),
+        )
+        .write_to_buf(buf);
+        Ok(())
+    }
+}
+
+#[derive(RedisCommand)]
+#[redis_command(syntax = "SCARD key")]
+pub struct Scard {
+    key: Bytes,
+}
+
+#[async_trait]
+impl AsyncCommand for Scard {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        let len = ctx.app_data.db.set_len(&self.key).await?;
+        RespType::Integer(len as i64).write_to_buf(buf);
+        Ok(())
+    }
+}
+
+/// Which members get popped is random, so rather than being replayed the
+/// command reaches the replicas as an `SREM` of the popped members
+#[derive(RedisCommand)]
+#[redis_command(syntax = "SPOP key [count]", no_parse)]
+pub struct Spop {
+    key: Bytes,
+    count: Option<i64>,
+}
+
+impl ParseStream for Spop {
+    fn parse_stream(stream: &mut RedisStream) -> Result<Self, StreamParseError> {
+        let key = stream.parse()?;
+        let count = parse_count(stream)?;
+        if count.is_some_and(|count| count < 0) {
+            return Err(StreamParseError::Other(
+                "value is out of range, must be positive".into(),
+            ));
+        }
+        Ok(Self { key, count })
+    }
+}
+
+#[async_trait]
+impl AsyncCommand for Spop {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        let count = self.count.unwrap_or(1) as usize;
+        let popped = ctx.app_data.db.set_pop(&self.key, count).await?;
+        if !popped.is_empty() {
+            let mut command = vec![
+                RespType::bulk_string("SREM"),
+                RespType::BulkString(self.key.clone()),
+            ];
+            command.extend(popped.iter().cloned().map(RespType::BulkString));
+            ctx.app_data.propagate(RespType::Array(command)).await;
+        }
+        if self.count.is_some() {
+            RespType::Array(popped.into_iter().map(RespType::BulkString).collect())
+                .write_to_buf(buf);
+        } else {
+            match popped.into_iter().next() {
+                Some(member) => RespType::BulkString(member).write_to_buf(buf),
+                None => NullBulkString.write_to_buf(buf),
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(RedisCommand)]
+#[redis_command(syntax = "SRANDMEMBER key [count]", no_parse)]
+pub struct Srandmember {
+    key: Bytes,
+    count: Option<i64>,
+}
+
+impl ParseStream for Srandmember {
+    fn parse_stream(stream: &mut RedisStream) -> Result<Self, StreamParseError> {
+        let key = stream.parse()?;
+        let count = parse_count(stream)?;
+        Ok(Self { key, count })
+    }
+}
+
+#[async_trait]
+impl AsyncCommand for Srandmember {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        let Some(count) = self.count else {
+            match ctx.app_data.db.set_rand_members(&self.key, 1).await?.pop() {
+                Some(member) => RespType::BulkString(member).write_to_buf(buf),
+                None => NullBulkString.write_to_buf(buf),
+            }
+            return Ok(());
+        };
+        let members = ctx.app_data.db.set_rand_members(&self.key, count).await?;
+        RespType::Array(members.into_iter().map(RespType::BulkString).collect()).write_to_buf(buf);
+        Ok(())
+    }
+}
+
+#[derive(RedisCommand)]
+#[redis_command(syntax = "SMOVE source destination member", write)]
+pub struct Smove {
+    source: Bytes,
+    destination: Bytes,
+    member: Bytes,
+}
+
+#[async_trait]
+impl AsyncCommand for Smove {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        let moved = ctx
+            .app_data
+            .db
+            .set_move(&self.source, &self.destination, &self.member)
+            .await?;
+        RespType::Integer(moved as i64).write_to_buf(buf);
+        Ok(())
+    }
+}
+
+#[derive(RedisCommand)]
+#[redis_command(syntax = "SINTER key [key ...]")]
+pub struct Sinter {
+    keys: Vec<Bytes>,
+}
+
+#[async_trait]
+impl AsyncCommand for Sinter {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        set_combine(ctx, buf, &self.keys, SetOperation::Inter).await
+    }
+}
+
+#[derive(RedisCommand)]
+#[redis_command(syntax = "SUNION key [key ...]")]
+pub struct Sunion {
+    keys: Vec<Bytes>,
+}
+
+#[async_trait]
+impl AsyncCommand for Sunion {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        set_combine(ctx, buf, &self.keys, SetOperation::Union).await
+    }
+}
+
+#[derive(RedisCommand)]
+#[redis_command(syntax = "SDIFF key [key ...]")]
+pub struct Sdiff {
+    keys: Vec<Bytes>,
+}
+
+#[async_trait]
+impl AsyncCommand for Sdiff {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        set_combine(ctx, buf, &self.keys, SetOperation::Diff).await
+    }
+}
+
+async fn set_combine(
+    ctx: &crate::context::Context,
+    buf: &mut bytes::BytesMut,
+    keys: &[Bytes],
+    operation: SetOperation,
+) -> Result<(), crate::redis::RedisError> {
+    if keys.is_empty() {
+        return Err(StreamParseError::EmptyArg.into());
+    }
+    let members = ctx.app_data.db.set_combine(keys, operation).await?;
+    RespType::Array(members.into_iter().map(RespType::BulkString).collect()).write_to_buf(buf);
+    Ok(())
+}
+
+#[derive(RedisCommand)]
+#[redis_command(syntax = "SINTERSTORE destination key [key ...]", write)]
+pub struct Sinterstore {
+    destination: Bytes,
+    keys: Vec<Bytes>,
+}
+
+#[async_trait]
+impl AsyncCommand for Sinterstore {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        set_combine_store(ctx, buf, &self.destination, &self.keys, SetOperation::Inter).await
+    }
+}
+
+#[derive(RedisCommand)]
+#[redis_command(syntax = "SUNIONSTORE destination key [key ...]", write)]
+pub struct Sunionstore {
+    destination: Bytes,
+    keys: Vec<Bytes>,
+}
+
+#[async_trait]
+impl AsyncCommand for Sunionstore {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        set_combine_store(ctx, buf, &self.destination, &self.keys, SetOperation::Union).await
+    }
+}
+
+#[derive(RedisCommand)]
+#[redis_command(syntax = "SDIFFSTORE destination key [key ...]", write)]
+pub struct Sdiffstore {
+    destination: Bytes,
+    keys: Vec<Bytes>,
+}
+
+#[async_trait]
+impl AsyncCommand for Sdiffstore {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        set_combine_store(ctx, buf, &self.destination, &self.keys, SetOperation::Diff).await
+    }
+}
+
+/// The result replaces the destination in one step, and as the command is
+/// deterministic the replicas get it as the same single write
+async fn set_combine_store(
+    ctx: &crate::context::Context,
+    buf: &mut bytes::BytesMut,
+    destination: &Bytes,
+    keys: &[Bytes],
+    operation: SetOperation,
+) -> Result<(), crate::redis::RedisError> {
+    if keys.is_empty() {
+        return Err(StreamParseError::EmptyArg.into());
+    }
+    let len = ctx
+        .app_data
+        .db
+        .set_combine_store(destination, keys, operation)
+        .await?;
+    RespType::Integer(len as i64).write_to_buf(buf);
+    Ok(())
+}
+
+#[derive(RedisCommand)]
+#[redis_command(syntax = "SINTERCARD numkeys key [key ...] [LIMIT limit]", no_parse)]
+pub struct Sintercard {
+    keys: Vec<Bytes>,
+    limit: Option<usize>,
+}
+
+impl ParseStream for Sintercard {
+    fn parse_stream(stream: &mut RedisStream) -> Result<Self, StreamParseError> {
+        let num_keys = match stream.parse::<i64>() {
+            Ok(num_keys) if num_keys > 0 => num_keys as usize,
+            _ => {
+                return Err(StreamParseError::Other(
+                    "numkeys should be greater than 0".into(),
+                ));
+            }
+        };
+        if num_keys > stream.remaining() {
+            return Err(StreamParseError::Other(
+                "Number of keys can't be greater than number of args".into(),
+            ));
+        }
+        let keys = (0..num_keys)
+            .map(|_| stream.parse())
+            .collect::<Result<_, _>>()?;
+        let limit = match stream.next() {
+            Some(next) if next.eq_ignore_ascii_case(b"limit") => match stream.parse::<i64>() {
+                Ok(limit) if limit >= 0 => Some(limit as usize),
+                _ => {
+                    return Err(StreamParseError::Other("LIMIT can't be negative".into()));
+                }
+            },
+            Some(_) => return Err(StreamParseError::Other("syntax error".into())),
+            None => None,
+        };
+        if stream.peek().is_some() {
+            return Err(StreamParseError::Other("syntax error".into()));
+        }
+        // A limit of 0 means there's none
+        Ok(Self {
+            keys,
+            limit: limit.filter(|limit| *limit > 0),
+        })
+    }
+}
+
+#[async_trait]
+impl AsyncCommand for Sintercard {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        let len = ctx
+            .app_data
+            .db
+            .set_inter_card(&self.keys, self.limit)
+            .await?;
+        RespType::Integer(len as i64).write_to_buf(buf);
+        Ok(())
+    }
+}
@@ -1,18 +1,97 @@
 use async_trait::async_trait;
 use bytes::Bytes;
+use either::Either;
 use redis_proc_macros::RedisCommand;
 
 use crate::{
-    command::AsyncCommand,
-    resp::{NullBulkString, RedisWrite, RespType},
+    command::{AsyncCommand, block_deadline, parse_keys_timeout},
+    database::{
+        Aggregate, LexBound, ScoreBound, ScoreComparison, SetOperation, ZaddCondition, ZaddOptions,
+        ZaddResult, ZpopResponse, ZrangeBy, ZrangeQuery, ZsetEnd,
+    },
+    redis_stream::{ParseStream, RedisStream, StreamParseError},
+    resp::{NullArray, NullBulkString, RedisWrite, RespType},
 };
 
 #[derive(RedisCommand)]
-#[redis_command(syntax = "ZADD key score member")]
+#[redis_command(
+    syntax = "ZADD key [NX | XX] [GT | LT] [CH] [INCR] score member [score member ...]",
+    no_parse,
+    write
+)]
 pub struct Zadd {
     key: Bytes,
-    score: f64,
-    member: Bytes,
+    options: ZaddOptions,
+    members: Vec<(f64, Bytes)>,
+}
+
+impl ParseStream for Zadd {
+    fn parse_stream(stream: &mut RedisStream) -> Result<Self, StreamParseError> {
+        let key = stream.parse()?;
+        let (mut nx, mut xx, mut gt, mut lt) = (false, false, false, false);
+        let mut options = ZaddOptions::default();
+        while let Some(flag) = stream.peek() {
+            match flag.to_ascii_lowercase().as_slice() {
+                b"nx" => nx = true,
+                b"xx" => xx = true,
+                b"gt" => gt = true,
+                b"lt" => lt = true,
+                b"ch" => options.changed = true,
+                b"incr" => options.incr = true,
+                _ => break,
+            }
+            stream.next();
+        }
+        if nx && xx {
+            return Err(StreamParseError::Other(
+                "XX and NX options at the same time are not compatible".into(),
+            ));
+        }
+        if [nx, gt, lt].into_iter().filter(|flag| *flag).count() > 1 {
+            return Err(StreamParseError::Other(
+                "GT, LT, and/or NX options at the same time are not compatible".into(),
+            ));
+        }
+        options.condition = match (nx, xx) {
+            (true, _) => Some(ZaddCondition::Nx),
+            (_, true) => Some(ZaddCondition::Xx),
+            _ => None,
+        };
+        options.comparison = match (gt, lt) {
+            (true, _) => Some(ScoreComparison::Gt),
+            (_, true) => Some(ScoreComparison::Lt),
+            _ => None,
+        };
+        let mut members = vec![];
+        while let Some(score) = stream.next() {
+            let member = stream
+                .next()
+                .ok_or_else(|| StreamParseError::Other("syntax error".into()))?;
+            members.push((parse_score(&score)?, member));
+        }
+        if members.is_empty() {
+            return Err(StreamParseError::EmptyArg);
+        }
+        if options.incr && members.len() > 1 {
+            return Err(StreamParseError::Other(
+                "INCR option supports a single increment-element pair".into(),
+            ));
+        }
+        Ok(Self {
+            key,
+            options,
+            members,
+        })
+    }
+}
+
+/// Reads a score, which may be `+inf` or `-inf` but never NaN
+fn parse_score(score: &[u8]) -> Result<f64, StreamParseError> {
+    std::str::from_utf8(score)
+        .ok()
+        .and_then(|score| score.parse::<f64>().ok())
+        .filter(|score| !score.is_nan())
+        .ok_or_else(|| StreamParseError::Other("value is not a valid float".into()))
 }
 
 #[async_trait]
@@ -22,21 +101,79 @@ impl AsyncCommand for Zadd {
         ctx: &crate::context::Context,
         buf: &mut bytes::BytesMut,
     ) -> Result<(), crate::redis::RedisError> {
-        let idx = ctx
+        let result = ctx
             .app_data
             .db
-            .insert_set_member(self.key.clone(), self.member.clone(), self.score)
-            .await;
-        RespType::Integer(idx as i64).write_to_buf(buf);
+            .zadd(&self.key, self.members.clone(), self.options)
+            .await?;
+        match result {
+            ZaddResult::Count(count) => RespType::Integer(count as i64).write_to_buf(buf),
+            ZaddResult::Score(Some(score)) => {
+                RespType::bulk_string(format_score(score)).write_to_buf(buf)
+            }
+            ZaddResult::Score(None) => NullBulkString.write_to_buf(buf),
+        }
+        Ok(())
+    }
+}
+
+#[derive(RedisCommand)]
+#[redis_command(syntax = "ZINCRBY key increment member", no_parse, write)]
+pub struct Zincrby {
+    key: Bytes,
+    increment: f64,
+    member: Bytes,
+}
+
+impl ParseStream for Zincrby {
+    fn parse_stream(stream: &mut RedisStream) -> Result<Self, StreamParseError> {
+        Ok(Self {
+            key: stream.parse()?,
+            increment: parse_score(&stream.parse::<Bytes>()?)?,
+            member: stream.parse()?,
+        })
+    }
+}
+
+#[async_trait]
+impl AsyncCommand for Zincrby {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        let options = ZaddOptions {
+            incr: true,
+            ..Default::default()
+        };
+        let members = vec![(self.increment, self.member.clone())];
+        match ctx.app_data.db.zadd(&self.key, members, options).await? {
+            ZaddResult::Score(Some(score)) => {
+                RespType::bulk_string(format_score(score)).write_to_buf(buf)
+            }
+            _ => NullBulkString.write_to_buf(buf),
+        }
         Ok(())
     }
 }
 
 #[derive(RedisCommand)]
-#[redis_command(syntax = "ZRANK key member")]
+#[redis_command(syntax = "ZRANK key member [WITHSCORE]", no_parse)]
 pub struct Zrank {
     key: Bytes,
     member: Bytes,
+    with_score: bool,
+}
+
+impl ParseStream for Zrank {
+    fn parse_stream(stream: &mut RedisStream) -> Result<Self, StreamParseError> {
+        let (key, member, with_score) = parse_rank(stream)?;
+        Ok(Self {
+            key,
+            member,
+            with_score,
+        })
+    }
 }
 
 #[async_trait]
@@ -46,26 +183,252 @@ impl AsyncCommand for Zrank {
         ctx: &crate::context::Context,
         buf: &mut bytes::BytesMut,
     ) -> Result<(), crate::redis::RedisError> {
-        if let Some(rank) = ctx
-            .app_data
-            .db
-            .get_set_member_rank(&self.key, &self.member)
-            .await
-        {
-            RespType::Integer(rank as i64).write_to_buf(buf);
+        member_rank(ctx, buf, &self.key, &self.member, false, self.with_score).await
+    }
+}
+
+#[derive(RedisCommand)]
+#[redis_command(syntax = "ZREVRANK key member [WITHSCORE]", no_parse)]
+pub struct Zrevrank {
+    key: Bytes,
+    member: Bytes,
+    with_score: bool,
+}
+
+impl ParseStream for Zrevrank {
+    fn parse_stream(stream: &mut RedisStream) -> Result<Self, StreamParseError> {
+        let (key, member, with_score) = parse_rank(stream)?;
+        Ok(Self {
+            key,
+            member,
+            with_score,
+        })
+    }
+}
+
+#[async_trait]
+impl AsyncCommand for Zrevrank {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        member_rank(ctx, buf, &self.key, &self.member, true, self.with_score).await
+    }
+}
+
+/// Reads the key and member of `ZRANK` and `ZREVRANK` and whether
+/// `WITHSCORE` follows
+fn parse_rank(stream: &mut RedisStream) -> Result<(Bytes, Bytes, bool), StreamParseError> {
+    let key = stream.parse()?;
+    let member = stream.parse()?;
+    let with_score = match stream.next() {
+        Some(flag) if flag.eq_ignore_ascii_case(b"withscore") => true,
+        Some(_) => return Err(StreamParseError::Other("syntax error".into())),
+        None => false,
+    };
+    if stream.peek().is_some() {
+        return Err(StreamParseError::Other("syntax error".into()));
+    }
+    Ok((key, member, with_score))
+}
+
+/// Replies with the rank, or `[rank, score]` with `WITHSCORE`
+async fn member_rank(
+    ctx: &crate::context::Context,
+    buf: &mut bytes::BytesMut,
+    key: &Bytes,
+    member: &Bytes,
+    rev: bool,
+    with_score: bool,
+) -> Result<(), crate::redis::RedisError> {
+    match ctx
+        .app_data
+        .db
+        .get_set_member_rank(key, member, rev)
+        .await?
+    {
+        Some((rank, score)) if with_score => RespType::Array(vec![
+            RespType::Integer(rank as i64),
+            RespType::bulk_string(format_score(score)),
+        ])
+        .write_to_buf(buf),
+        Some((rank, _)) => RespType::Integer(rank as i64).write_to_buf(buf),
+        None => NullBulkString.write_to_buf(buf),
+    }
+    Ok(())
+}
+
+/// The flags that may follow the range of `ZRANGE` and its older forms
+#[derive(Default)]
+struct RangeFlags {
+    by_score: bool,
+    by_lex: bool,
+    rev: bool,
+    limit: Option<(i64, i64)>,
+    with_scores: bool,
+}
+
+impl RangeFlags {
+    /// Reads flags up to the end of the command, any not in `allowed` being a
+    /// syntax error
+    fn parse(stream: &mut RedisStream, allowed: &[&[u8]]) -> Result<Self, StreamParseError> {
+        let syntax_error = || StreamParseError::Other("syntax error".into());
+        let mut flags = Self::default();
+        while let Some(flag) = stream.next() {
+            let flag = flag.to_ascii_lowercase();
+            if !allowed.contains(&flag.as_slice()) {
+                return Err(syntax_error());
+            }
+            match flag.as_slice() {
+                b"byscore" => flags.by_score = true,
+                b"bylex" => flags.by_lex = true,
+                b"rev" => flags.rev = true,
+                b"withscores" => flags.with_scores = true,
+                b"limit" => {
+                    let mut integer = || match stream.next() {
+                        Some(value) => parse_integer(&value),
+                        None => Err(syntax_error()),
+                    };
+                    flags.limit = Some((integer()?, integer()?));
+                }
+                _ => return Err(syntax_error()),
+            }
+        }
+        Ok(flags)
+    }
+    /// Turns `start` and `stop` into the range the flags ask for. With `REV`
+    /// a score or lex range is given highest first.
+    fn query(&self, start: &Bytes, stop: &Bytes) -> Result<ZrangeQuery, StreamParseError> {
+        let (min, max) = if self.rev {
+            (stop, start)
         } else {
-            NullBulkString.write_to_buf(buf);
+            (start, stop)
+        };
+        let by = match (self.by_score, self.by_lex) {
+            (true, true) => return Err(StreamParseError::Other("syntax error".into())),
+            (true, false) => ZrangeBy::Score(parse_score_bound(min)?, parse_score_bound(max)?),
+            (false, true) => {
+                if self.with_scores {
+                    return Err(StreamParseError::Other(
+                        "syntax error, WITHSCORES not supported in combination with BYLEX".into(),
+                    ));
+                }
+                ZrangeBy::Lex(parse_lex_bound(min)?, parse_lex_bound(max)?)
+            }
+            (false, false) => {
+                if self.limit.is_some() {
+                    return Err(StreamParseError::Other(
+                        "syntax error, LIMIT is only supported in combination with either BYSCORE or BYLEX".into(),
+                    ));
+                }
+                ZrangeBy::Rank(parse_integer(start)?, parse_integer(stop)?)
+            }
+        };
+        Ok(ZrangeQuery {
+            by,
+            rev: self.rev,
+            limit: self.limit,
+        })
+    }
+}
+
+fn parse_integer(value: &[u8]) -> Result<i64, StreamParseError> {
+    std::str::from_utf8(value)
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .ok_or_else(|| StreamParseError::Other("value is not an integer or out of range".into()))
+}
+
+/// Reads a score bound like `1.5`, `(1.5` or `-inf`
+fn parse_score_bound(bound: &Bytes) -> Result<ScoreBound, StreamParseError> {
+    let (value, exclusive) = match bound.strip_prefix(b"(") {
+        Some(value) => (value, true),
+        None => (bound.as_ref(), false),
+    };
+    let value = parse_score(value)
+        .map_err(|_| StreamParseError::Other("min or max is not a float".into()))?;
+    Ok(ScoreBound { value, exclusive })
+}
+
+/// Reads a lex bound, one of `-`, `+`, `[member` or `(member`
+fn parse_lex_bound(bound: &Bytes) -> Result<LexBound, StreamParseError> {
+    match bound.as_ref() {
+        b"-" => Ok(LexBound::Min),
+        b"+" => Ok(LexBound::Max),
+        [b'[', ..] => Ok(LexBound::Inclusive(bound.slice(1..))),
+        [b'(', ..] => Ok(LexBound::Exclusive(bound.slice(1..))),
+        _ => Err(StreamParseError::Other(
+            "min or max not valid string range item".into(),
+        )),
+    }
+}
+
+/// Scores are replied with the way Redis prints them, C's `%.17g`
+fn format_score(score: f64) -> String {
+    const PRECISION: i32 = 17;
+    if score.is_infinite() {
+        return if score > 0.0 { "inf" } else { "-inf" }.to_string();
+    }
+    // Rounding to the precision first settles the exponent `%g` goes by
+    let scientific = format!("{score:.*e}", PRECISION as usize - 1);
+    let (mantissa, exponent) = scientific.split_once('e').expect("has an exponent");
+    let exponent: i32 = exponent.parse().expect("valid exponent");
+    let trim = |digits: &str| {
+        if digits.contains('.') {
+            digits
+                .trim_end_matches('0')
+                .trim_end_matches('.')
+                .to_string()
+        } else {
+            digits.to_string()
+        }
+    };
+    if (-4..PRECISION).contains(&exponent) {
+        let decimals = (PRECISION - 1 - exponent) as usize;
+        trim(&format!("{score:.decimals$}"))
+    } else {
+        let sign = if exponent < 0 { '-' } else { '+' };
+        format!("{}e{sign}{:02}", trim(mantissa), exponent.abs())
+    }
+}
+
+/// Replies with the members, each followed by its score with `WITHSCORES`
+fn write_members(members: Vec<(Bytes, f64)>, with_scores: bool, buf: &mut bytes::BytesMut) {
+    let mut reply = Vec::with_capacity(members.len() * if with_scores { 2 } else { 1 });
+    for (member, score) in members {
+        reply.push(RespType::BulkString(member));
+        if with_scores {
+            reply.push(RespType::bulk_string(format_score(score)));
         }
-        Ok(())
     }
+    RespType::Array(reply).write_to_buf(buf);
 }
 
 #[derive(RedisCommand)]
-#[redis_command(syntax = "ZRANGE key start end")]
+#[redis_command(
+    syntax = "ZRANGE key start stop [BYSCORE | BYLEX] [REV] [LIMIT offset count] [WITHSCORES]",
+    no_parse
+)]
 pub struct Zrange {
     key: Bytes,
-    start: i64,
-    end: i64,
+    query: ZrangeQuery,
+    with_scores: bool,
+}
+
+impl ParseStream for Zrange {
+    fn parse_stream(stream: &mut RedisStream) -> Result<Self, StreamParseError> {
+        let key = stream.parse()?;
+        let start: Bytes = stream.parse()?;
+        let stop: Bytes = stream.parse()?;
+        let allowed: &[&[u8]] = &[b"byscore", b"bylex", b"rev", b"limit", b"withscores"];
+        let flags = RangeFlags::parse(stream, allowed)?;
+        Ok(Self {
+            key,
+            query: flags.query(&start, &stop)?,
+            with_scores: flags.with_scores,
+        })
+    }
 }
 
 #[async_trait]
@@ -75,12 +438,253 @@ impl AsyncCommand for Zrange {
         ctx: &crate::context::Context,
         buf: &mut bytes::BytesMut,
     ) -> Result<(), crate::redis::RedisError> {
-        let result = ctx
+        let members = ctx
+            .app_data
+            .db
+            .range_sorted_set(&self.key, &self.query)
+            .await?;
+        write_members(members, self.with_scores, buf);
+        Ok(())
+    }
+}
+
+#[derive(RedisCommand)]
+#[redis_command(
+    syntax = "ZRANGEBYSCORE key min max [WITHSCORES] [LIMIT offset count]",
+    no_parse
+)]
+pub struct Zrangebyscore {
+    key: Bytes,
+    query: ZrangeQuery,
+    with_scores: bool,
+}
+
+impl ParseStream for Zrangebyscore {
+    fn parse_stream(stream: &mut RedisStream) -> Result<Self, StreamParseError> {
+        let key = stream.parse()?;
+        let min: Bytes = stream.parse()?;
+        let max: Bytes = stream.parse()?;
+        let mut flags = RangeFlags::parse(stream, &[b"withscores", b"limit"])?;
+        flags.by_score = true;
+        Ok(Self {
+            key,
+            query: flags.query(&min, &max)?,
+            with_scores: flags.with_scores,
+        })
+    }
+}
+
+#[async_trait]
+impl AsyncCommand for Zrangebyscore {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        let members = ctx
+            .app_data
+            .db
+            .range_sorted_set(&self.key, &self.query)
+            .await?;
+        write_members(members, self.with_scores, buf);
+        Ok(())
+    }
+}
+
+#[derive(RedisCommand)]
+#[redis_command(
+    syntax = "ZREVRANGEBYSCORE key max min [WITHSCORES] [LIMIT offset count]",
+    no_parse
+)]
+pub struct Zrevrangebyscore {
+    key: Bytes,
+    query: ZrangeQuery,
+    with_scores: bool,
+}
+
+impl ParseStream for Zrevrangebyscore {
+    fn parse_stream(stream: &mut RedisStream) -> Result<Self, StreamParseError> {
+        let key = stream.parse()?;
+        let max: Bytes = stream.parse()?;
+        let min: Bytes = stream.parse()?;
+        let mut flags = RangeFlags::parse(stream, &[b"withscores", b"limit"])?;
+        flags.by_score = true;
+        flags.rev = true;
+        Ok(Self {
+            key,
+            query: flags.query(&max, &min)?,
+            with_scores: flags.with_scores,
+        })
+    }
+}
+
+#[async_trait]
+impl AsyncCommand for Zrevrangebyscore {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        let members = ctx
+            .app_data
+            .db
+            .range_sorted_set(&self.key, &self.query)
+            .await?;
+        write_members(members, self.with_scores, buf);
+        Ok(())
+    }
+}
+
+#[derive(RedisCommand)]
+#[redis_command(syntax = "ZRANGEBYLEX key min max [LIMIT offset count]", no_parse)]
+pub struct Zrangebylex {
+    key: Bytes,
+    query: ZrangeQuery,
+}
+
+impl ParseStream for Zrangebylex {
+    fn parse_stream(stream: &mut RedisStream) -> Result<Self, StreamParseError> {
+        let key = stream.parse()?;
+        let min: Bytes = stream.parse()?;
+        let max: Bytes = stream.parse()?;
+        let mut flags = RangeFlags::parse(stream, &[b"limit"])?;
+        flags.by_lex = true;
+        Ok(Self {
+            key,
+            query: flags.query(&min, &max)?,
+        })
+    }
+}
+
+#[async_trait]
+impl AsyncCommand for Zrangebylex {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        let members = ctx
+            .app_data
+            .db
+            .range_sorted_set(&self.key, &self.query)
+            .await?;
+        write_members(members, false, buf);
+        Ok(())
+    }
+}
+
+#[derive(RedisCommand)]
+#[redis_command(
+    syntax = "ZRANGESTORE dst src min max [BYSCORE | BYLEX] [REV] [LIMIT offset count]",
+    no_parse,
+    write
+)]
+pub struct Zrangestore {
+    destination: Bytes,
+    source: Bytes,
+    query: ZrangeQuery,
+}
+
+impl ParseStream for Zrangestore {
+    fn parse_stream(stream: &mut RedisStream) -> Result<Self, StreamParseError> {
+        let destination = stream.parse()?;
+        let source = stream.parse()?;
+        let start: Bytes = stream.parse()?;
+        let stop: Bytes = stream.parse()?;
+        let flags = RangeFlags::parse(stream, &[b"byscore", b"bylex", b"rev", b"limit"])?;
+        Ok(Self {
+            destination,
+            source,
+            query: flags.query(&start, &stop)?,
+        })
+    }
+}
+
+#[async_trait]
+impl AsyncCommand for Zrangestore {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        let len = ctx
+            .app_data
+            .db
+            .store_sorted_set_range(&self.destination, &self.source, &self.query)
+            .await?;
+        RespType::Integer(len as i64).write_to_buf(buf);
+        Ok(())
+    }
+}
+
+#[derive(RedisCommand)]
+#[redis_command(syntax = "ZCOUNT key min max", no_parse)]
+pub struct Zcount {
+    key: Bytes,
+    by: ZrangeBy,
+}
+
+impl ParseStream for Zcount {
+    fn parse_stream(stream: &mut RedisStream) -> Result<Self, StreamParseError> {
+        let key = stream.parse()?;
+        let min = parse_score_bound(&stream.parse()?)?;
+        let max = parse_score_bound(&stream.parse()?)?;
+        Ok(Self {
+            key,
+            by: ZrangeBy::Score(min, max),
+        })
+    }
+}
+
+#[async_trait]
+impl AsyncCommand for Zcount {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        let count = ctx
+            .app_data
+            .db
+            .count_sorted_set_range(&self.key, &self.by)
+            .await?;
+        RespType::Integer(count as i64).write_to_buf(buf);
+        Ok(())
+    }
+}
+
+#[derive(RedisCommand)]
+#[redis_command(syntax = "ZLEXCOUNT key min max", no_parse)]
+pub struct Zlexcount {
+    key: Bytes,
+    by: ZrangeBy,
+}
+
+impl ParseStream for Zlexcount {
+    fn parse_stream(stream: &mut RedisStream) -> Result<Self, StreamParseError> {
+        let key = stream.parse()?;
+        let min = parse_lex_bound(&stream.parse()?)?;
+        let max = parse_lex_bound(&stream.parse()?)?;
+        Ok(Self {
+            key,
+            by: ZrangeBy::Lex(min, max),
+        })
+    }
+}
+
+#[async_trait]
+impl AsyncCommand for Zlexcount {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        let count = ctx
             .app_data
             .db
-            .range_sorted_set(&self.key, self.start, self.end)
-            .await;
-        result.write_to_buf(buf);
+            .count_sorted_set_range(&self.key, &self.by)
+            .await?;
+        RespType::Integer(count as i64).write_to_buf(buf);
         Ok(())
     }
 }
@@ -98,7 +702,7 @@ impl AsyncCommand for Zcard {
         ctx: &crate::context::Context,
         buf: &mut bytes::BytesMut,
     ) -> Result<(), crate::redis::RedisError> {
-        let len = ctx.app_data.db.count_sorted_set(&self.key).await;
+        let len = ctx.app_data.db.count_sorted_set(&self.key).await?;
         RespType::Integer(len as i64).write_to_buf(buf);
         Ok(())
     }
@@ -121,9 +725,9 @@ impl AsyncCommand for Zscore {
             .app_data
             .db
             .get_set_member_score(&self.key, &self.member)
-            .await
+            .await?
         {
-            RespType::BulkString(score.to_string().into()).write_to_buf(buf);
+            RespType::bulk_string(format_score(score)).write_to_buf(buf);
         } else {
             NullBulkString.write_to_buf(buf);
         }
@@ -132,10 +736,10 @@ impl AsyncCommand for Zscore {
 }
 
 #[derive(RedisCommand)]
-#[redis_command(syntax = "ZREM key member")]
+#[redis_command(syntax = "ZREM key member [member ...]", write)]
 pub struct Zrem {
     key: Bytes,
-    member: Bytes,
+    members: Vec<Bytes>,
 }
 
 #[async_trait]
@@ -148,9 +752,938 @@ impl AsyncCommand for Zrem {
         let num = ctx
             .app_data
             .db
-            .remove_set_member(&self.key, &self.member)
-            .await;
+            .remove_set_members(&self.key, &self.members)
+            .await?;
         RespType::Integer(num as i64).write_to_buf(buf);
         Ok(())
     }
 }
+
+#[derive(RedisCommand)]
+#[redis_command(syntax = "ZMSCORE key member [member ...]")]
+pub struct Zmscore {
+    key: Bytes,
+    members: Vec<Bytes>,
+}
+
+#[async_trait]
+impl AsyncCommand for Zmscore {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        let scores = ctx
+            .app_data
+            .db
+            .get_set_member_scores(&self.key, &self.members)
+            .await?;
+        let scores = scores
+            .into_iter()
+            .map(|score| match score {
+                Some(score) => RespType::bulk_string(format_score(score)),
+                None => RespType::NullBulkString,
+            })
+            .collect();
+        RespType::Array(scores).write_to_buf(buf);
+        Ok(())
+    }
+}
+
+#[derive(RedisCommand)]
+#[redis_command(syntax = "ZREMRANGEBYRANK key start stop", no_parse, write)]
+pub struct Zremrangebyrank {
+    key: Bytes,
+    by: ZrangeBy,
+}
+
+impl ParseStream for Zremrangebyrank {
+    fn parse_stream(stream: &mut RedisStream) -> Result<Self, StreamParseError> {
+        let key = stream.parse()?;
+        let start = parse_integer(&stream.parse::<Bytes>()?)?;
+        let stop = parse_integer(&stream.parse::<Bytes>()?)?;
+        Ok(Self {
+            key,
+            by: ZrangeBy::Rank(start, stop),
+        })
+    }
+}
+
+#[async_trait]
+impl AsyncCommand for Zremrangebyrank {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        remove_range(ctx, buf, &self.key, &self.by).await
+    }
+}
+
+#[derive(RedisCommand)]
+#[redis_command(syntax = "ZREMRANGEBYSCORE key min max", no_parse, write)]
+pub struct Zremrangebyscore {
+    key: Bytes,
+    by: ZrangeBy,
+}
+
+impl ParseStream for Zremrangebyscore {
+    fn parse_stream(stream: &mut RedisStream) -> Result<Self, StreamParseError> {
+        let key = stream.parse()?;
+        let min = parse_score_bound(&stream.parse()?)?;
+        let max = parse_score_bound(&stream.parse()?)?;
+        Ok(Self {
+            key,
+            by: ZrangeBy::Score(min, max),
+        })
+    }
+}
+
+#[async_trait]
+impl AsyncCommand for Zremrangebyscore {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        remove_range(ctx, buf, &self.key, &self.by).await
+    }
+}
+
+#[derive(RedisCommand)]
+#[redis_command(syntax = "ZREMRANGEBYLEX key min max", no_parse, write)]
+pub struct Zremrangebylex {
+    key: Bytes,
+    by: ZrangeBy,
+}
+
+impl ParseStream for Zremrangebylex {
+    fn parse_stream(stream: &mut RedisStream) -> Result<Self, StreamParseError> {
+        let key = stream.parse()?;
+        let min = parse_lex_bound(&stream.parse()?)?;
+        let max = parse_lex_bound(&stream.parse()?)?;
+        Ok(Self {
+            key,
+            by: ZrangeBy::Lex(min, max),
+        })
+    }
+}
+
+#[async_trait]
+impl AsyncCommand for Zremrangebylex {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        remove_range(ctx, buf, &self.key, &self.by).await
+    }
+}
+
+async fn remove_range(
+    ctx: &crate::context::Context,
+    buf: &mut bytes::BytesMut,
+    key: &Bytes,
+    by: &ZrangeBy,
+) -> Result<(), crate::redis::RedisError> {
+    let removed = ctx.app_data.db.remove_sorted_set_range(key, by).await?;
+    RespType::Integer(removed as i64).write_to_buf(buf);
+    Ok(())
+}
+
+#[derive(RedisCommand)]
+#[redis_command(syntax = "ZRANDMEMBER key [count [WITHSCORES]]", no_parse)]
+pub struct Zrandmember {
+    key: Bytes,
+    count: Option<i64>,
+    with_scores: bool,
+}
+
+impl ParseStream for Zrandmember {
+    fn parse_stream(stream: &mut RedisStream) -> Result<Self, StreamParseError> {
+        let key = stream.parse()?;
+        let count = match stream.next() {
+            Some(count) => Some(parse_integer(&count)?),
+            None => None,
+        };
+        let with_scores = match stream.next() {
+            Some(flag) if flag.eq_ignore_ascii_case(b"withscores") => true,
+            Some(_) => return Err(StreamParseError::Other("syntax error".into())),
+            None => false,
+        };
+        if stream.peek().is_some() {
+            return Err(StreamParseError::Other("syntax error".into()));
+        }
+        Ok(Self {
+            key,
+            count,
+            with_scores,
+        })
+    }
+}
+
+#[async_trait]
+impl AsyncCommand for Zrandmember {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        let db = &ctx.app_data.db;
+        let Some(count) = self.count else {
+            match db.sorted_set_rand_members(&self.key, 1).await?.pop() {
+                Some((member, _)) => RespType::BulkString(member).write_to_buf(buf),
+                None => NullBulkString.write_to_buf(buf),
+            }
+            return Ok(());
+        };
+        let members = db.sorted_set_rand_members(&self.key, count).await?;
+        write_members(members, self.with_scores, buf);
+        Ok(())
+    }
+}
+
+/// The arguments shared by `ZUNION`, `ZINTER`, `ZDIFF` and their `STORE`
+/// forms, from `numkeys` on
+struct ZsetCombine {
+    keys: Vec<Bytes>,
+    weights: Vec<f64>,
+    aggregate: Aggregate,
+    with_scores: bool,
+}
+
+impl ZsetCombine {
+    /// Reads the keys and then the options in `allowed`, naming `command` when
+    /// there are no keys
+    fn parse(
+        stream: &mut RedisStream,
+        command: &str,
+        allowed: &[&[u8]],
+    ) -> Result<Self, StreamParseError> {
+        let syntax_error = || StreamParseError::Other("syntax error".into());
+        let num_keys = parse_integer(&stream.parse::<Bytes>()?)?;
+        if num_keys < 1 {
+            return Err(StreamParseError::Other(format!(
+                "at least 1 input key is needed for '{command}' command"
+            )));
+        }
+        let num_keys = num_keys as usize;
+        if num_keys > stream.remaining() {
+            return Err(syntax_error());
+        }
+        let keys: Vec<Bytes> = (0..num_keys)
+            .map(|_| stream.parse())
+            .collect::<Result<_, _>>()?;
+        let mut combine = Self {
+            weights: vec![1.0; keys.len()],
+            keys,
+            aggregate: Aggregate::default(),
+            with_scores: false,
+        };
+        while let Some(option) = stream.next() {
+            let option = option.to_ascii_lowercase();
+            if !allowed.contains(&option.as_slice()) {
+                return Err(syntax_error());
+            }
+            match option.as_slice() {
+                b"weights" => {
+                    if stream.remaining() < num_keys {
+                        return Err(syntax_error());
+                    }
+                    for weight in combine.weights.iter_mut() {
+                        *weight = parse_score(&stream.parse::<Bytes>()?).map_err(|_| {
+                            StreamParseError::Other("weight value is not a float".into())
+                        })?;
+                    }
+                }
+                b"aggregate" => {
+                    let aggregate = stream.next().ok_or_else(syntax_error)?;
+                    combine.aggregate = match aggregate.to_ascii_lowercase().as_slice() {
+                        b"sum" => Aggregate::Sum,
+                        b"min" => Aggregate::Min,
+                        b"max" => Aggregate::Max,
+                        _ => return Err(syntax_error()),
+                    };
+                }
+                b"withscores" => combine.with_scores = true,
+                _ => return Err(syntax_error()),
+            }
+        }
+        Ok(combine)
+    }
+}
+
+#[derive(RedisCommand)]
+#[redis_command(
+    syntax = "ZUNION numkeys key [key ...] [WEIGHTS weight [weight ...]] [AGGREGATE SUM | MIN | MAX] [WITHSCORES]",
+    no_parse
+)]
+pub struct Zunion {
+    combine: ZsetCombine,
+}
+
+impl ParseStream for Zunion {
+    fn parse_stream(stream: &mut RedisStream) -> Result<Self, StreamParseError> {
+        let allowed: &[&[u8]] = &[b"weights", b"aggregate", b"withscores"];
+        Ok(Self {
+            combine: ZsetCombine::parse(stream, "zunion", allowed)?,
+        })
+    }
+}
+
+#[async_trait]
+impl AsyncCommand for Zunion {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        sorted_set_combine(ctx, buf, &self.combine, SetOperation::Union).await
+    }
+}
+
+#[derive(RedisCommand)]
+#[redis_command(
+    syntax = "ZINTER numkeys key [key ...] [WEIGHTS weight [weight ...]] [AGGREGATE SUM | MIN | MAX] [WITHSCORES]",
+    no_parse
+)]
+pub struct Zinter {
+    combine: ZsetCombine,
+}
+
+impl ParseStream for Zinter {
+    fn parse_stream(stream: &mut RedisStream) -> Result<Self, StreamParseError> {
+        let allowed: &[&[u8]] = &[b"weights", b"aggregate", b"withscores"];
+        Ok(Self {
+            combine: ZsetCombine::parse(stream, "zinter", allowed)?,
+        })
+    }
+}
+
+#[async_trait]
+impl AsyncCommand for Zinter {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        sorted_set_combine(ctx, buf, &self.combine, SetOperation::Inter).await
+    }
+}
+
+#[derive(RedisCommand)]
+#[redis_command(syntax = "ZDIFF numkeys key [key ...] [WITHSCORES]", no_parse)]
+pub struct Zdiff {
+    combine: ZsetCombine,
+}
+
+impl ParseStream for Zdiff {
+    fn parse_stream(stream: &mut RedisStream) -> Result<Self, StreamParseError> {
+        Ok(Self {
+            combine: ZsetCombine::parse(stream, "zdiff", &[b"withscores"])?,
+        })
+    }
+}
+
+#[async_trait]
+impl AsyncCommand for Zdiff {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        sorted_set_combine(ctx, buf, &self.combine, SetOperation::Diff).await
+    }
+}
+
+async fn sorted_set_combine(
+    ctx: &crate::context::Context,
+    buf: &mut bytes::BytesMut,
+    combine: &ZsetCombine,
+    operation: SetOperation,
+) -> Result<(), crate::redis::RedisError> {
+    let members = ctx
+        .app_data
+        .db
+        .sorted_set_combine(
+            &combine.keys,
+            &combine.weights,
+            operation,
+            combine.aggregate,
+        )
+        .await?;
+    write_members(members, combine.with_scores, buf);
+    Ok(())
+}
+
+#[derive(RedisCommand)]
+#[redis_command(
+    syntax = "ZUNIONSTORE destination numkeys key [key ...] [WEIGHTS weight [weight ...]] [AGGREGATE SUM | MIN | MAX]",
+    no_parse,
+    write
+)]
+pub struct Zunionstore {
+    destination: Bytes,
+    combine: ZsetCombine,
+}
+
+impl ParseStream for Zunionstore {
+    fn parse_stream(stream: &mut RedisStream) -> Result<Self, StreamParseError> {
+        Ok(Self {
+            destination: stream.parse()?,
+            combine: ZsetCombine::parse(stream, "zunionstore", &[b"weights", b"aggregate"])?,
+        })
+    }
+}
+
+#[async_trait]
+impl AsyncCommand for Zunionstore {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        sorted_set_combine_store(
+            ctx,
+            buf,
+            &self.destination,
+            &self.combine,
+            SetOperation::Union,
+        )
+        .await
+    }
+}
+
+#[derive(RedisCommand)]
+#[redis_command(
+    syntax = "ZINTERSTORE destination numkeys key [key ...] [WEIGHTS weight [weight ...]] [AGGREGATE SUM | MIN | MAX]",
+    no_parse,
+    write
+)]
+pub struct Zinterstore {
+    destination: Bytes,
+    combine: ZsetCombine,
+}
+
+impl ParseStream for Zinterstore {
+    fn parse_stream(stream: &mut RedisStream) -> Result<Self, StreamParseError> {
+        Ok(Self {
+            destination: stream.parse()?,
+            combine: ZsetCombine::parse(stream, "zinterstore", &[b"weights", b"aggregate"])?,
+        })
+    }
+}
+
+#[async_trait]
+impl AsyncCommand for Zinterstore {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        sorted_set_combine_store(
+            ctx,
+            buf,
+            &self.destination,
+            &self.combine,
+            SetOperation::Inter,
+        )
+        .await
+    }
+}
+
+#[derive(RedisCommand)]
+#[redis_command(
+    syntax = "ZDIFFSTORE destination numkeys key [key ...]",
+    no_parse,
+    write
+)]
+pub struct Zdiffstore {
+    destination: Bytes,
+    combine: ZsetCombine,
+}
+
+impl ParseStream for Zdiffstore {
+    fn parse_stream(stream: &mut RedisStream) -> Result<Self, StreamParseError> {
+        Ok(Self {
+            destination: stream.parse()?,
+            combine: ZsetCombine::parse(stream, "zdiffstore", &[])?,
+        })
+    }
+}
+
+#[async_trait]
+impl AsyncCommand for Zdiffstore {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        sorted_set_combine_store(
+            ctx,
+            buf,
+            &self.destination,
+            &self.combine,
+            SetOperation::Diff,
+        )
+        .await
+    }
+}
+
+/// Like `SUNIONSTORE`, the destination is replaced under one lock and the
+/// command replicates as it was given
+async fn sorted_set_combine_store(
+    ctx: &crate::context::Context,
+    buf: &mut bytes::BytesMut,
+    destination: &Bytes,
+    combine: &ZsetCombine,
+    operation: SetOperation,
+) -> Result<(), crate::redis::RedisError> {
+    let len = ctx
+        .app_data
+        .db
+        .sorted_set_combine_store(
+            destination,
+            &combine.keys,
+            &combine.weights,
+            operation,
+            combine.aggregate,
+        )
+        .await?;
+    RespType::Integer(len as i64).write_to_buf(buf);
+    Ok(())
+}
+
+#[derive(RedisCommand)]
+#[redis_command(syntax = "ZINTERCARD numkeys key [key ...] [LIMIT limit]", no_parse)]
+pub struct Zintercard {
+    keys: Vec<Bytes>,
+    limit: Option<usize>,
+}
+
+impl ParseStream for Zintercard {
+    fn parse_stream(stream: &mut RedisStream) -> Result<Self, StreamParseError> {
+        let num_keys = match stream.parse::<i64>() {
+            Ok(num_keys) if num_keys > 0 => num_keys as usize,
+            _ => {
+                return Err(StreamParseError::Other(
+                    "numkeys should be greater than 0".into(),
+                ));
+            }
+        };
+        if num_keys > stream.remaining() {
+            return Err(StreamParseError::Other(
+                "Number of keys can't be greater than number of args".into(),
+            ));
+        }
+        let keys = (0..num_keys)
+            .map(|_| stream.parse())
+            .collect::<Result<_, _>>()?;
+        let limit = match stream.next() {
+            Some(next) if next.eq_ignore_ascii_case(b"limit") => match stream.parse::<i64>() {
+                Ok(limit) if limit >= 0 => Some(limit as usize),
+                _ => {
+                    return Err(StreamParseError::Other("LIMIT can't be negative".into()));
+                }
+            },
+            Some(_) => return Err(StreamParseError::Other("syntax error".into())),
+            None => None,
+        };
+        if stream.peek().is_some() {
+            return Err(StreamParseError::Other("syntax error".into()));
+        }
+        // A limit of 0 means there's none
+        Ok(Self {
+            keys,
+            limit: limit.filter(|limit| *limit > 0),
+        })
+    }
+}
+
+#[async_trait]
+impl AsyncCommand for Zintercard {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        let len = ctx
+            .app_data
+            .db
+            .sorted_set_inter_card(&self.keys, self.limit)
+            .await?;
+        RespType::Integer(len as i64).write_to_buf(buf);
+        Ok(())
+    }
+}
+
+#[derive(RedisCommand)]
+#[redis_command(syntax = "ZPOPMIN key [count]", no_parse, write)]
+pub struct Zpopmin {
+    key: Bytes,
+    count: usize,
+}
+
+impl ParseStream for Zpopmin {
+    fn parse_stream(stream: &mut RedisStream) -> Result<Self, StreamParseError> {
+        let (key, count) = parse_key_count(stream)?;
+        Ok(Self { key, count })
+    }
+}
+
+#[async_trait]
+impl AsyncCommand for Zpopmin {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        pop_sorted_set(ctx, buf, &self.key, ZsetEnd::Min, self.count).await
+    }
+}
+
+#[derive(RedisCommand)]
+#[redis_command(syntax = "ZPOPMAX key [count]", no_parse, write)]
+pub struct Zpopmax {
+    key: Bytes,
+    count: usize,
+}
+
+impl ParseStream for Zpopmax {
+    fn parse_stream(stream: &mut RedisStream) -> Result<Self, StreamParseError> {
+        let (key, count) = parse_key_count(stream)?;
+        Ok(Self { key, count })
+    }
+}
+
+#[async_trait]
+impl AsyncCommand for Zpopmax {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        pop_sorted_set(ctx, buf, &self.key, ZsetEnd::Max, self.count).await
+    }
+}
+
+/// Reads the key of `ZPOPMIN` and `ZPOPMAX` and the count, which is 1 when
+/// left out
+fn parse_key_count(stream: &mut RedisStream) -> Result<(Bytes, usize), StreamParseError> {
+    let key = stream.parse()?;
+    let count = match stream.next() {
+        Some(count) => match parse_integer(&count)? {
+            count if count >= 0 => count as usize,
+            _ => {
+                return Err(StreamParseError::Other(
+                    "value is out of range, must be positive".into(),
+                ));
+            }
+        },
+        None => 1,
+    };
+    if stream.peek().is_some() {
+        return Err(StreamParseError::Other("syntax error".into()));
+    }
+    Ok((key, count))
+}
+
+/// Replies with the popped members and their scores in one flat array
+async fn pop_sorted_set(
+    ctx: &crate::context::Context,
+    buf: &mut bytes::BytesMut,
+    key: &Bytes,
+    end: ZsetEnd,
+    count: usize,
+) -> Result<(), crate::redis::RedisError> {
+    let members = ctx.app_data.db.pop_sorted_set(key, end, count).await?;
+    write_members(members, true, buf);
+    Ok(())
+}
+
+#[derive(RedisCommand)]
+#[redis_command(syntax = "BZPOPMIN key [key ...] timeout", no_parse)]
+pub struct Bzpopmin {
+    keys: Vec<Bytes>,
+    timeout: f64,
+}
+
+impl ParseStream for Bzpopmin {
+    fn parse_stream(stream: &mut RedisStream) -> Result<Self, StreamParseError> {
+        let (keys, timeout) = parse_keys_timeout(stream)?;
+        Ok(Self { keys, timeout })
+    }
+}
+
+#[async_trait]
+impl AsyncCommand for Bzpopmin {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        let pop = ZsetMultiPop {
+            keys: self.keys.clone(),
+            end: ZsetEnd::Min,
+            count: None,
+        };
+        blocking_pop(ctx, buf, &pop, self.timeout).await
+    }
+}
+
+#[derive(RedisCommand)]
+#[redis_command(syntax = "BZPOPMAX key [key ...] timeout", no_parse)]
+pub struct Bzpopmax {
+    keys: Vec<Bytes>,
+    timeout: f64,
+}
+
+impl ParseStream for Bzpopmax {
+    fn parse_stream(stream: &mut RedisStream) -> Result<Self, StreamParseError> {
+        let (keys, timeout) = parse_keys_timeout(stream)?;
+        Ok(Self { keys, timeout })
+    }
+}
+
+#[async_trait]
+impl AsyncCommand for Bzpopmax {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        let pop = ZsetMultiPop {
+            keys: self.keys.clone(),
+            end: ZsetEnd::Max,
+            count: None,
+        };
+        blocking_pop(ctx, buf, &pop, self.timeout).await
+    }
+}
+
+impl ParseStream for ZsetEnd {
+    fn parse_stream(stream: &mut RedisStream) -> Result<Self, StreamParseError> {
+        let next = stream.next().ok_or(StreamParseError::EmptyArg)?;
+        match next.to_ascii_lowercase().as_slice() {
+            b"min" => Ok(ZsetEnd::Min),
+            b"max" => Ok(ZsetEnd::Max),
+            _ => Err(StreamParseError::Other("syntax error".into())),
+        }
+    }
+}
+
+/// The `numkeys key [key ...] <MIN | MAX> [COUNT count]` arguments of `ZMPOP`
+/// and `BZMPOP`. The count is only set for those two, which reply with every
+/// popped member rather than a single one.
+#[derive(Debug, PartialEq)]
+pub struct ZsetMultiPop {
+    keys: Vec<Bytes>,
+    end: ZsetEnd,
+    count: Option<usize>,
+}
+
+impl ParseStream for ZsetMultiPop {
+    fn parse_stream(stream: &mut RedisStream) -> Result<Self, StreamParseError> {
+        let num_keys = match stream.parse::<i64>() {
+            Ok(num_keys) if num_keys > 0 => num_keys as usize,
+            _ => {
+                return Err(StreamParseError::Other(
+                    "numkeys should be greater than 0".into(),
+                ));
+            }
+        };
+        if num_keys > stream.remaining() {
+            return Err(StreamParseError::Other(
+                "Number of keys can't be greater than number of args".into(),
+            ));
+        }
+        let keys = (0..num_keys)
+            .map(|_| stream.parse())
+            .collect::<Result<_, _>>()?;
+        let end = stream.parse()?;
+        let count = match stream.next() {
+            Some(next) if next.eq_ignore_ascii_case(b"count") => match stream.parse::<i64>() {
+                Ok(count) if count > 0 => count as usize,
+                _ => {
+                    return Err(StreamParseError::Other(
+                        "count should be greater than 0".into(),
+                    ));
+                }
+            },
+            Some(_) => return Err(StreamParseError::Other("syntax error".into())),
+            None => 1,
+        };
+        if stream.peek().is_some() {
+            return Err(StreamParseError::Other("syntax error".into()));
+        }
+        Ok(Self {
+            keys,
+            end,
+            count: Some(count),
+        })
+    }
+}
+
+/// Replies with the key and what was popped from it, `[key, [[member, score]
+/// ...]]` when a count was given and `[key, member, score]` otherwise
+fn write_popped(response: ZpopResponse, count: Option<usize>, buf: &mut bytes::BytesMut) {
+    let mut reply = vec![RespType::BulkString(response.key)];
+    let mut members = response.members.into_iter().map(|(member, score)| {
+        vec![
+            RespType::BulkString(member),
+            RespType::bulk_string(format_score(score)),
+        ]
+    });
+    match count {
+        Some(_) => reply.push(RespType::Array(members.map(RespType::Array).collect())),
+        None => reply.extend(members.next().into_iter().flatten()),
+    }
+    RespType::Array(reply).write_to_buf(buf);
+}
+
+#[derive(RedisCommand)]
+#[redis_command(
+    syntax = "ZMPOP numkeys key [key ...] <MIN | MAX> [COUNT count]",
+    no_parse,
+    write
+)]
+pub struct Zmpop {
+    pop: ZsetMultiPop,
+}
+
+impl ParseStream for Zmpop {
+    fn parse_stream(stream: &mut RedisStream) -> Result<Self, StreamParseError> {
+        Ok(Self {
+            pop: stream.parse()?,
+        })
+    }
+}
+
+#[async_trait]
+impl AsyncCommand for Zmpop {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        let ZsetMultiPop { keys, end, count } = &self.pop;
+        let popped = ctx
+            .app_data
+            .db
+            .pop_sorted_sets(keys, *end, count.unwrap_or(1))
+            .await?;
+        match popped {
+            Some(response) => write_popped(response, *count, buf),
+            None => NullArray.write_to_buf(buf),
+        }
+        Ok(())
+    }
+}
+
+#[derive(RedisCommand)]
+#[redis_command(
+    syntax = "BZMPOP timeout numkeys key [key ...] <MIN | MAX> [COUNT count]",
+    no_parse
+)]
+pub struct Bzmpop {
+    timeout: f64,
+    pop: ZsetMultiPop,
+}
+
+impl ParseStream for Bzmpop {
+    fn parse_stream(stream: &mut RedisStream) -> Result<Self, StreamParseError> {
+        Ok(Self {
+            timeout: stream.parse()?,
+            pop: stream.parse()?,
+        })
+    }
+}
+
+#[async_trait]
+impl AsyncCommand for Bzmpop {
+    async fn run_command(
+        &self,
+        ctx: &crate::context::Context,
+        buf: &mut bytes::BytesMut,
+    ) -> Result<(), crate::redis::RedisError> {
+        blocking_pop(ctx, buf, &self.pop, self.timeout).await
+    }
+}
+
+/// Like the blocking list pops, a served pop replicates as the `ZPOPMIN` or
+/// `ZPOPMAX` it turned into
+async fn blocking_pop(
+    ctx: &crate::context::Context,
+    buf: &mut bytes::BytesMut,
+    ZsetMultiPop { keys, end, count }: &ZsetMultiPop,
+    timeout: f64,
+) -> Result<(), crate::redis::RedisError> {
+    let timeout = block_deadline(timeout)?;
+    let popped = ctx
+        .app_data
+        .db
+        .blocking_pop_sorted_set(ctx.client_id, keys, timeout, *end, count.unwrap_or(1))
+        .await?;
+    let response = match popped {
+        Either::Left(response) => Some(response),
+        Either::Right(receiver) => {
+            ctx.app_data
+                .db
+                .wait_blocked(ctx.client_id, ctx.closed.clone(), keys, receiver, timeout)
+                .await?
+        }
+    };
+    let Some(response) = response else {
+        NullArray.write_to_buf(buf);
+        return Ok(());
+    };
+    let mut command = vec![
+        match end {
+            ZsetEnd::Min => RespType::bulk_string("ZPOPMIN"),
+            ZsetEnd::Max => RespType::bulk_string("ZPOPMAX"),
+        },
+        RespType::BulkString(response.key.clone()),
+    ];
+    if count.is_some() {
+        command.push(RespType::bulk_string(response.members.len()));
+    }
+    ctx.app_data.propagate(RespType::Array(command)).await;
+    write_popped(response, *count, buf);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+
+    #[test]
+    fn test_format_score() {
+        assert_eq!(format_score(1.5), "1.5");
+        assert_eq!(format_score(-3.0), "-3");
+        assert_eq!(format_score(0.0), "0");
+        assert_eq!(format_score(0.1), "0.10000000000000001");
+        assert_eq!(format_score(1e20), "1e+20");
+        assert_eq!(format_score(1e-7), "9.9999999999999995e-08");
+        assert_eq!(format_score(123456789012345680.0), "1.2345678901234568e+17");
+        assert_eq!(format_score(0.0001), "0.0001");
+        assert_eq!(format_score(f64::NEG_INFINITY), "-inf");
+    }
+
+    #[test]
+    fn test_bzpopmin_timeout_out_of_range() {
+        for timeout in ["inf", "-inf", "nan", "1e300"] {
+            let mut stream = RedisStream {
+                stream: Arc::new(vec![Bytes::from("key"), Bytes::from(timeout)]),
+                cursor: 0,
+            };
+            let bzpopmin = Bzpopmin::parse_stream(&mut stream).unwrap();
+            let err = block_deadline(bzpopmin.timeout).unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                "ERR timeout is not a float or out of range"
+            );
+        }
+    }
+}
@@ -9,16 +9,12 @@ use tokio::time::Instant;
 
 use crate::command::macros::Symbol;
 use crate::command::{SymbolBlock, SymbolStreams};
-use crate::database::StreamQuery;
+use crate::database::{DbStreamAddError, StreamQuery};
 use crate::id::Id;
 use crate::redis::RedisError;
 use crate::redis_stream::ParseStream;
 use crate::resp::NullArray;
-use crate::{
-    command::AsyncCommand,
-    id::WildcardID,
-    resp::{RedisWrite, RespType},
-};
+use crate::{command::AsyncCommand, id::WildcardID, resp::RedisWrite};
 
 #[derive(RedisCommand)]
 #[redis_command(
@@ -44,7 +40,10 @@ impl AsyncCommand for Xadd {
             .db
             .add_stream(self.key.clone(), self.id, self.values.clone())
             .await
-            .map_err(|err| RedisError::Other(err.to_string()))?;
+            .map_err(|err| match err {
+                DbStreamAddError::Database(err) => err.into(),
+                err => RedisError::Other(err.to_string()),
+            })?;
         id.write_to_buf(buf);
         Ok(())
     }
@@ -105,7 +104,7 @@ impl AsyncCommand for Xrange {
         } else {
             None
         };
-        let values = ctx.app_data.db.range_stream(&self.key, start, end).await;
+        let values = ctx.app_data.db.range_stream(&self.key, start, end).await?;
         if values.is_empty() {
             NullArray.write_to_buf(buf);
         } else {
@@ -188,16 +187,16 @@ impl AsyncCommand for Xread {
             };
 
             if self.contains_dollar {
-                self.block_read_stream(ctx, buf, timeout).await;
+                self.block_read_stream(ctx, buf, timeout).await?;
             } else {
                 let mut results = vec![];
                 for query in &self.queries {
-                    if let Some(res) = ctx.app_data.db.read_stream(query).await {
+                    if let Some(res) = ctx.app_data.db.read_stream(query).await? {
                         results.push(res);
                     }
                 }
                 if results.is_empty() {
-                    self.block_read_stream(ctx, buf, timeout).await;
+                    self.block_read_stream(ctx, buf, timeout).await?;
                 } else {
                     results.write_to_buf(buf);
                 }
@@ -205,7 +204,7 @@ impl AsyncCommand for Xread {
         } else {
             let mut results = vec![];
             for query in &self.queries {
-                if let Some(res) = ctx.app_data.db.read_stream(query).await {
+                if let Some(res) = ctx.app_data.db.read_stream(query).await? {
                     results.push(res);
                 }
             }
@@ -226,32 +225,22 @@ impl Xread {
         ctx: &crate::context::Context,
         buf: &mut bytes::BytesMut,
         timeout: Option<Instant>,
-    ) {
-        let mut receiver = ctx
+    ) -> Result<(), RedisError> {
+        let receiver = ctx
             .app_data
             .db
-            .block_read_stream(&self.queries, timeout)
+            .block_read_stream(ctx.client_id, &self.queries, timeout)
             .await;
-        let result = if let Some(timeout) = timeout {
-            match tokio::time::timeout_at(timeout, receiver.recv()).await {
-                Ok(result) => result,
-                Err(_) => {
-                    NullArray.write_to_buf(buf);
-                    return;
-                }
-            }
-        } else {
-            receiver.recv().await
-        };
-
-        if let Some(result) = result {
-            vec![result].write_to_buf(buf);
-        } else {
-            let message = "ERR Channel closed";
-            eprintln!("{message}");
-            RespType::simple_error(message).write_to_buf(buf);
-            return;
+        let keys: Vec<Bytes> = self.queries.iter().map(|query| query.key.clone()).collect();
+        let result = ctx
+            .app_data
+            .db
+            .wait_blocked(ctx.client_id, ctx.closed.clone(), &keys, receiver, timeout)
+            .await?;
+        match result {
+            Some(result) => vec![result].write_to_buf(buf),
+            None => NullArray.write_to_buf(buf),
         }
-        receiver.close();
+        Ok(())
     }
 }
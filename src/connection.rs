@@ -7,7 +7,7 @@ use tokio::{
         TcpStream,
         tcp::{OwnedReadHalf, OwnedWriteHalf},
     },
-    sync::RwLock,
+    sync::{RwLock, mpsc, watch},
 };
 use tokio_stream::StreamExt;
 use tokio_util::codec::FramedRead;
@@ -49,31 +49,35 @@ impl Connection {
                 }
             }
         };
+        let (closed_sender, closed) = watch::channel(false);
         let ctx = Context {
             writer: self.writer.clone(),
             transactions: Arc::new(RwLock::new(None)),
             signed_in: Arc::new(RwLock::new(signed_in)),
             master_conn,
             get_ack: Arc::new(RwLock::new(false)),
+            client_id: app_data.next_client_id(),
+            closed,
             app_data,
         };
         let mut reader = self.reader.clone().write_owned().await;
+        // Keep reading while a command blocks, so commands pipelined behind it
+        // are queued up and a client that goes away mid-wait is noticed
+        let (frames, mut commands) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            while let Some(result) = reader.next().await {
+                if frames.send(result).is_err() {
+                    break;
+                }
+            }
+            let _ = closed_sender.send(true);
+        });
         tokio::spawn(async move {
             let ctx = ctx.clone();
-            loop {
-                if let Some(result) = reader.next().await {
-                    let cmd = match result {
-                        Ok(cmd) => cmd,
-                        Err(err) => {
-                            let mut buf = BytesMut::new();
-                            tracing::error!("ERROR {err}");
-                            RespType::simple_error(err).write_to_buf(&mut buf);
-                            let mut writer = ctx.writer.write().await;
-                            writer.write_all(&buf).await.expect("valid read");
-                            continue;
-                        }
-                    };
-                    if let Err(err) = handle_command(ctx.clone(), cmd).await {
+            while let Some(result) = commands.recv().await {
+                let cmd = match result {
+                    Ok(cmd) => cmd,
+                    Err(err) => {
                         let mut buf = BytesMut::new();
                         tracing::error!("ERROR {err}");
                         RespType::simple_error(err).write_to_buf(&mut buf);
@@ -81,6 +85,14 @@ impl Connection {
                         writer.write_all(&buf).await.expect("valid read");
                         continue;
                     }
+                };
+                if let Err(err) = handle_command(ctx.clone(), cmd).await {
+                    let mut buf = BytesMut::new();
+                    tracing::error!("ERROR {err}");
+                    RespType::simple_error(err).write_to_buf(&mut buf);
+                    let mut writer = ctx.writer.write().await;
+                    writer.write_all(&buf).await.expect("valid read");
+                    continue;
                 }
             }
         });
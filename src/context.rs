@@ -1,14 +1,18 @@
-use std::sync::Arc;
+use std::sync::{
+    Arc,
+    atomic::{AtomicU64, Ordering},
+};
 
 use either::Either;
-use tokio::net::tcp::OwnedWriteHalf;
+use tokio::{net::tcp::OwnedWriteHalf, sync::watch};
 
 use crate::{
     ArcLock,
     account::AccountDB,
     command::RedisCommand,
-    database::RedisDatabase,
+    database::{ClientId, RedisDatabase},
     replica::{MainServer, Replica, ReplicationInfo},
+    resp::RespType,
 };
 
 pub type ConnWriter = ArcLock<OwnedWriteHalf>;
@@ -20,6 +24,9 @@ pub struct Context {
     pub signed_in: ArcLock<Option<usize>>,
     pub master_conn: bool,
     pub get_ack: ArcLock<bool>,
+    pub client_id: ClientId,
+    /// Flips to `true` once the client disconnects
+    pub closed: watch::Receiver<bool>,
     pub app_data: AppData,
 }
 
@@ -30,6 +37,22 @@ pub struct AppData {
     pub config: ArcLock<Config>,
     pub replication: ArcLock<ReplicationInfo>,
     pub role: Either<MainServer, Replica>,
+    pub client_ids: Arc<AtomicU64>,
+}
+
+impl AppData {
+    /// Hands out ids to connections in the order they arrive, starting at 1
+    pub fn next_client_id(&self) -> ClientId {
+        self.client_ids.fetch_add(1, Ordering::Relaxed) + 1
+    }
+    /// Sends `command` to the replicas in place of the one that ran, for
+    /// writes whose effect can't be replayed as is
+    pub async fn propagate(&self, command: RespType) {
+        if let Either::Left(main) = &self.role {
+            *main.need_offset.write().await = true;
+            main.write_to_replicas(command).await;
+        }
+    }
 }
 
 #[derive(Default, Clone)]
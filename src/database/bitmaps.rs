@@ -0,0 +1,387 @@
+use bytes::Bytes;
+use either::Either;
+
+use crate::database::{
+    DatabaseError, DatabaseValue, RedisDatabase, RedisValue, string_bytes, string_value,
+};
+
+/// Whether `BITCOUNT`/`BITPOS` ranges count bytes or bits
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BitUnit {
+    #[default]
+    Byte,
+    Bit,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BitRange {
+    pub start: i64,
+    pub end: Option<i64>,
+    pub unit: BitUnit,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BitOperation {
+    And,
+    Or,
+    Xor,
+    Not,
+}
+
+/// What `BITFIELD` does when a `SET` or `INCRBY` leaves the integer's range
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BitOverflow {
+    #[default]
+    Wrap,
+    Sat,
+    Fail,
+}
+
+/// An integer type such as `i5` or `u8`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BitFieldType {
+    pub signed: bool,
+    pub bits: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BitFieldOp {
+    Get(BitFieldType, usize),
+    Set(BitFieldType, usize, i64),
+    Incrby(BitFieldType, usize, i64),
+    Overflow(BitOverflow),
+}
+
+impl BitFieldType {
+    fn range(&self) -> (i128, i128) {
+        if self.signed {
+            (-(1 << (self.bits - 1)), (1 << (self.bits - 1)) - 1)
+        } else {
+            (0, (1 << self.bits) - 1)
+        }
+    }
+    /// Fits `value` into the type, `None` meaning the operation fails
+    fn overflow(&self, value: i128, overflow: BitOverflow) -> Option<i64> {
+        let (min, max) = self.range();
+        if (min..=max).contains(&value) {
+            return Some(value as i64);
+        }
+        match overflow {
+            BitOverflow::Wrap => {
+                let modulo = 1i128 << self.bits;
+                let mut wrapped = value.rem_euclid(modulo);
+                if wrapped > max {
+                    wrapped -= modulo;
+                }
+                Some(wrapped as i64)
+            }
+            BitOverflow::Sat => Some(value.clamp(min, max) as i64),
+            BitOverflow::Fail => None,
+        }
+    }
+    fn read(&self, bytes: &[u8], offset: usize) -> i64 {
+        let mut raw = 0u64;
+        for bit in offset..offset + self.bits as usize {
+            raw = (raw << 1) | get_bit(bytes, bit) as u64;
+        }
+        if self.signed && self.bits < 64 && raw >> (self.bits - 1) & 1 == 1 {
+            (raw as i64) - (1i64 << self.bits)
+        } else {
+            raw as i64
+        }
+    }
+    fn write(&self, bytes: &mut Vec<u8>, offset: usize, value: i64) {
+        let raw = value as u64;
+        for idx in 0..self.bits as usize {
+            let bit = raw >> (self.bits as usize - 1 - idx) & 1;
+            set_bit(bytes, offset + idx, bit == 1);
+        }
+    }
+}
+
+/// Bits are numbered from the most significant bit of the first byte
+pub fn get_bit(bytes: &[u8], offset: usize) -> u8 {
+    bytes
+        .get(offset / 8)
+        .map(|byte| byte >> (7 - offset % 8) & 1)
+        .unwrap_or(0)
+}
+
+/// Sets a bit, growing `bytes` with zeroes as needed. Returns the old bit.
+pub fn set_bit(bytes: &mut Vec<u8>, offset: usize, bit: bool) -> u8 {
+    let idx = offset / 8;
+    if bytes.len() <= idx {
+        bytes.resize(idx + 1, 0);
+    }
+    let mask = 1 << (7 - offset % 8);
+    let old = (bytes[idx] & mask != 0) as u8;
+    if bit {
+        bytes[idx] |= mask;
+    } else {
+        bytes[idx] &= !mask;
+    }
+    old
+}
+
+/// Resolves an inclusive range with negative indexes counting from the end,
+/// clamped to `len`
+fn clamp_range(start: i64, end: i64, len: usize) -> Option<(usize, usize)> {
+    let len = len as i64;
+    let start = if start < 0 {
+        (start + len).max(0)
+    } else {
+        start
+    };
+    let end = if end < 0 {
+        (end + len).max(0)
+    } else {
+        end.min(len - 1)
+    };
+    if len == 0 || start > end {
+        None
+    } else {
+        Some((start as usize, end as usize))
+    }
+}
+
+/// The bit range covered by `range`, in bits
+fn bit_span(bytes: &[u8], range: Option<BitRange>) -> Option<(usize, usize)> {
+    let Some(range) = range else {
+        return clamp_range(0, -1, bytes.len() * 8);
+    };
+    let end = range.end.unwrap_or(-1);
+    match range.unit {
+        BitUnit::Byte => {
+            clamp_range(range.start, end, bytes.len()).map(|(start, end)| (start * 8, end * 8 + 7))
+        }
+        BitUnit::Bit => clamp_range(range.start, end, bytes.len() * 8),
+    }
+}
+
+pub fn bit_count(bytes: &[u8], range: Option<BitRange>) -> usize {
+    let Some((start, end)) = bit_span(bytes, range) else {
+        return 0;
+    };
+    // Whole bytes at once, the partial ones at the edges bit by bit
+    let first_byte = start.div_ceil(8);
+    let last_byte = (end + 1) / 8;
+    if first_byte >= last_byte {
+        return (start..=end)
+            .filter(|bit| get_bit(bytes, *bit) == 1)
+            .count();
+    }
+    let head = (start..first_byte * 8)
+        .filter(|bit| get_bit(bytes, *bit) == 1)
+        .count();
+    let tail = (last_byte * 8..=end)
+        .filter(|bit| get_bit(bytes, *bit) == 1)
+        .count();
+    let middle: u32 = bytes[first_byte..last_byte]
+        .iter()
+        .map(|byte| byte.count_ones())
+        .sum();
+    head + middle as usize + tail
+}
+
+/// The first bit set to `bit` within `range`. Looking for a clear bit without
+/// an explicit end treats the string as padded with zeroes, like Redis.
+pub fn bit_pos(bytes: &[u8], bit: bool, range: Option<BitRange>) -> i64 {
+    let Some((start, end)) = bit_span(bytes, range) else {
+        return -1;
+    };
+    let wanted = bit as u8;
+    if let Some(pos) = (start..=end).find(|pos| get_bit(bytes, *pos) == wanted) {
+        return pos as i64;
+    }
+    if !bit && range.is_none_or(|range| range.end.is_none()) {
+        (end + 1) as i64
+    } else {
+        -1
+    }
+}
+
+impl RedisDatabase {
+    pub async fn get_bit(&self, key: &Bytes, offset: usize) -> Result<u8, DatabaseError> {
+        let keyspace = self.keyspace.read().await;
+        Ok(keyspace
+            .get_as(key)?
+            .map(|value| get_bit(&string_bytes(value), offset))
+            .unwrap_or(0))
+    }
+
+    pub async fn set_bit(
+        &self,
+        key: &Bytes,
+        offset: usize,
+        bit: bool,
+    ) -> Result<u8, DatabaseError> {
+        let mut keyspace = self.keyspace.write().await;
+        let value = keyspace.get_or_insert_with_as(key, || Either::Left(Bytes::new()))?;
+        let mut bytes = string_bytes(value).to_vec();
+        let old = set_bit(&mut bytes, offset, bit);
+        *value = string_value(Bytes::from(bytes));
+        Ok(old)
+    }
+
+    pub async fn bit_count(
+        &self,
+        key: &Bytes,
+        range: Option<BitRange>,
+    ) -> Result<usize, DatabaseError> {
+        let keyspace = self.keyspace.read().await;
+        Ok(keyspace
+            .get_as(key)?
+            .map(|value| bit_count(&string_bytes(value), range))
+            .unwrap_or(0))
+    }
+
+    pub async fn bit_pos(
+        &self,
+        key: &Bytes,
+        bit: bool,
+        range: Option<BitRange>,
+    ) -> Result<i64, DatabaseError> {
+        let keyspace = self.keyspace.read().await;
+        match keyspace.get_as(key)? {
+            Some(value) => Ok(bit_pos(&string_bytes(value), bit, range)),
+            // A missing key is an empty string of zeroes
+            None => Ok(if bit { -1 } else { 0 }),
+        }
+    }
+
+    /// Stores the result of `operation` over `keys` in `destination`,
+    /// deleting it if the result is empty. Returns the result's length.
+    pub async fn bit_op(
+        &self,
+        operation: BitOperation,
+        destination: &Bytes,
+        keys: &[Bytes],
+    ) -> Result<usize, DatabaseError> {
+        let mut keyspace = self.keyspace.write().await;
+        let mut sources = Vec::with_capacity(keys.len());
+        for key in keys {
+            sources.push(keyspace.get_as(key)?.map(string_bytes).unwrap_or_default());
+        }
+        let len = sources.iter().map(|source| source.len()).max().unwrap_or(0);
+        let result: Vec<u8> = (0..len)
+            .map(|idx| {
+                let mut bytes = sources
+                    .iter()
+                    .map(|source| source.get(idx).copied().unwrap_or(0));
+                let first = bytes.next().unwrap_or(0);
+                match operation {
+                    BitOperation::And => bytes.fold(first, |acc, byte| acc & byte),
+                    BitOperation::Or => bytes.fold(first, |acc, byte| acc | byte),
+                    BitOperation::Xor => bytes.fold(first, |acc, byte| acc ^ byte),
+                    BitOperation::Not => !first,
+                }
+            })
+            .collect();
+        if result.is_empty() {
+            keyspace.remove(destination);
+        } else {
+            keyspace.insert(
+                destination.clone(),
+                DatabaseValue::new(RedisValue::String(string_value(Bytes::from(result))), None),
+            );
+        }
+        Ok(len)
+    }
+
+    /// Runs `BITFIELD` subcommands in order. `SET` replies with the old value,
+    /// `INCRBY` with the new one and `None` when `OVERFLOW FAIL` stopped it.
+    pub async fn bit_field(
+        &self,
+        key: &Bytes,
+        ops: &[BitFieldOp],
+    ) -> Result<Vec<Option<i64>>, DatabaseError> {
+        let mut keyspace = self.keyspace.write().await;
+        let writes = ops
+            .iter()
+            .any(|op| matches!(op, BitFieldOp::Set(..) | BitFieldOp::Incrby(..)));
+        let mut bytes = keyspace
+            .get_as(key)?
+            .map(|value| string_bytes(value).to_vec())
+            .unwrap_or_default();
+        let mut overflow = BitOverflow::default();
+        let mut replies = vec![];
+        for op in ops {
+            match *op {
+                BitFieldOp::Get(ty, offset) => replies.push(Some(ty.read(&bytes, offset))),
+                BitFieldOp::Set(ty, offset, value) => {
+                    let old = ty.read(&bytes, offset);
+                    match ty.overflow(value as i128, overflow) {
+                        Some(value) => {
+                            ty.write(&mut bytes, offset, value);
+                            replies.push(Some(old));
+                        }
+                        None => replies.push(None),
+                    }
+                }
+                BitFieldOp::Incrby(ty, offset, increment) => {
+                    let old = ty.read(&bytes, offset);
+                    let value = ty.overflow(old as i128 + increment as i128, overflow);
+                    if let Some(value) = value {
+                        ty.write(&mut bytes, offset, value);
+                    }
+                    replies.push(value);
+                }
+                BitFieldOp::Overflow(behaviour) => overflow = behaviour,
+            }
+        }
+        if writes {
+            let value = keyspace.get_or_insert_with_as(key, || Either::Left(Bytes::new()))?;
+            *value = string_value(Bytes::from(bytes));
+        }
+        Ok(replies)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bit_count_and_pos() {
+        let bytes = b"foobar";
+        assert_eq!(bit_count(bytes, None), 26);
+        let range = |start, end, unit| Some(BitRange { start, end, unit });
+        assert_eq!(bit_count(bytes, range(1, Some(1), BitUnit::Byte)), 6);
+        assert_eq!(bit_count(bytes, range(5, Some(30), BitUnit::Bit)), 17);
+
+        let bytes = [0xff, 0xf0, 0x00];
+        assert_eq!(bit_pos(&bytes, false, None), 12);
+        assert_eq!(bit_pos(&bytes, true, range(2, Some(-1), BitUnit::Byte)), -1);
+        assert_eq!(bit_pos(&[0xff], false, None), 8);
+        assert_eq!(
+            bit_pos(&[0xff], false, range(0, Some(-1), BitUnit::Byte)),
+            -1
+        );
+        assert_eq!(
+            bit_pos(&[0x00, 0x01], true, range(7, Some(15), BitUnit::Bit)),
+            15
+        );
+    }
+
+    #[test]
+    fn test_bit_field_overflow() {
+        let u8 = BitFieldType {
+            signed: false,
+            bits: 8,
+        };
+        let i8 = BitFieldType {
+            signed: true,
+            bits: 8,
+        };
+        assert_eq!(u8.overflow(256, BitOverflow::Wrap), Some(0));
+        assert_eq!(u8.overflow(-1, BitOverflow::Sat), Some(0));
+        assert_eq!(i8.overflow(128, BitOverflow::Wrap), Some(-128));
+        assert_eq!(i8.overflow(200, BitOverflow::Sat), Some(127));
+        assert_eq!(i8.overflow(200, BitOverflow::Fail), None);
+
+        let mut bytes = vec![];
+        i8.write(&mut bytes, 4, -3);
+        assert_eq!(i8.read(&bytes, 4), -3);
+        assert_eq!(u8.read(&bytes, 4), 253);
+    }
+}
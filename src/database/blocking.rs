@@ -0,0 +1,215 @@
+use std::{collections::VecDeque, sync::Arc};
+
+use bytes::Bytes;
+use hashbrown::HashMap;
+use tokio::{
+    sync::{mpsc, oneshot, watch},
+    time::Instant,
+};
+
+use crate::database::{DatabaseError, RedisDatabase};
+
+/// Identifies a connection, as handed out by `CLIENT ID`
+pub type ClientId = u64;
+
+/// How `CLIENT UNBLOCK` ends a client's wait
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnblockReason {
+    /// As if the wait timed out
+    Timeout,
+    /// With an `-UNBLOCKED` error
+    Error,
+}
+
+/// A client waiting on one key
+pub struct Blocker<T> {
+    pub client: ClientId,
+    pub waiter: T,
+    pub timeout: Option<Instant>,
+}
+
+impl<T> Blocker<T> {
+    pub fn timed_out(&self) -> bool {
+        if let Some(timeout) = self.timeout {
+            Instant::now() > timeout
+        } else {
+            false
+        }
+    }
+}
+
+/// The clients blocked on keys of one type, queued per key in the order
+/// they blocked
+pub struct WaitQueues<T> {
+    queues: HashMap<Bytes, VecDeque<Blocker<T>>>,
+}
+
+impl<T> Default for WaitQueues<T> {
+    fn default() -> Self {
+        Self {
+            queues: HashMap::new(),
+        }
+    }
+}
+
+impl<T> WaitQueues<T> {
+    pub fn push(&mut self, key: Bytes, blocker: Blocker<T>) {
+        self.queues.entry(key).or_default().push_back(blocker);
+    }
+    /// Takes the client that has waited on `key` the longest
+    pub fn pop_front(&mut self, key: &Bytes) -> Option<Blocker<T>> {
+        let queue = self.queues.get_mut(key)?;
+        let blocker = queue.pop_front();
+        if queue.is_empty() {
+            self.queues.remove(key);
+        }
+        blocker
+    }
+    pub fn retain(&mut self, key: &Bytes, f: impl FnMut(&Blocker<T>) -> bool) {
+        if let Some(queue) = self.queues.get_mut(key) {
+            queue.retain(f);
+            if queue.is_empty() {
+                self.queues.remove(key);
+            }
+        }
+    }
+    pub fn remove_client(&mut self, key: &Bytes, client: ClientId) {
+        self.retain(key, |blocker| blocker.client != client);
+    }
+}
+
+pub type Blocklist<T> = Arc<tokio::sync::Mutex<WaitQueues<T>>>;
+
+/// Every client in the middle of a blocking command, with a way to wake it
+/// for `CLIENT UNBLOCK`
+pub type BlockedClients = Arc<std::sync::Mutex<HashMap<ClientId, oneshot::Sender<UnblockReason>>>>;
+
+/// Where a blocked client's reply comes from
+pub trait BlockedReply {
+    type Output;
+    fn recv(&mut self) -> impl Future<Output = Option<Self::Output>> + Send;
+    /// Stops anything more being sent, handing back what already was
+    fn close_and_take(&mut self) -> Option<Self::Output>;
+}
+
+impl<T: Send> BlockedReply for oneshot::Receiver<T> {
+    type Output = T;
+    async fn recv(&mut self) -> Option<T> {
+        self.await.ok()
+    }
+    fn close_and_take(&mut self) -> Option<T> {
+        self.close();
+        self.try_recv().ok()
+    }
+}
+
+impl<T: Send> BlockedReply for mpsc::Receiver<T> {
+    type Output = T;
+    async fn recv(&mut self) -> Option<T> {
+        mpsc::Receiver::recv(self).await
+    }
+    fn close_and_take(&mut self) -> Option<T> {
+        self.close();
+        self.try_recv().ok()
+    }
+}
+
+impl RedisDatabase {
+    /// Waits for a client queued on `keys` to be served, giving up once
+    /// `timeout` passes, the client is unblocked or its connection closes.
+    /// The client is then taken off the queues of every key either way.
+    pub async fn wait_blocked<R: BlockedReply>(
+        &self,
+        client: ClientId,
+        mut closed: watch::Receiver<bool>,
+        keys: &[Bytes],
+        mut reply: R,
+        timeout: Option<Instant>,
+    ) -> Result<Option<R::Output>, DatabaseError> {
+        let (unblock, unblocked) = oneshot::channel();
+        self.blocked_clients.lock().unwrap().insert(client, unblock);
+        let deadline = async {
+            match timeout {
+                Some(timeout) => tokio::time::sleep_until(timeout).await,
+                None => std::future::pending().await,
+            }
+        };
+        let outcome = tokio::select! {
+            value = reply.recv() => Ok(value),
+            _ = deadline => Ok(None),
+            _ = closed.wait_for(|closed| *closed) => Ok(None),
+            reason = unblocked => match reason {
+                Ok(UnblockReason::Error) => Err(DatabaseError::Unblocked),
+                _ => Ok(None),
+            },
+        };
+        self.blocked_clients.lock().unwrap().remove(&client);
+        self.remove_blocked(client, keys).await;
+        // The reply may have been sent just as the wait gave up, in which case
+        // it was already taken from the key and must not be dropped
+        if !matches!(outcome, Ok(Some(_)))
+            && let Some(value) = reply.close_and_take()
+        {
+            return Ok(Some(value));
+        }
+        outcome
+    }
+    async fn remove_blocked(&self, client: ClientId, keys: &[Bytes]) {
+        {
+            let mut blocklist = self.list_blocklist.lock().await;
+            for key in keys {
+                blocklist.remove_client(key, client);
+            }
+        }
+        {
+            let mut blocklist = self.stream_blocklist.lock().await;
+            for key in keys {
+                blocklist.remove_client(key, client);
+            }
+        }
+        let mut blocklist = self.zset_blocklist.lock().await;
+        for key in keys {
+            blocklist.remove_client(key, client);
+        }
+    }
+    /// Wakes `client` if it's blocked, returning whether it was
+    pub fn unblock_client(&self, client: ClientId, reason: UnblockReason) -> bool {
+        let unblock = self.blocked_clients.lock().unwrap().remove(&client);
+        unblock.is_some_and(|unblock| unblock.send(reason).is_ok())
+    }
+    /// The `INFO clients` section
+    pub fn clients_info(&self) -> String {
+        let blocked = self.blocked_clients.lock().unwrap().len();
+        format!("blocked_clients:{blocked}\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wait_queues() {
+        let key = Bytes::from("key");
+        let mut queues = WaitQueues::default();
+        for client in 1..=3 {
+            let blocker = Blocker {
+                client,
+                waiter: (),
+                timeout: None,
+            };
+            queues.push(key.clone(), blocker);
+        }
+        queues.remove_client(&key, 2);
+        assert_eq!(
+            queues.pop_front(&key).map(|blocker| blocker.client),
+            Some(1)
+        );
+        assert_eq!(
+            queues.pop_front(&key).map(|blocker| blocker.client),
+            Some(3)
+        );
+        assert!(queues.pop_front(&key).is_none());
+        assert!(queues.queues.is_empty());
+    }
+}
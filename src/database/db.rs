@@ -1,76 +1,37 @@
-use std::{collections::VecDeque, sync::Arc};
-
 use bytes::Bytes;
 use either::Either;
-use hashbrown::HashMap;
-use indexmap::IndexMap;
-use tokio::{
-    sync::{RwLock, mpsc, oneshot},
-    time::Instant,
-};
 
 use crate::{
-    ArcLock, Pair,
-    command::macros::Symbol,
-    database::{BlpopResponse, DatabaseValue, ReadStreamResult, channels::ChannelDB},
-    id::Id,
+    ArcLock,
+    database::{
+        BlockedClients, Blocklist, DatabaseError, Keyspace, ListWaiter, SetOptions, StreamWaiter,
+        ZsetWaiter, channels::ChannelDB,
+    },
     rdb::RdbKeyValue,
 };
 
-pub type DB<T> = Arc<RwLock<hashbrown::HashMap<Bytes, T>>>;
-
-pub type Blocklist<T> = Arc<tokio::sync::Mutex<HashMap<Bytes, T>>>;
-
-pub struct Blocker<T> {
-    pub sender: T,
-    pub timeout: Option<Instant>,
-}
-
-impl<T> Blocker<T> {
-    pub fn timed_out(&self) -> bool {
-        if let Some(timeout) = self.timeout {
-            Instant::now() > timeout
-        } else {
-            false
-        }
-    }
-}
-
-type StreamBlocklist =
-    Blocklist<Vec<Pair<Either<Id, Symbol!("$")>, Blocker<mpsc::Sender<ReadStreamResult>>>>>;
-
 #[derive(Default)]
 pub struct RedisDatabase {
-    pub(crate) key_value: DB<DatabaseValue>,
-    pub(crate) streams: DB<IndexMap<Id, HashMap<Bytes, Bytes>>>,
-    pub(crate) lists: DB<VecDeque<Bytes>>,
-    pub(crate) sets: DB<IndexMap<Bytes, f64>>,
+    pub(crate) keyspace: ArcLock<Keyspace>,
     pub(crate) channels: ArcLock<ChannelDB>,
-    pub(crate) list_blocklist: Blocklist<Vec<Blocker<oneshot::Sender<BlpopResponse>>>>,
-    pub(crate) stream_blocklist: StreamBlocklist,
+    pub(crate) list_blocklist: Blocklist<ListWaiter>,
+    pub(crate) stream_blocklist: Blocklist<StreamWaiter>,
+    pub(crate) zset_blocklist: Blocklist<ZsetWaiter>,
+    pub(crate) blocked_clients: BlockedClients,
 }
 
 impl RedisDatabase {
     pub async fn db_type(&self, key: &Bytes) -> Bytes {
-        let value = if self.key_value.read().await.contains_key(key) {
-            "string"
-        } else if self.streams.read().await.contains_key(key) {
-            "stream"
-        } else if self.lists.read().await.contains_key(key) {
-            "list"
-        } else {
-            "none"
-        };
+        let keyspace = self.keyspace.read().await;
+        let value = keyspace
+            .get(key)
+            .map(|value| value.value.type_name())
+            .unwrap_or("none");
         Bytes::from_static(value.as_bytes())
     }
     pub async fn keys(&self, filter: &Bytes) -> Vec<Bytes> {
-        let key_value = self.key_value.read().await;
-        let stream = self.streams.read().await;
-        let list = self.lists.read().await;
-        let kv_keys = key_value.keys();
-        let stream_keys = stream.keys();
-        let list_keys = list.keys();
-        let all_keys = kv_keys.chain(stream_keys).chain(list_keys);
+        let keyspace = self.keyspace.read().await;
+        let all_keys = keyspace.keys();
         if filter.to_ascii_lowercase().as_slice() == b"*" {
             let mut out: Vec<Bytes> = all_keys.cloned().collect();
             out.sort();
@@ -84,16 +45,105 @@ impl RedisDatabase {
             out
         }
     }
+    pub async fn count_existing(&self, keys: &[Bytes]) -> usize {
+        let keyspace = self.keyspace.read().await;
+        keys.iter().filter(|key| keyspace.contains_key(key)).count()
+    }
+    pub async fn delete_keys(&self, keys: &[Bytes]) -> usize {
+        let deleted: Vec<Bytes> = {
+            let mut keyspace = self.keyspace.write().await;
+            keys.iter()
+                .filter(|key| keyspace.remove(key).is_some())
+                .cloned()
+                .collect()
+        };
+        for key in &deleted {
+            self.signal_key_deleted(key).await;
+        }
+        deleted.len()
+    }
+    /// Moves `key` to `new_key`, keeping its TTL. Returns `false` when `nx` is
+    /// set and `new_key` already exists.
+    pub async fn rename_key(
+        &self,
+        key: &Bytes,
+        new_key: &Bytes,
+        nx: bool,
+    ) -> Result<bool, DatabaseError> {
+        {
+            let mut keyspace = self.keyspace.write().await;
+            if !keyspace.contains_key(key) {
+                return Err(DatabaseError::NoSuchKey);
+            }
+            if key == new_key {
+                return Ok(!nx);
+            }
+            if nx && keyspace.contains_key(new_key) {
+                return Ok(false);
+            }
+            let value = keyspace.remove(key).ok_or(DatabaseError::NoSuchKey)?;
+            keyspace.insert(new_key.clone(), value);
+        }
+        self.signal_key_deleted(key).await;
+        self.signal_key_ready(new_key).await;
+        Ok(true)
+    }
+    /// Copies `source` to `destination`, keeping its TTL. Returns `false` when
+    /// `source` is missing or `destination` exists and `replace` isn't set.
+    pub async fn copy_key(
+        &self,
+        source: &Bytes,
+        destination: &Bytes,
+        replace: bool,
+    ) -> Result<bool, DatabaseError> {
+        if source == destination {
+            return Err(DatabaseError::SameObject);
+        }
+        {
+            let mut keyspace = self.keyspace.write().await;
+            let Some(value) = keyspace.get(source).cloned() else {
+                return Ok(false);
+            };
+            if !replace && keyspace.contains_key(destination) {
+                return Ok(false);
+            }
+            keyspace.insert(destination.clone(), value);
+        }
+        self.signal_key_ready(destination).await;
+        Ok(true)
+    }
+    /// Serves any clients blocked on `key` after a value was moved into it
+    pub async fn signal_key_ready(&self, key: &Bytes) {
+        self.handle_list_blocklist(key).await;
+        self.handle_stream_ready(key).await;
+        self.handle_zset_blocklist(key).await;
+    }
+    /// Drops waiters on a removed key that have already timed out or gone away;
+    /// live waiters stay blocked until the key is created again
+    pub async fn signal_key_deleted(&self, key: &Bytes) {
+        self.list_blocklist.lock().await.retain(key, |blocker| {
+            !blocker.timed_out() && !blocker.waiter.is_closed()
+        });
+        self.stream_blocklist.lock().await.retain(key, |blocker| {
+            !blocker.timed_out() && !blocker.waiter.is_closed()
+        });
+        self.zset_blocklist.lock().await.retain(key, |blocker| {
+            !blocker.timed_out() && !blocker.waiter.is_closed()
+        });
+    }
     pub async fn from_rdb(keys: impl IntoIterator<Item = RdbKeyValue>) -> Self {
         let db = RedisDatabase::default();
         for kv in keys.into_iter() {
-            db.set_kv(
-                kv.key().clone(),
-                kv.value().clone(),
-                kv.expiry().map(Either::Right),
-                false,
-            )
-            .await;
+            let options = SetOptions {
+                expiry: kv.expiry().map(Either::Right),
+                ..Default::default()
+            };
+            if let Err(err) = db
+                .set_kv(kv.key().clone(), kv.value().clone(), options)
+                .await
+            {
+                tracing::warn!("failed to load key from rdb: {err}");
+            }
         }
         db
     }
@@ -0,0 +1,221 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use bytes::Bytes;
+use either::Either;
+use tokio::time::Instant;
+
+use crate::database::{DatabaseValue, RedisDatabase};
+
+/// Which existing TTLs an `EXPIRE` style command is allowed to replace
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ExpireCondition {
+    #[default]
+    Always,
+    /// Only when the key has no expiry
+    Nx,
+    /// Only when the key already has an expiry
+    Xx,
+    /// Only when the new expiry is later than the current one
+    Gt,
+    /// Only when the new expiry is earlier than the current one
+    Lt,
+    /// `XX` with `LT`: like [`ExpireCondition::Lt`] but a key without an
+    /// expiry is left alone
+    XxLt,
+}
+
+impl ExpireCondition {
+    /// Whether a key or field with the `current` deadline may get `expiry`.
+    /// No deadline counts as an infinite one.
+    pub fn allows(self, current: Option<SystemTime>, expiry: SystemTime) -> bool {
+        match (self, current) {
+            (ExpireCondition::Always, _) => true,
+            (ExpireCondition::Nx, current) => current.is_none(),
+            (ExpireCondition::Xx, current) => current.is_some(),
+            (ExpireCondition::Gt, Some(current)) => expiry > current,
+            (ExpireCondition::Gt, None) => false,
+            (ExpireCondition::Lt, Some(current)) => expiry < current,
+            (ExpireCondition::Lt, None) => true,
+            (ExpireCondition::XxLt, Some(current)) => expiry < current,
+            (ExpireCondition::XxLt, None) => false,
+        }
+    }
+}
+
+/// The expiry state of a key as reported by `TTL` and friends
+pub enum KeyTtl {
+    Missing,
+    Persistent,
+    ExpiresAt(SystemTime),
+}
+
+impl KeyTtl {
+    /// Redis replies `-2` for a missing key and `-1` for a key without a TTL,
+    /// otherwise `reply` turns the deadline into the wanted unit
+    pub fn reply(&self, reply: impl Fn(SystemTime) -> i64) -> i64 {
+        match self {
+            KeyTtl::Missing => -2,
+            KeyTtl::Persistent => -1,
+            KeyTtl::ExpiresAt(time) => reply(*time),
+        }
+    }
+}
+
+pub fn unix_millis(time: SystemTime) -> i64 {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(duration) => duration.as_millis() as i64,
+        Err(err) => -(err.duration().as_millis() as i64),
+    }
+}
+
+impl DatabaseValue {
+    /// The wall clock deadline of the value, converting monotonic expiries
+    pub fn expires_at(&self) -> Option<SystemTime> {
+        self.expiry.map(|expiry| match expiry {
+            Either::Left(instant) => {
+                let now = Instant::now();
+                if instant >= now {
+                    SystemTime::now() + (instant - now)
+                } else {
+                    SystemTime::now() - (now - instant)
+                }
+            }
+            Either::Right(time) => time,
+        })
+    }
+}
+
+/// What a run of [`RedisDatabase::active_expire_cycle`] reclaimed
+#[derive(Debug, Default)]
+pub struct ExpireCycle {
+    pub keys: Vec<Bytes>,
+    /// Expired fields of hashes that still have live ones
+    pub fields: Vec<(Bytes, Vec<Bytes>)>,
+    pub time_cap_reached: bool,
+}
+
+impl RedisDatabase {
+    /// Applies `expiry` to `key` if `condition` allows it. A deadline that has
+    /// already passed deletes the key straight away.
+    pub async fn set_expiry(
+        &self,
+        key: &Bytes,
+        expiry: SystemTime,
+        condition: ExpireCondition,
+    ) -> bool {
+        let deleted = {
+            let mut keyspace = self.keyspace.write().await;
+            let Some(value) = keyspace.get_mut(key) else {
+                return false;
+            };
+            if !condition.allows(value.expires_at(), expiry) {
+                return false;
+            }
+            if expiry <= SystemTime::now() {
+                keyspace.remove(key);
+                true
+            } else {
+                keyspace.set_expiry(key, Some(Either::Right(expiry)));
+                false
+            }
+        };
+        if deleted {
+            self.signal_key_deleted(key).await;
+        }
+        true
+    }
+    pub async fn key_ttl(&self, key: &Bytes) -> KeyTtl {
+        let keyspace = self.keyspace.read().await;
+        match keyspace.get(key) {
+            Some(value) => match value.expires_at() {
+                Some(time) => KeyTtl::ExpiresAt(time),
+                None => KeyTtl::Persistent,
+            },
+            None => KeyTtl::Missing,
+        }
+    }
+    pub async fn persist(&self, key: &Bytes) -> bool {
+        let mut keyspace = self.keyspace.write().await;
+        if let Some(value) = keyspace.get_mut(key) {
+            value.expiry.take().is_some()
+        } else {
+            false
+        }
+    }
+
+    /// One run of the active expire cycle, modelled on Redis: keep sampling
+    /// batches of keys with a TTL while more than a tenth of each batch turns
+    /// out to be expired, stopping early once `budget` is used up. Hashes
+    /// sampled along the way lose their expired fields.
+    pub async fn active_expire_cycle(&self, budget: Duration) -> ExpireCycle {
+        const SAMPLE_SIZE: usize = 20;
+        const ACCEPTABLE_STALE_PERC: usize = 10;
+
+        let start = Instant::now();
+        let mut cycle = ExpireCycle::default();
+        let mut sampled = 0;
+        let mut stale = 0;
+        loop {
+            let batch = self.keyspace.write().await.expire_sample(SAMPLE_SIZE);
+            sampled += batch.sampled;
+            stale += batch.stale();
+            let batch_stale_perc = (batch.stale() * 100)
+                .checked_div(batch.sampled)
+                .unwrap_or(0);
+            cycle.keys.extend(batch.keys);
+            cycle.fields.extend(batch.fields);
+            if batch_stale_perc <= ACCEPTABLE_STALE_PERC {
+                break;
+            }
+            if start.elapsed() > budget {
+                cycle.time_cap_reached = true;
+                break;
+            }
+            tokio::task::yield_now().await;
+        }
+        {
+            let mut keyspace = self.keyspace.write().await;
+            let stats = &mut keyspace.stats;
+            let current_perc = if sampled == 0 {
+                0.0
+            } else {
+                stale as f64 / sampled as f64
+            };
+            stats.expired_stale_perc = current_perc * 0.05 + stats.expired_stale_perc * 0.95;
+            stats.cycle_cpu_milliseconds += start.elapsed().as_millis() as u64;
+            if cycle.time_cap_reached {
+                stats.time_cap_reached_count += 1;
+            }
+        }
+        for key in &cycle.keys {
+            self.signal_key_deleted(key).await;
+        }
+        cycle
+    }
+
+    /// The expiry counters of `INFO stats`
+    pub async fn stats_info(&self) -> String {
+        let stats = self.keyspace.read().await.stats;
+        format!(
+            "expired_keys:{}\nexpired_stale_perc:{:.2}\nexpired_time_cap_reached_count:{}\nexpire_cycle_cpu_milliseconds:{}\n",
+            stats.expired_keys,
+            stats.expired_stale_perc * 100.0,
+            stats.time_cap_reached_count,
+            stats.cycle_cpu_milliseconds,
+        )
+    }
+
+    /// The `INFO keyspace` line for the only database
+    pub async fn keyspace_info(&self) -> String {
+        let keyspace = self.keyspace.read().await;
+        let keys = keyspace.key_count();
+        if keys == 0 {
+            String::new()
+        } else {
+            format!(
+                "db0:keys={keys},expires={},avg_ttl=0\n",
+                keyspace.volatile_count()
+            )
+        }
+    }
+}
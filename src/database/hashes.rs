@@ -0,0 +1,486 @@
+use std::time::SystemTime;
+
+use bytes::Bytes;
+use hashbrown::HashMap;
+use indexmap::IndexMap;
+use rand::Rng;
+
+use crate::database::{
+    DatabaseError, ExpireCondition, KeyTtl, Keyspace, RedisDatabase, check_rand_count,
+    format_float, parse_float, parse_int,
+};
+
+/// The fields of a hash along with the deadlines of those given a TTL.
+///
+/// Expired fields stay around until they're reclaimed but are never handed
+/// out, the same way the [`Keyspace`] treats keys.
+#[derive(Debug, Clone, Default)]
+pub struct Hash {
+    fields: IndexMap<Bytes, Bytes>,
+    /// Only ever holds fields that are in `fields`
+    expiries: HashMap<Bytes, SystemTime>,
+}
+
+impl Hash {
+    fn is_live(&self, field: &Bytes, now: SystemTime) -> bool {
+        self.expiries.get(field).is_none_or(|expiry| now <= *expiry)
+    }
+    pub fn get(&self, field: &Bytes) -> Option<&Bytes> {
+        self.fields
+            .get(field)
+            .filter(|_| self.is_live(field, SystemTime::now()))
+    }
+    pub fn contains_key(&self, field: &Bytes) -> bool {
+        self.get(field).is_some()
+    }
+    pub fn len(&self) -> usize {
+        if self.expiries.is_empty() {
+            self.fields.len()
+        } else {
+            self.iter().count()
+        }
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    /// The live fields in insertion order
+    pub fn iter(&self) -> impl Iterator<Item = (&Bytes, &Bytes)> {
+        let now = SystemTime::now();
+        self.fields
+            .iter()
+            .filter(move |(field, _)| self.is_live(field, now))
+    }
+    /// Sets the value of `field` and clears its TTL, returning whether the
+    /// field is new
+    pub fn insert(&mut self, field: Bytes, value: Bytes) -> bool {
+        let live = self.is_live(&field, SystemTime::now());
+        self.expiries.remove(&field);
+        self.fields.insert(field, value).is_none() || !live
+    }
+    /// Sets the value of `field` while keeping its TTL
+    pub fn update(&mut self, field: Bytes, value: Bytes) {
+        if !self.is_live(&field, SystemTime::now()) {
+            self.expiries.remove(&field);
+        }
+        self.fields.insert(field, value);
+    }
+    pub fn remove(&mut self, field: &Bytes) -> Option<Bytes> {
+        let live = self.is_live(field, SystemTime::now());
+        self.expiries.remove(field);
+        self.fields.shift_remove(field).filter(|_| live)
+    }
+    /// The deadline of a live field, `None` if it has no TTL
+    pub fn expires_at(&self, field: &Bytes) -> Option<SystemTime> {
+        self.expiries.get(field).copied()
+    }
+    /// Sets or clears the TTL of a live field, returning `false` if it's missing
+    pub fn set_expiry(&mut self, field: &Bytes, expiry: Option<SystemTime>) -> bool {
+        if !self.contains_key(field) {
+            return false;
+        }
+        match expiry {
+            Some(expiry) => self.expiries.insert(field.clone(), expiry),
+            None => self.expiries.remove(field),
+        };
+        true
+    }
+    pub fn has_field_ttls(&self) -> bool {
+        !self.expiries.is_empty()
+    }
+    /// Whether every field has expired, in which case the key is gone as well
+    pub fn all_expired(&self) -> bool {
+        let now = SystemTime::now();
+        !self.expiries.is_empty()
+            && self.expiries.len() == self.fields.len()
+            && self.expiries.values().all(|expiry| now > *expiry)
+    }
+    /// Drops the expired fields, returning their names
+    pub fn purge_expired(&mut self) -> Vec<Bytes> {
+        let now = SystemTime::now();
+        let expired: Vec<Bytes> = self
+            .expiries
+            .iter()
+            .filter(|(_, expiry)| now > **expiry)
+            .map(|(field, _)| field.clone())
+            .collect();
+        for field in &expired {
+            self.expiries.remove(field);
+            self.fields.shift_remove(field);
+        }
+        expired
+    }
+}
+
+/// Looks the hash up for writing, reclaiming its expired fields first
+fn hash_mut<'a>(
+    keyspace: &'a mut Keyspace,
+    key: &Bytes,
+) -> Result<Option<&'a mut Hash>, DatabaseError> {
+    let mut hash = keyspace.get_as_mut::<Hash>(key)?;
+    if let Some(hash) = hash.as_deref_mut() {
+        hash.purge_expired();
+    }
+    Ok(hash)
+}
+
+/// Like [`hash_mut`] but creating an empty hash when the key is missing
+fn hash_or_insert<'a>(
+    keyspace: &'a mut Keyspace,
+    key: &Bytes,
+) -> Result<&'a mut Hash, DatabaseError> {
+    let hash = keyspace.get_or_insert_as::<Hash>(key)?;
+    hash.purge_expired();
+    Ok(hash)
+}
+
+impl RedisDatabase {
+    /// Returns how many of the fields were new
+    pub async fn hash_set(
+        &self,
+        key: &Bytes,
+        fields: &IndexMap<Bytes, Bytes>,
+    ) -> Result<usize, DatabaseError> {
+        let mut keyspace = self.keyspace.write().await;
+        let hash = hash_or_insert(&mut keyspace, key)?;
+        Ok(fields
+            .iter()
+            .filter(|(field, value)| hash.insert((*field).clone(), (*value).clone()))
+            .count())
+    }
+    pub async fn hash_set_nx(
+        &self,
+        key: &Bytes,
+        field: &Bytes,
+        value: &Bytes,
+    ) -> Result<bool, DatabaseError> {
+        let mut keyspace = self.keyspace.write().await;
+        let hash = hash_or_insert(&mut keyspace, key)?;
+        if hash.contains_key(field) {
+            Ok(false)
+        } else {
+            hash.insert(field.clone(), value.clone());
+            Ok(true)
+        }
+    }
+    pub async fn hash_get(
+        &self,
+        key: &Bytes,
+        field: &Bytes,
+    ) -> Result<Option<Bytes>, DatabaseError> {
+        let keyspace = self.keyspace.read().await;
+        Ok(keyspace
+            .get_as::<Hash>(key)?
+            .and_then(|hash| hash.get(field).cloned()))
+    }
+    pub async fn hash_get_many(
+        &self,
+        key: &Bytes,
+        fields: &[Bytes],
+    ) -> Result<Vec<Option<Bytes>>, DatabaseError> {
+        let keyspace = self.keyspace.read().await;
+        let hash = keyspace.get_as::<Hash>(key)?;
+        Ok(fields
+            .iter()
+            .map(|field| hash.and_then(|hash| hash.get(field).cloned()))
+            .collect())
+    }
+    /// Removes the fields, deleting the key once the hash is empty
+    pub async fn hash_del(&self, key: &Bytes, fields: &[Bytes]) -> Result<usize, DatabaseError> {
+        let mut keyspace = self.keyspace.write().await;
+        let Some(hash) = hash_mut(&mut keyspace, key)? else {
+            return Ok(0);
+        };
+        let removed = fields
+            .iter()
+            .filter(|field| hash.remove(field).is_some())
+            .count();
+        if hash.is_empty() {
+            keyspace.remove(key);
+        }
+        Ok(removed)
+    }
+    pub async fn hash_exists(&self, key: &Bytes, field: &Bytes) -> Result<bool, DatabaseError> {
+        let keyspace = self.keyspace.read().await;
+        Ok(keyspace
+            .get_as::<Hash>(key)?
+            .is_some_and(|hash| hash.contains_key(field)))
+    }
+    pub async fn hash_len(&self, key: &Bytes) -> Result<usize, DatabaseError> {
+        let keyspace = self.keyspace.read().await;
+        Ok(keyspace.get_as::<Hash>(key)?.map(Hash::len).unwrap_or(0))
+    }
+    pub async fn hash_str_len(&self, key: &Bytes, field: &Bytes) -> Result<usize, DatabaseError> {
+        let keyspace = self.keyspace.read().await;
+        Ok(keyspace
+            .get_as::<Hash>(key)?
+            .and_then(|hash| hash.get(field))
+            .map(Bytes::len)
+            .unwrap_or(0))
+    }
+    /// Every field and value in insertion order
+    pub async fn hash_get_all(&self, key: &Bytes) -> Result<Vec<(Bytes, Bytes)>, DatabaseError> {
+        let keyspace = self.keyspace.read().await;
+        Ok(keyspace
+            .get_as::<Hash>(key)?
+            .map(|hash| {
+                hash.iter()
+                    .map(|(field, value)| (field.clone(), value.clone()))
+                    .collect()
+            })
+            .unwrap_or_default())
+    }
+    pub async fn hash_incr(
+        &self,
+        key: &Bytes,
+        field: &Bytes,
+        delta: i64,
+    ) -> Result<i64, DatabaseError> {
+        let mut keyspace = self.keyspace.write().await;
+        let hash = hash_or_insert(&mut keyspace, key)?;
+        let current = match hash.get(field) {
+            Some(value) => parse_int(value).ok_or(DatabaseError::HashNotInteger)?,
+            None => 0,
+        };
+        let num = current
+            .checked_add(delta)
+            .ok_or(DatabaseError::IncrOverflow)?;
+        hash.update(field.clone(), Bytes::from(num.to_string()));
+        Ok(num)
+    }
+    pub async fn hash_incr_float(
+        &self,
+        key: &Bytes,
+        field: &Bytes,
+        delta: f64,
+    ) -> Result<Bytes, DatabaseError> {
+        let mut keyspace = self.keyspace.write().await;
+        // The hash is only created once there's a valid value to put in it
+        let current = match keyspace
+            .get_as::<Hash>(key)?
+            .and_then(|hash| hash.get(field))
+        {
+            Some(value) => parse_float(value).ok_or(DatabaseError::HashNotFloat)?,
+            None => 0.0,
+        };
+        let num = current + delta;
+        if !num.is_finite() {
+            return Err(DatabaseError::NanOrInfinity);
+        }
+        let formatted = Bytes::from(format_float(num));
+        hash_or_insert(&mut keyspace, key)?.update(field.clone(), formatted.clone());
+        Ok(formatted)
+    }
+    /// Picks `count` random fields: distinct ones when positive, possibly
+    /// repeated ones when negative
+    pub async fn hash_rand_fields(
+        &self,
+        key: &Bytes,
+        count: i64,
+    ) -> Result<Vec<(Bytes, Bytes)>, DatabaseError> {
+        check_rand_count(count)?;
+        let keyspace = self.keyspace.read().await;
+        let Some(hash) = keyspace.get_as::<Hash>(key)? else {
+            return Ok(vec![]);
+        };
+        // Only live fields are picked, so they're gathered up front. The last
+        // of them may have expired since the hash was looked up.
+        let entries: Vec<_> = hash.iter().collect();
+        if entries.is_empty() {
+            return Ok(vec![]);
+        }
+        let entry = |idx: usize| (entries[idx].0.clone(), entries[idx].1.clone());
+        let mut rng = rand::rng();
+        if count < 0 {
+            Ok((0..count.unsigned_abs())
+                .map(|_| entry(rng.random_range(0..entries.len())))
+                .collect())
+        } else if count as usize >= entries.len() {
+            Ok((0..entries.len()).map(entry).collect())
+        } else {
+            Ok(
+                rand::seq::index::sample(&mut rng, entries.len(), count as usize)
+                    .into_iter()
+                    .map(entry)
+                    .collect(),
+            )
+        }
+    }
+
+    /// Gives each field the TTL `condition` allows. Per field the reply is
+    /// `-2` when it's missing, `0` when the condition failed, `1` when the TTL
+    /// was set and `2` when the deadline already passed and it was deleted.
+    pub async fn hash_expire(
+        &self,
+        key: &Bytes,
+        fields: &[Bytes],
+        expiry: SystemTime,
+        condition: ExpireCondition,
+    ) -> Result<Vec<i64>, DatabaseError> {
+        let mut keyspace = self.keyspace.write().await;
+        let Some(hash) = hash_mut(&mut keyspace, key)? else {
+            return Ok(vec![-2; fields.len()]);
+        };
+        let now = SystemTime::now();
+        let replies = fields
+            .iter()
+            .map(|field| {
+                if !hash.contains_key(field) {
+                    -2
+                } else if !condition.allows(hash.expires_at(field), expiry) {
+                    0
+                } else if expiry <= now {
+                    hash.remove(field);
+                    2
+                } else {
+                    hash.set_expiry(field, Some(expiry));
+                    1
+                }
+            })
+            .collect();
+        let (empty, has_ttls) = (hash.is_empty(), hash.has_field_ttls());
+        if empty {
+            keyspace.remove(key);
+        } else if has_ttls {
+            keyspace.track_field_ttls(key);
+        }
+        Ok(replies)
+    }
+    pub async fn hash_field_ttls(
+        &self,
+        key: &Bytes,
+        fields: &[Bytes],
+    ) -> Result<Vec<KeyTtl>, DatabaseError> {
+        let keyspace = self.keyspace.read().await;
+        let hash = keyspace.get_as::<Hash>(key)?;
+        Ok(fields
+            .iter()
+            .map(|field| match hash.filter(|hash| hash.contains_key(field)) {
+                Some(hash) => match hash.expires_at(field) {
+                    Some(time) => KeyTtl::ExpiresAt(time),
+                    None => KeyTtl::Persistent,
+                },
+                None => KeyTtl::Missing,
+            })
+            .collect())
+    }
+    /// Clears the TTL of each field, replying `-2` for a missing field, `-1`
+    /// when it had none and `1` when it was cleared
+    pub async fn hash_persist(
+        &self,
+        key: &Bytes,
+        fields: &[Bytes],
+    ) -> Result<Vec<i64>, DatabaseError> {
+        let mut keyspace = self.keyspace.write().await;
+        let Some(hash) = hash_mut(&mut keyspace, key)? else {
+            return Ok(vec![-2; fields.len()]);
+        };
+        Ok(fields
+            .iter()
+            .map(|field| {
+                if !hash.contains_key(field) {
+                    -2
+                } else if hash.expires_at(field).is_none() {
+                    -1
+                } else {
+                    hash.set_expiry(field, None);
+                    1
+                }
+            })
+            .collect())
+    }
+    /// Gets the fields, replacing the TTL of those that exist when `expiry`
+    /// is given. `Some(None)` persists them and a deadline in the past
+    /// deletes them after they're read.
+    pub async fn hash_get_ex(
+        &self,
+        key: &Bytes,
+        fields: &[Bytes],
+        expiry: Option<Option<SystemTime>>,
+    ) -> Result<Vec<Option<Bytes>>, DatabaseError> {
+        let mut keyspace = self.keyspace.write().await;
+        let Some(hash) = hash_mut(&mut keyspace, key)? else {
+            return Ok(vec![None; fields.len()]);
+        };
+        let values: Vec<_> = fields
+            .iter()
+            .map(|field| hash.get(field).cloned())
+            .collect();
+        let Some(expiry) = expiry else {
+            return Ok(values);
+        };
+        let now = SystemTime::now();
+        for (field, _) in fields
+            .iter()
+            .zip(&values)
+            .filter(|(_, value)| value.is_some())
+        {
+            match expiry {
+                Some(expiry) if expiry <= now => {
+                    hash.remove(field);
+                }
+                expiry => {
+                    hash.set_expiry(field, expiry);
+                }
+            }
+        }
+        let (empty, has_ttls) = (hash.is_empty(), hash.has_field_ttls());
+        if empty {
+            keyspace.remove(key);
+        } else if has_ttls {
+            keyspace.track_field_ttls(key);
+        }
+        Ok(values)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn test_field_expiry() {
+        let mut hash = Hash::default();
+        let (a, b) = (Bytes::from("a"), Bytes::from("b"));
+        assert!(hash.insert(a.clone(), Bytes::from("1")));
+        assert!(hash.insert(b.clone(), Bytes::from("2")));
+        let past = SystemTime::now() - Duration::from_secs(1);
+        hash.expiries.insert(a.clone(), past);
+        assert_eq!(hash.get(&a), None);
+        assert_eq!(hash.len(), 1);
+        assert!(!hash.all_expired());
+        // Writing an expired field brings it back as a new one without a TTL
+        assert!(hash.insert(a.clone(), Bytes::from("3")));
+        assert_eq!(hash.expires_at(&a), None);
+
+        hash.expiries.insert(b.clone(), past);
+        assert_eq!(hash.purge_expired(), vec![b]);
+        hash.expiries.insert(a, past);
+        assert!(hash.all_expired());
+    }
+
+    #[tokio::test]
+    async fn test_hash_incr_float_not_finite() {
+        let db = RedisDatabase::default();
+        let (key, field) = (Bytes::from("missing"), Bytes::from("field"));
+        assert!(matches!(
+            db.hash_incr_float(&key, &field, f64::INFINITY).await,
+            Err(DatabaseError::NanOrInfinity)
+        ));
+        assert!(!db.keyspace.read().await.contains_key(&key));
+    }
+
+    #[tokio::test]
+    async fn test_hash_rand_count_out_of_range() {
+        let db = RedisDatabase::default();
+        let key = Bytes::from("key");
+        let fields = IndexMap::from([(Bytes::from("f"), Bytes::from("v"))]);
+        db.hash_set(&key, &fields).await.unwrap();
+        assert!(matches!(
+            db.hash_rand_fields(&key, i64::MIN).await,
+            Err(DatabaseError::OutOfRange)
+        ));
+        assert_eq!(db.hash_rand_fields(&key, -3).await.unwrap().len(), 3);
+    }
+}
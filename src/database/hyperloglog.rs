@@ -0,0 +1,400 @@
+use bytes::{BufMut, Bytes, BytesMut};
+use either::Either;
+
+use crate::database::{DatabaseError, RedisDatabase, string_bytes};
+
+// The layout follows Redis' hyperloglog.c so that the strings can be moved
+// between servers: a 16 byte header, `HYLL`, the encoding, 3 unused bytes and
+// the cached cardinality, followed by either 16384 packed 6 bit registers or
+// the run length encoded sparse form.
+const HLL_P: u32 = 14;
+const HLL_Q: u32 = 64 - HLL_P;
+const HLL_REGISTERS: usize = 1 << HLL_P;
+const HLL_BITS: usize = 6;
+const HLL_REGISTER_MAX: u8 = (1 << HLL_BITS) - 1;
+const HLL_HDR_SIZE: usize = 16;
+const HLL_DENSE_SIZE: usize = HLL_HDR_SIZE + (HLL_REGISTERS * HLL_BITS).div_ceil(8);
+const HLL_MAGIC: &[u8] = b"HYLL";
+const HLL_DENSE: u8 = 0;
+const HLL_SPARSE: u8 = 1;
+const HLL_ALPHA_INF: f64 = 0.721_347_520_444_481_7;
+/// Past this size a sparse HLL is converted to the dense encoding
+const HLL_SPARSE_MAX_BYTES: usize = 3000;
+
+// Sparse opcodes
+const HLL_SPARSE_ZERO_MAX_LEN: usize = 64;
+const HLL_SPARSE_XZERO_MAX_LEN: usize = 16384;
+const HLL_SPARSE_VAL_MAX_VALUE: u8 = 32;
+const HLL_SPARSE_VAL_MAX_LEN: usize = 4;
+
+/// A HyperLogLog with its registers unpacked, remembering which encoding it
+/// should be written back with
+#[derive(Debug, Clone)]
+pub struct HyperLogLog {
+    registers: Vec<u8>,
+    dense: bool,
+}
+
+impl Default for HyperLogLog {
+    fn default() -> Self {
+        Self {
+            registers: vec![0; HLL_REGISTERS],
+            dense: false,
+        }
+    }
+}
+
+impl HyperLogLog {
+    pub fn decode(bytes: &[u8]) -> Result<Self, DatabaseError> {
+        if bytes.len() < HLL_HDR_SIZE || &bytes[..4] != HLL_MAGIC {
+            return Err(DatabaseError::InvalidHll);
+        }
+        let data = &bytes[HLL_HDR_SIZE..];
+        match bytes[4] {
+            HLL_DENSE if bytes.len() == HLL_DENSE_SIZE => Ok(Self {
+                registers: (0..HLL_REGISTERS).map(|idx| dense_get(data, idx)).collect(),
+                dense: true,
+            }),
+            HLL_SPARSE => {
+                let mut registers = Vec::with_capacity(HLL_REGISTERS);
+                let mut idx = 0;
+                while idx < data.len() {
+                    let op = data[idx];
+                    let (value, len) = if op & 0xc0 == 0 {
+                        // ZERO: 00xxxxxx
+                        (0, (op & 0x3f) as usize + 1)
+                    } else if op & 0xc0 == 0x40 {
+                        // XZERO: 01xxxxxx yyyyyyyy
+                        idx += 1;
+                        let low = *data.get(idx).ok_or(DatabaseError::CorruptHll)?;
+                        (0, (((op & 0x3f) as usize) << 8 | low as usize) + 1)
+                    } else {
+                        // VAL: 1vvvvvxx
+                        (((op >> 2) & 0x1f) + 1, (op & 0x3) as usize + 1)
+                    };
+                    if registers.len() + len > HLL_REGISTERS {
+                        return Err(DatabaseError::CorruptHll);
+                    }
+                    registers.resize(registers.len() + len, value);
+                    idx += 1;
+                }
+                if registers.len() != HLL_REGISTERS {
+                    return Err(DatabaseError::CorruptHll);
+                }
+                Ok(Self {
+                    registers,
+                    dense: false,
+                })
+            }
+            _ => Err(DatabaseError::InvalidHll),
+        }
+    }
+
+    /// The cardinality cached in the header of an encoded HLL, if still valid
+    pub fn cached_count(bytes: &[u8]) -> Option<u64> {
+        let card: [u8; 8] = bytes.get(8..HLL_HDR_SIZE)?.try_into().ok()?;
+        (card[7] & 0x80 == 0).then(|| u64::from_le_bytes(card))
+    }
+
+    /// Replaces the cached cardinality in the header of an encoded HLL
+    pub fn with_cached_count(bytes: &[u8], count: Option<u64>) -> Bytes {
+        let mut out = BytesMut::from(bytes);
+        out[8..HLL_HDR_SIZE].copy_from_slice(&card_bytes(count));
+        out.freeze()
+    }
+
+    /// Encodes the HLL, staying sparse while that is possible and small enough
+    pub fn encode(&mut self, count: Option<u64>) -> Bytes {
+        if !self.dense {
+            if let Some(sparse) = self.encode_sparse()
+                && HLL_HDR_SIZE + sparse.len() <= HLL_SPARSE_MAX_BYTES
+            {
+                return encode_with_header(HLL_SPARSE, count, &sparse);
+            }
+            self.dense = true;
+        }
+        let mut data = vec![0; HLL_DENSE_SIZE - HLL_HDR_SIZE];
+        for (idx, value) in self.registers.iter().enumerate() {
+            dense_set(&mut data, idx, *value);
+        }
+        encode_with_header(HLL_DENSE, count, &data)
+    }
+
+    /// `None` when a register is too large for the sparse encoding
+    fn encode_sparse(&self) -> Option<Vec<u8>> {
+        let mut out = vec![];
+        let mut idx = 0;
+        while idx < HLL_REGISTERS {
+            let value = self.registers[idx];
+            let run = self.registers[idx..]
+                .iter()
+                .take_while(|register| **register == value)
+                .count();
+            idx += run;
+            let mut run = run;
+            if value == 0 {
+                while run > 0 {
+                    if run <= HLL_SPARSE_ZERO_MAX_LEN {
+                        out.push((run - 1) as u8);
+                        run = 0;
+                    } else {
+                        let len = run.min(HLL_SPARSE_XZERO_MAX_LEN);
+                        out.push(0x40 | ((len - 1) >> 8) as u8);
+                        out.push(((len - 1) & 0xff) as u8);
+                        run -= len;
+                    }
+                }
+            } else if value > HLL_SPARSE_VAL_MAX_VALUE {
+                return None;
+            } else {
+                while run > 0 {
+                    let len = run.min(HLL_SPARSE_VAL_MAX_LEN);
+                    out.push(0x80 | ((value - 1) << 2) | (len - 1) as u8);
+                    run -= len;
+                }
+            }
+        }
+        Some(out)
+    }
+
+    /// Returns whether a register changed
+    pub fn add(&mut self, element: &[u8]) -> bool {
+        let hash = murmurhash64a(element, 0xadc83b19);
+        let idx = (hash & (HLL_REGISTERS as u64 - 1)) as usize;
+        // The run of zeroes in the remaining bits, capped by the sentinel bit
+        let count = ((hash >> HLL_P) | (1 << HLL_Q)).trailing_zeros() as u8 + 1;
+        if count > self.registers[idx] {
+            self.registers[idx] = count;
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn merge(&mut self, other: &HyperLogLog) {
+        for (register, other) in self.registers.iter_mut().zip(&other.registers) {
+            *register = (*register).max(*other);
+        }
+        self.dense |= other.dense;
+    }
+
+    /// The estimator from Otmar Ertl's "New cardinality estimation algorithms
+    /// for HyperLogLog sketches", as used by Redis
+    pub fn count(&self) -> u64 {
+        let m = HLL_REGISTERS as f64;
+        let mut histogram = [0u32; HLL_REGISTER_MAX as usize + 1];
+        for register in &self.registers {
+            histogram[*register as usize] += 1;
+        }
+        let mut z = m * tau((m - histogram[HLL_Q as usize + 1] as f64) / m);
+        for count in histogram[1..=HLL_Q as usize].iter().rev() {
+            z += *count as f64;
+            z *= 0.5;
+        }
+        z += m * sigma(histogram[0] as f64 / m);
+        (HLL_ALPHA_INF * m * m / z).round() as u64
+    }
+}
+
+fn card_bytes(count: Option<u64>) -> [u8; 8] {
+    match count {
+        Some(count) => count.to_le_bytes(),
+        // The most significant bit marks the cached value as stale
+        None => [0, 0, 0, 0, 0, 0, 0, 0x80],
+    }
+}
+
+fn encode_with_header(encoding: u8, count: Option<u64>, data: &[u8]) -> Bytes {
+    let mut out = BytesMut::with_capacity(HLL_HDR_SIZE + data.len());
+    out.put_slice(HLL_MAGIC);
+    out.put_u8(encoding);
+    out.put_slice(&[0; 3]);
+    out.put_slice(&card_bytes(count));
+    out.put_slice(data);
+    out.freeze()
+}
+
+fn dense_get(data: &[u8], idx: usize) -> u8 {
+    let byte = idx * HLL_BITS / 8;
+    let shift = idx * HLL_BITS % 8;
+    let low = data[byte] as u16 >> shift;
+    let high = data.get(byte + 1).copied().unwrap_or(0) as u16;
+    ((low | high << (8 - shift)) & HLL_REGISTER_MAX as u16) as u8
+}
+
+fn dense_set(data: &mut [u8], idx: usize, value: u8) {
+    let byte = idx * HLL_BITS / 8;
+    let shift = idx * HLL_BITS % 8;
+    let value = value as u16 & HLL_REGISTER_MAX as u16;
+    data[byte] &= !((HLL_REGISTER_MAX as u16) << shift) as u8;
+    data[byte] |= (value << shift) as u8;
+    if let Some(next) = data.get_mut(byte + 1) {
+        *next &= !((HLL_REGISTER_MAX as u16) >> (8 - shift)) as u8;
+        *next |= (value >> (8 - shift)) as u8;
+    }
+}
+
+fn sigma(mut x: f64) -> f64 {
+    if x == 1.0 {
+        return f64::INFINITY;
+    }
+    let mut y = 1.0;
+    let mut z = x;
+    loop {
+        x *= x;
+        let prev = z;
+        z += x * y;
+        y += y;
+        if prev == z {
+            return z;
+        }
+    }
+}
+
+fn tau(mut x: f64) -> f64 {
+    if x == 0.0 || x == 1.0 {
+        return 0.0;
+    }
+    let mut y = 1.0;
+    let mut z = 1.0 - x;
+    loop {
+        x = x.sqrt();
+        let prev = z;
+        y *= 0.5;
+        z -= (1.0 - x).powi(2) * y;
+        if prev == z {
+            return z / 3.0;
+        }
+    }
+}
+
+/// MurmurHash2, 64-bit version by Austin Appleby
+fn murmurhash64a(key: &[u8], seed: u64) -> u64 {
+    const M: u64 = 0xc6a4a7935bd1e995;
+    const R: u32 = 47;
+    let mut h = seed ^ (key.len() as u64).wrapping_mul(M);
+    let mut chunks = key.chunks_exact(8);
+    for chunk in chunks.by_ref() {
+        let mut k = u64::from_le_bytes(chunk.try_into().expect("8 byte chunk"));
+        k = k.wrapping_mul(M);
+        k ^= k >> R;
+        k = k.wrapping_mul(M);
+        h ^= k;
+        h = h.wrapping_mul(M);
+    }
+    let tail = chunks.remainder();
+    if !tail.is_empty() {
+        for (idx, byte) in tail.iter().enumerate() {
+            h ^= (*byte as u64) << (idx * 8);
+        }
+        h = h.wrapping_mul(M);
+    }
+    h ^= h >> R;
+    h = h.wrapping_mul(M);
+    h ^= h >> R;
+    h
+}
+
+impl RedisDatabase {
+    /// Returns whether the HLL changed, creating the key counts as a change
+    pub async fn pf_add(&self, key: &Bytes, elements: &[Bytes]) -> Result<bool, DatabaseError> {
+        let mut keyspace = self.keyspace.write().await;
+        let (mut hll, mut updated) = match keyspace.get_as(key)? {
+            Some(value) => (HyperLogLog::decode(&string_bytes(value))?, false),
+            None => (HyperLogLog::default(), true),
+        };
+        for element in elements {
+            updated |= hll.add(element);
+        }
+        if updated {
+            let encoded = hll.encode(None);
+            *keyspace.get_or_insert_with_as(key, || Either::Left(Bytes::new()))? =
+                Either::Left(encoded);
+        }
+        Ok(updated)
+    }
+
+    /// Counts the union of the HLLs at `keys`. A single key caches the result
+    /// in its header.
+    pub async fn pf_count(&self, keys: &[Bytes]) -> Result<u64, DatabaseError> {
+        let mut keyspace = self.keyspace.write().await;
+        if let [key] = keys {
+            let Some(value) = keyspace.get_as_mut::<Either<Bytes, i64>>(key)? else {
+                return Ok(0);
+            };
+            let bytes = string_bytes(value);
+            let hll = HyperLogLog::decode(&bytes)?;
+            if let Some(count) = HyperLogLog::cached_count(&bytes) {
+                return Ok(count);
+            }
+            let count = hll.count();
+            *value = Either::Left(HyperLogLog::with_cached_count(&bytes, Some(count)));
+            return Ok(count);
+        }
+        let mut merged = HyperLogLog::default();
+        for key in keys {
+            if let Some(value) = keyspace.get_as(key)? {
+                merged.merge(&HyperLogLog::decode(&string_bytes(value))?);
+            }
+        }
+        Ok(merged.count())
+    }
+
+    /// Merges `sources` into `destination`, which is included in the union
+    pub async fn pf_merge(
+        &self,
+        destination: &Bytes,
+        sources: &[Bytes],
+    ) -> Result<(), DatabaseError> {
+        let mut keyspace = self.keyspace.write().await;
+        let mut merged = HyperLogLog::default();
+        for key in std::iter::once(destination).chain(sources) {
+            if let Some(value) = keyspace.get_as(key)? {
+                merged.merge(&HyperLogLog::decode(&string_bytes(value))?);
+            }
+        }
+        let encoded = merged.encode(None);
+        *keyspace.get_or_insert_with_as(destination, || Either::Left(Bytes::new()))? =
+            Either::Left(encoded);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_roundtrip() {
+        let mut hll = HyperLogLog::default();
+        let empty = hll.encode(Some(0));
+        // A new HLL is a single XZERO run covering every register
+        assert_eq!(&empty[HLL_HDR_SIZE..], &[0x7f, 0xff]);
+
+        for idx in 0..100 {
+            hll.add(format!("element{idx}").as_bytes());
+        }
+        let sparse = hll.encode(None);
+        assert_eq!(sparse[4], HLL_SPARSE);
+        let decoded = HyperLogLog::decode(&sparse).unwrap();
+        assert_eq!(decoded.registers, hll.registers);
+
+        hll.dense = true;
+        let dense = hll.encode(None);
+        assert_eq!(dense.len(), HLL_DENSE_SIZE);
+        assert_eq!(
+            HyperLogLog::decode(&dense).unwrap().registers,
+            hll.registers
+        );
+    }
+
+    #[test]
+    fn test_count_error() {
+        let mut hll = HyperLogLog::default();
+        for idx in 0..100_000 {
+            hll.add(format!("element{idx}").as_bytes());
+        }
+        let count = hll.count() as f64;
+        assert!((count - 100_000.0).abs() / 100_000.0 < 0.02, "{count}");
+    }
+}
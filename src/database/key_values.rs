@@ -4,100 +4,315 @@ use bytes::Bytes;
 use either::Either;
 use tokio::time::Instant;
 
-use crate::database::RedisDatabase;
+use crate::database::{DatabaseError, DatabaseValue, RedisDatabase, RedisValue};
 
-#[derive(Debug, Clone)]
-pub struct DatabaseValue {
-    value: Either<Bytes, i64>,
-    expiry: Option<Either<Instant, SystemTime>>,
+/// Only write the value depending on whether the key exists
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SetCondition {
+    Nx,
+    Xx,
 }
 
-impl DatabaseValue {
-    fn zeroed_num() -> Self {
-        Self {
-            value: Either::Right(0),
-            expiry: None,
-        }
-    }
-    fn incr_value(&mut self) -> Option<i64> {
-        if let Either::Right(num) = &mut self.value {
-            *num += 1;
-            Some(*num)
+#[derive(Debug, Default)]
+pub struct SetOptions {
+    pub expiry: Option<Either<Instant, SystemTime>>,
+    pub keep_ttl: bool,
+    pub condition: Option<SetCondition>,
+    /// Return the old string stored at the key
+    pub get: bool,
+}
+
+impl RedisDatabase {
+    /// Returns whether the value was written along with the previous value
+    /// when [`SetOptions::get`] is set
+    pub async fn set_kv(
+        &self,
+        key: Bytes,
+        val: Bytes,
+        options: SetOptions,
+    ) -> Result<(bool, Option<Bytes>), DatabaseError> {
+        let mut keyspace = self.keyspace.write().await;
+        let previous = if options.get {
+            keyspace.get_as(&key)?.map(string_bytes)
         } else {
             None
+        };
+        let existing = keyspace.get(&key);
+        let allowed = match options.condition {
+            Some(SetCondition::Nx) => existing.is_none(),
+            Some(SetCondition::Xx) => existing.is_some(),
+            None => true,
+        };
+        if !allowed {
+            return Ok((false, previous));
         }
+        let expiry = if options.keep_ttl {
+            existing.and_then(|value| value.expiry)
+        } else {
+            options.expiry
+        };
+        keyspace.insert(
+            key,
+            DatabaseValue::new(RedisValue::String(string_value(val)), expiry),
+        );
+        Ok((true, previous))
     }
-}
 
-impl DatabaseValue {
-    // pub fn new(value: Bytes, expiry: Option<Instant>) -> Self {
-    //     Self { value, expiry }
-    // }
-    // pub fn value(&self) -> &Bytes {
-    //     &self.value
-    // }
-    pub fn is_expired(&self) -> bool {
-        if let Some(expiry) = self.expiry {
-            match expiry {
-                Either::Left(expiry) => Instant::now() > expiry,
-                Either::Right(expiry) => SystemTime::now() > expiry,
-            }
-        } else {
-            false
+    /// Adds `delta` to the integer at `key`, starting from 0 when it's missing
+    pub async fn incr_value(&self, key: Bytes, delta: i64) -> Result<i64, DatabaseError> {
+        let mut keyspace = self.keyspace.write().await;
+        let value = keyspace.get_or_insert_with_as(&key, || Either::<Bytes, i64>::Right(0))?;
+        let current = match value {
+            Either::Right(num) => *num,
+            Either::Left(bytes) => parse_int(bytes).ok_or(DatabaseError::NotAnInteger)?,
+        };
+        let num = current
+            .checked_add(delta)
+            .ok_or(DatabaseError::IncrOverflow)?;
+        *value = Either::Right(num);
+        Ok(num)
+    }
+
+    /// Returns the new value formatted the way Redis replies with it
+    pub async fn incr_float_value(&self, key: Bytes, delta: f64) -> Result<Bytes, DatabaseError> {
+        let mut keyspace = self.keyspace.write().await;
+        let current = match keyspace.get_as::<Either<Bytes, i64>>(&key)? {
+            Some(Either::Right(num)) => *num as f64,
+            Some(Either::Left(bytes)) => parse_float(bytes).ok_or(DatabaseError::NotAFloat)?,
+            None => 0.0,
+        };
+        // Nothing is written, not even a missing key, unless the result is
+        // stored
+        let num = current + delta;
+        if !num.is_finite() {
+            return Err(DatabaseError::NanOrInfinity);
         }
+        let formatted = Bytes::from(format_float(num));
+        let value = keyspace.get_or_insert_with_as(&key, || Either::<Bytes, i64>::Right(0))?;
+        *value = string_value(formatted.clone());
+        Ok(formatted)
     }
-    // pub fn update_value_ttl_expiry(&mut self, value: Bytes) {
-    //     self.value = value
-    // }
-}
 
-impl RedisDatabase {
-    pub async fn set_kv(
-        &self,
-        key: Bytes,
-        val: Bytes,
-        expiry: Option<Either<Instant, SystemTime>>,
-        _keep_ttl: bool,
-    ) {
-        let mut string_db = self.key_value.write().await;
-        if let Ok(value) = str::from_utf8(&val).expect("valid utf-8").parse::<i64>() {
-            string_db.insert(
+    pub async fn get_string(&self, key: &Bytes) -> Result<Option<Bytes>, DatabaseError> {
+        let keyspace = self.keyspace.read().await;
+        Ok(keyspace.get_as(key)?.map(string_bytes))
+    }
+
+    /// `MGET` replies nil for missing keys and keys of another type
+    pub async fn get_strings(&self, keys: &[Bytes]) -> Vec<Option<Bytes>> {
+        let keyspace = self.keyspace.read().await;
+        keys.iter()
+            .map(|key| keyspace.get_as(key).ok().flatten().map(string_bytes))
+            .collect()
+    }
+
+    /// Sets all of `pairs` under a single lock. With `nx` nothing is written
+    /// if any of the keys already exists.
+    pub async fn set_strings(&self, pairs: &[(Bytes, Bytes)], nx: bool) -> bool {
+        let mut keyspace = self.keyspace.write().await;
+        if nx && pairs.iter().any(|(key, _)| keyspace.contains_key(key)) {
+            return false;
+        }
+        for (key, val) in pairs {
+            keyspace.insert(
                 key.clone(),
-                DatabaseValue {
-                    value: Either::Right(value),
-                    expiry,
-                },
-            );
-        } else {
-            string_db.insert(
-                key,
-                DatabaseValue {
-                    value: Either::Left(val),
-                    expiry,
-                },
+                DatabaseValue::new(RedisValue::String(string_value(val.clone())), None),
             );
         }
+        true
+    }
+
+    pub async fn get_del(&self, key: &Bytes) -> Result<Option<Bytes>, DatabaseError> {
+        let mut keyspace = self.keyspace.write().await;
+        let Some(value) = keyspace.get_as(key)?.map(string_bytes) else {
+            return Ok(None);
+        };
+        keyspace.remove(key);
+        Ok(Some(value))
+    }
+
+    /// Gets the string at `key`, replacing its TTL when `expiry` is given.
+    /// `Some(None)` persists the key.
+    pub async fn get_ex(
+        &self,
+        key: &Bytes,
+        expiry: Option<Option<Either<Instant, SystemTime>>>,
+    ) -> Result<Option<Bytes>, DatabaseError> {
+        let mut keyspace = self.keyspace.write().await;
+        let Some(value) = keyspace.get_as(key)?.map(string_bytes) else {
+            return Ok(None);
+        };
+        if let Some(expiry) = expiry {
+            keyspace.set_expiry(key, expiry);
+        }
+        Ok(Some(value))
     }
 
-    pub async fn incr_value(&self, key: Bytes) -> Option<i64> {
-        let mut strings = self.key_value.write().await;
-        let value = strings.entry(key).or_insert(DatabaseValue::zeroed_num());
-        value.incr_value()
+    /// Returns the length of the string after appending
+    pub async fn append(&self, key: &Bytes, suffix: &Bytes) -> Result<usize, DatabaseError> {
+        let mut keyspace = self.keyspace.write().await;
+        let value = keyspace.get_or_insert_with_as(key, || Either::Left(Bytes::new()))?;
+        let mut appended = string_bytes(value).to_vec();
+        appended.extend_from_slice(suffix);
+        let len = appended.len();
+        *value = string_value(Bytes::from(appended));
+        Ok(len)
     }
 
-    pub async fn get_string(&self, key: &Bytes) -> Option<Bytes> {
-        let string_db = self.key_value.read().await;
-        if let Some(val) = string_db.get(key) {
-            if val.is_expired() {
-                None
-            } else {
-                match &val.value {
-                    Either::Left(value) => Some(value.clone()),
-                    Either::Right(num) => Some(Bytes::from(num.to_string())),
-                }
-            }
+    pub async fn str_len(&self, key: &Bytes) -> Result<usize, DatabaseError> {
+        let keyspace = self.keyspace.read().await;
+        Ok(keyspace
+            .get_as(key)?
+            .map(|value| string_bytes(value).len())
+            .unwrap_or(0))
+    }
+
+    /// Both ends are inclusive and negative offsets count from the end
+    pub async fn get_range(
+        &self,
+        key: &Bytes,
+        start: i64,
+        end: i64,
+    ) -> Result<Bytes, DatabaseError> {
+        let keyspace = self.keyspace.read().await;
+        let Some(value) = keyspace.get_as(key)?.map(string_bytes) else {
+            return Ok(Bytes::new());
+        };
+        let len = value.len() as i64;
+        if start < 0 && end < 0 && start > end {
+            return Ok(Bytes::new());
+        }
+        let start = if start < 0 {
+            (start + len).max(0)
         } else {
-            None
+            start
+        };
+        let end = if end < 0 {
+            (end + len).max(0)
+        } else {
+            end.min(len - 1)
+        };
+        if start > end || len == 0 {
+            return Ok(Bytes::new());
+        }
+        Ok(value.slice(start as usize..=end as usize))
+    }
+
+    /// Overwrites the string from `offset`, padding with zero bytes when the
+    /// string is too short. Returns the new length.
+    pub async fn set_range(
+        &self,
+        key: &Bytes,
+        offset: usize,
+        val: &Bytes,
+    ) -> Result<usize, DatabaseError> {
+        let mut keyspace = self.keyspace.write().await;
+        if val.is_empty() {
+            // Nothing to write so a missing key isn't created
+            return Ok(keyspace
+                .get_as(key)?
+                .map(|value| string_bytes(value).len())
+                .unwrap_or(0));
         }
+        let value = keyspace.get_or_insert_with_as(key, || Either::Left(Bytes::new()))?;
+        let mut bytes = string_bytes(value).to_vec();
+        if bytes.len() < offset + val.len() {
+            bytes.resize(offset + val.len(), 0);
+        }
+        bytes[offset..offset + val.len()].copy_from_slice(val);
+        let len = bytes.len();
+        *value = string_value(Bytes::from(bytes));
+        Ok(len)
+    }
+}
+
+/// The bytes a string value reads as
+pub fn string_bytes(value: &Either<Bytes, i64>) -> Bytes {
+    match value {
+        Either::Left(value) => value.clone(),
+        Either::Right(num) => Bytes::from(num.to_string()),
+    }
+}
+
+/// Stores strings that hold an integer as one, as long as it reads back
+/// unchanged so that values like `007` keep their bytes
+pub fn string_value(val: Bytes) -> Either<Bytes, i64> {
+    match str::from_utf8(&val)
+        .ok()
+        .and_then(|s| s.parse::<i64>().ok())
+    {
+        Some(num) if num.to_string().as_bytes() == val => Either::Right(num),
+        _ => Either::Left(val),
+    }
+}
+
+/// Reads a string kept as bytes as an integer, ignoring surrounding spaces
+pub fn parse_int(bytes: &[u8]) -> Option<i64> {
+    str::from_utf8(bytes.trim_ascii()).ok()?.parse().ok()
+}
+
+pub fn parse_float(bytes: &[u8]) -> Option<f64> {
+    let num = str::from_utf8(bytes.trim_ascii())
+        .ok()?
+        .parse::<f64>()
+        .ok()?;
+    (!num.is_nan()).then_some(num)
+}
+
+/// Formats like Redis' `%.17Lf` followed by trimming trailing zeroes. A
+/// double only carries about 15 significant digits so only those are kept,
+/// which hides the rounding noise a long double would have absorbed.
+pub fn format_float(num: f64) -> String {
+    const SIGNIFICANT_DIGITS: i32 = 15;
+    let int_digits = if num == 0.0 {
+        0
+    } else {
+        num.abs().log10().floor() as i32 + 1
+    };
+    let precision = (SIGNIFICANT_DIGITS - int_digits).clamp(0, 17) as usize;
+    let mut out = format!("{num:.precision$}");
+    if out.contains('.') {
+        let trimmed = out.trim_end_matches('0').trim_end_matches('.').len();
+        out.truncate(trimmed);
+    }
+    if out == "-0" { "0".into() } else { out }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_float() {
+        assert_eq!(format_float(10.1 + 0.1), "10.2");
+        assert_eq!(format_float(5.0e3 + 2.0e2), "5200");
+        assert_eq!(format_float(-0.5), "-0.5");
+        assert_eq!(format_float(1e20), "100000000000000000000");
+        assert_eq!(format_float(-1e-20), "0");
+    }
+
+    #[test]
+    fn test_string_value() {
+        assert_eq!(string_value(Bytes::from("42")), Either::Right(42));
+        assert_eq!(
+            string_value(Bytes::from("007")),
+            Either::Left(Bytes::from("007"))
+        );
+        assert_eq!(
+            string_value(Bytes::from_static(b"\xff")),
+            Either::Left(Bytes::from_static(b"\xff"))
+        );
+        assert_eq!(parse_int(b" 12 "), Some(12));
+    }
+
+    #[tokio::test]
+    async fn test_incr_float_not_finite() {
+        let db = RedisDatabase::default();
+        let key = Bytes::from("missing");
+        assert!(matches!(
+            db.incr_float_value(key.clone(), f64::INFINITY).await,
+            Err(DatabaseError::NanOrInfinity)
+        ));
+        assert!(!db.keyspace.read().await.contains_key(&key));
     }
 }
@@ -0,0 +1,366 @@
+use std::{collections::VecDeque, time::SystemTime};
+
+use bytes::Bytes;
+use either::Either;
+use hashbrown::HashMap;
+use indexmap::{IndexMap, IndexSet};
+use rand::Rng;
+use tokio::time::Instant;
+
+use crate::{
+    database::{Hash, Set, SortedSet},
+    id::Id,
+};
+
+pub type Stream = IndexMap<Id, HashMap<Bytes, Bytes>>;
+
+/// Every kind of value that can live under a key
+#[derive(Debug, Clone)]
+pub enum RedisValue {
+    String(Either<Bytes, i64>),
+    List(VecDeque<Bytes>),
+    Stream(Stream),
+    SortedSet(SortedSet),
+    Hash(Hash),
+    Set(Set),
+}
+
+impl RedisValue {
+    /// The name reported by `TYPE`
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            RedisValue::String(_) => "string",
+            RedisValue::List(_) => "list",
+            RedisValue::Stream(_) => "stream",
+            RedisValue::SortedSet(_) => "zset",
+            RedisValue::Hash(_) => "hash",
+            RedisValue::Set(_) => "set",
+        }
+    }
+}
+
+/// Maps a concrete container type to its [`RedisValue`] variant so that
+/// lookups can be typed and fail with `WRONGTYPE` on a mismatch.
+pub trait ValueType: Sized {
+    fn from_value(value: &RedisValue) -> Option<&Self>;
+    fn from_value_mut(value: &mut RedisValue) -> Option<&mut Self>;
+    fn into_value(self) -> RedisValue;
+}
+
+macro_rules! value_type {
+    ($ty:ty, $variant:ident) => {
+        impl ValueType for $ty {
+            fn from_value(value: &RedisValue) -> Option<&Self> {
+                if let RedisValue::$variant(inner) = value {
+                    Some(inner)
+                } else {
+                    None
+                }
+            }
+            fn from_value_mut(value: &mut RedisValue) -> Option<&mut Self> {
+                if let RedisValue::$variant(inner) = value {
+                    Some(inner)
+                } else {
+                    None
+                }
+            }
+            fn into_value(self) -> RedisValue {
+                RedisValue::$variant(self)
+            }
+        }
+    };
+}
+
+value_type!(Either<Bytes, i64>, String);
+value_type!(VecDeque<Bytes>, List);
+value_type!(Stream, Stream);
+value_type!(SortedSet, SortedSet);
+value_type!(Hash, Hash);
+value_type!(Set, Set);
+
+#[derive(Debug, Clone)]
+pub struct DatabaseValue {
+    pub(crate) value: RedisValue,
+    pub(crate) expiry: Option<Either<Instant, SystemTime>>,
+}
+
+impl DatabaseValue {
+    pub fn new(value: RedisValue, expiry: Option<Either<Instant, SystemTime>>) -> Self {
+        Self { value, expiry }
+    }
+    /// A hash whose fields have all expired counts as expired as well
+    pub fn is_expired(&self) -> bool {
+        let key_expired = if let Some(expiry) = self.expiry {
+            match expiry {
+                Either::Left(expiry) => Instant::now() > expiry,
+                Either::Right(expiry) => SystemTime::now() > expiry,
+            }
+        } else {
+            false
+        };
+        key_expired || matches!(&self.value, RedisValue::Hash(hash) if hash.all_expired())
+    }
+    /// Whether the active expire cycle needs to look at the value
+    fn has_ttl(&self) -> bool {
+        self.expiry.is_some()
+            || matches!(&self.value, RedisValue::Hash(hash) if hash.has_field_ttls())
+    }
+}
+
+/// The single map holding every key regardless of type.
+///
+/// Expired values are never handed out; the mutable accessors drop them
+/// before looking the key up again.
+#[derive(Default)]
+pub struct Keyspace {
+    values: HashMap<Bytes, DatabaseValue>,
+    /// Keys that were given a TTL, sampled by the active expire cycle. It can
+    /// still hold keys that were deleted or persisted since, those are dropped
+    /// once they get sampled.
+    volatile: IndexSet<Bytes>,
+    pub(crate) stats: ExpireStats,
+}
+
+/// What one [`Keyspace::expire_sample`] call looked at and reclaimed
+#[derive(Default, Debug)]
+pub struct ExpireSample {
+    pub sampled: usize,
+    pub keys: Vec<Bytes>,
+    /// Hashes that lost fields without being deleted
+    pub fields: Vec<(Bytes, Vec<Bytes>)>,
+}
+
+impl ExpireSample {
+    /// Keys that had something reclaimed
+    pub fn stale(&self) -> usize {
+        self.keys.len() + self.fields.len()
+    }
+}
+
+#[derive(Default, Debug, Clone, Copy)]
+pub struct ExpireStats {
+    pub expired_keys: u64,
+    pub expired_stale_perc: f64,
+    pub time_cap_reached_count: u64,
+    pub cycle_cpu_milliseconds: u64,
+}
+
+impl Keyspace {
+    pub fn get(&self, key: &Bytes) -> Option<&DatabaseValue> {
+        self.values.get(key).filter(|value| !value.is_expired())
+    }
+    pub fn get_mut(&mut self, key: &Bytes) -> Option<&mut DatabaseValue> {
+        self.purge_expired(key);
+        self.values.get_mut(key)
+    }
+    pub fn contains_key(&self, key: &Bytes) -> bool {
+        self.get(key).is_some()
+    }
+    pub fn get_as<T: ValueType>(&self, key: &Bytes) -> Result<Option<&T>, DatabaseError> {
+        match self.get(key) {
+            Some(value) => T::from_value(&value.value)
+                .map(Some)
+                .ok_or(DatabaseError::WrongType),
+            None => Ok(None),
+        }
+    }
+    pub fn get_as_mut<T: ValueType>(
+        &mut self,
+        key: &Bytes,
+    ) -> Result<Option<&mut T>, DatabaseError> {
+        match self.get_mut(key) {
+            Some(value) => T::from_value_mut(&mut value.value)
+                .map(Some)
+                .ok_or(DatabaseError::WrongType),
+            None => Ok(None),
+        }
+    }
+    pub fn get_or_insert_with_as<T: ValueType>(
+        &mut self,
+        key: &Bytes,
+        default: impl FnOnce() -> T,
+    ) -> Result<&mut T, DatabaseError> {
+        self.purge_expired(key);
+        let value = self
+            .values
+            .entry(key.clone())
+            .or_insert_with(|| DatabaseValue::new(default().into_value(), None));
+        T::from_value_mut(&mut value.value).ok_or(DatabaseError::WrongType)
+    }
+    pub fn get_or_insert_as<T: ValueType + Default>(
+        &mut self,
+        key: &Bytes,
+    ) -> Result<&mut T, DatabaseError> {
+        self.get_or_insert_with_as(key, T::default)
+    }
+    pub fn insert(&mut self, key: Bytes, value: DatabaseValue) -> Option<DatabaseValue> {
+        let old = self.take(&key);
+        if value.has_ttl() {
+            self.volatile.insert(key.clone());
+        }
+        self.values.insert(key, value);
+        old
+    }
+    pub fn remove(&mut self, key: &Bytes) -> Option<DatabaseValue> {
+        self.take(key)
+    }
+    /// Sets or clears the TTL of an existing key, returning `false` if it's missing
+    pub fn set_expiry(&mut self, key: &Bytes, expiry: Option<Either<Instant, SystemTime>>) -> bool {
+        let Some(value) = self.get_mut(key) else {
+            return false;
+        };
+        value.expiry = expiry;
+        if expiry.is_some() {
+            self.volatile.insert(key.clone());
+        }
+        true
+    }
+    /// Lets the active expire cycle find a key whose hash fields have TTLs
+    pub fn track_field_ttls(&mut self, key: &Bytes) {
+        if self.values.contains_key(key) {
+            self.volatile.insert(key.clone());
+        }
+    }
+    pub fn key_count(&self) -> usize {
+        self.values.len()
+    }
+    pub fn volatile_count(&self) -> usize {
+        self.values
+            .values()
+            .filter(|value| value.expiry.is_some())
+            .count()
+    }
+    /// Checks up to `count` random keys with a TTL, deleting the expired ones
+    /// along with the expired fields of hashes.
+    pub fn expire_sample(&mut self, count: usize) -> ExpireSample {
+        let mut sample = ExpireSample::default();
+        let mut rng = rand::rng();
+        while sample.sampled < count && !self.volatile.is_empty() {
+            let idx = rng.random_range(0..self.volatile.len());
+            let Some(key) = self.volatile.get_index(idx).cloned() else {
+                break;
+            };
+            let Some(value) = self.values.get_mut(&key).filter(|value| value.has_ttl()) else {
+                self.volatile.swap_remove_index(idx);
+                continue;
+            };
+            sample.sampled += 1;
+            if value.is_expired() {
+                self.take(&key);
+                sample.keys.push(key);
+            } else if let RedisValue::Hash(hash) = &mut value.value {
+                let fields = hash.purge_expired();
+                if !fields.is_empty() {
+                    sample.fields.push((key, fields));
+                }
+            }
+        }
+        sample
+    }
+    /// Removes `key`, only returning the value if it hadn't expired
+    fn take(&mut self, key: &Bytes) -> Option<DatabaseValue> {
+        let value = self.values.remove(key)?;
+        if value.has_ttl() {
+            self.volatile.swap_remove(key);
+        }
+        if value.is_expired() {
+            self.stats.expired_keys += 1;
+            None
+        } else {
+            Some(value)
+        }
+    }
+    pub fn keys(&self) -> impl Iterator<Item = &Bytes> {
+        self.values
+            .iter()
+            .filter(|(_, value)| !value.is_expired())
+            .map(|(key, _)| key)
+    }
+    fn purge_expired(&mut self, key: &Bytes) {
+        if self.values.get(key).is_some_and(|value| value.is_expired()) {
+            self.take(key);
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum DatabaseError {
+    #[error("WRONGTYPE Operation against a key holding the wrong kind of value")]
+    WrongType,
+    #[error("ERR no such key")]
+    NoSuchKey,
+    #[error("ERR index out of range")]
+    IndexOutOfRange,
+    #[error("ERR source and destination objects are the same")]
+    SameObject,
+    #[error("ERR could not decode requested zset member")]
+    UnknownMember,
+    #[error("UNBLOCKED client unblocked via CLIENT UNBLOCK")]
+    Unblocked,
+    #[error("ERR value is not an integer or out of range")]
+    NotAnInteger,
+    #[error("ERR value is out of range")]
+    OutOfRange,
+    #[error("ERR increment or decrement would overflow")]
+    IncrOverflow,
+    #[error("ERR value is not a valid float")]
+    NotAFloat,
+    #[error("ERR increment would produce NaN or Infinity")]
+    NanOrInfinity,
+    #[error("ERR resulting score is not a number (NaN)")]
+    ScoreNan,
+    #[error("ERR hash value is not an integer")]
+    HashNotInteger,
+    #[error("ERR hash value is not a float")]
+    HashNotFloat,
+    #[error("WRONGTYPE Key is not a valid HyperLogLog string value.")]
+    InvalidHll,
+    #[error("INVALIDOBJ Corrupted HLL object detected")]
+    CorruptHll,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wrong_type() {
+        let mut keyspace = Keyspace::default();
+        let key = Bytes::from("key");
+        keyspace
+            .get_or_insert_as::<VecDeque<Bytes>>(&key)
+            .unwrap()
+            .push_back(Bytes::from("value"));
+        assert!(matches!(
+            keyspace.get_as::<SortedSet>(&key),
+            Err(DatabaseError::WrongType)
+        ));
+        assert!(matches!(
+            keyspace.get_or_insert_as::<Stream>(&key),
+            Err(DatabaseError::WrongType)
+        ));
+        assert_eq!(keyspace.get(&key).unwrap().value.type_name(), "list");
+    }
+
+    #[test]
+    fn test_expire_sample() {
+        let mut keyspace = Keyspace::default();
+        let past = Some(Either::Left(
+            Instant::now() - std::time::Duration::from_secs(1),
+        ));
+        for idx in 0..10 {
+            keyspace.insert(
+                Bytes::from(format!("expired{idx}")),
+                DatabaseValue::new(RedisValue::String(Either::Right(idx)), past),
+            );
+        }
+        keyspace.insert(
+            Bytes::from("persistent"),
+            DatabaseValue::new(RedisValue::String(Either::Right(0)), None),
+        );
+        let sample = keyspace.expire_sample(20);
+        assert_eq!((sample.sampled, sample.keys.len()), (10, 10));
+        assert_eq!(keyspace.key_count(), 1);
+        assert_eq!(keyspace.stats.expired_keys, 10);
+    }
+}
@@ -1,52 +1,126 @@
-use std::collections::VecDeque;
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
 
 use bytes::Bytes;
 use either::Either;
 use tokio::{sync::oneshot, time::Instant};
 
-use crate::{
-    database::{Blocker, RedisDatabase},
-    resp::RedisWrite,
-};
+use crate::database::{Blocker, ClientId, DatabaseError, Keyspace, RedisDatabase};
+
+/// Which end of a list an operation works on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ListEnd {
+    Left,
+    Right,
+}
+
+/// Where `LINSERT` puts the element relative to the pivot
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InsertPosition {
+    Before,
+    After,
+}
+
+/// The options of `LPOS`
+#[derive(Debug, Clone, Copy)]
+pub struct PositionOptions {
+    /// Skip to the nth match, counting from the tail when negative
+    pub rank: i64,
+    /// How many matches to return, `0` for all of them
+    pub count: usize,
+    /// How many elements to compare, `0` for the whole list
+    pub max_len: usize,
+}
+
+impl Default for PositionOptions {
+    fn default() -> Self {
+        Self {
+            rank: 1,
+            count: 1,
+            max_len: 0,
+        }
+    }
+}
+
+fn pop_end(list: &mut VecDeque<Bytes>, end: ListEnd) -> Option<Bytes> {
+    match end {
+        ListEnd::Left => list.pop_front(),
+        ListEnd::Right => list.pop_back(),
+    }
+}
+
+/// Turns a possibly negative index into one within the list
+fn list_index(list: &VecDeque<Bytes>, index: i64) -> Option<usize> {
+    let index = if index < 0 {
+        list.len() as i64 + index
+    } else {
+        index
+    };
+    (0..list.len() as i64)
+        .contains(&index)
+        .then_some(index as usize)
+}
 
 impl RedisDatabase {
-    pub async fn push_list(&self, key: &Bytes, values: Vec<Bytes>) -> i64 {
-        let output = {
-            let mut lists = self.lists.write().await;
-            if let Some(list) = lists.get_mut(key) {
-                list.extend(values.iter().cloned());
-                list.len() as i64
-            } else {
-                let list = VecDeque::from(values);
-                let len = list.len();
-                lists.insert(key.clone(), list);
-                len as i64
-            }
-        };
-        self.handle_list_blocklist(key).await;
-        output
+    pub async fn push_list(&self, key: &Bytes, values: Vec<Bytes>) -> Result<i64, DatabaseError> {
+        self.push_values(key, values, ListEnd::Right, true).await
+    }
+    pub async fn prepend_list(
+        &self,
+        key: &Bytes,
+        values: Vec<Bytes>,
+    ) -> Result<i64, DatabaseError> {
+        self.push_values(key, values, ListEnd::Left, true).await
     }
-    pub async fn prepend_list(&self, key: &Bytes, values: Vec<Bytes>) -> i64 {
+    /// Pushes onto an existing list only, replying `0` when there's none
+    pub async fn push_list_existing(
+        &self,
+        key: &Bytes,
+        values: Vec<Bytes>,
+        end: ListEnd,
+    ) -> Result<i64, DatabaseError> {
+        self.push_values(key, values, end, false).await
+    }
+    async fn push_values(
+        &self,
+        key: &Bytes,
+        values: Vec<Bytes>,
+        end: ListEnd,
+        create: bool,
+    ) -> Result<i64, DatabaseError> {
         let output = {
-            let mut lists = self.lists.write().await;
-            if let Some(list) = lists.get_mut(key) {
-                for value in values {
-                    list.push_front(value);
-                }
-                list.len() as i64
+            let mut keyspace = self.keyspace.write().await;
+            let list = if create {
+                keyspace.get_or_insert_as::<VecDeque<Bytes>>(key)?
             } else {
-                let list = VecDeque::from_iter(values.iter().cloned().rev());
-                let len = list.len() as i64;
-                lists.insert(key.clone(), list);
-                len
+                match keyspace.get_as_mut::<VecDeque<Bytes>>(key)? {
+                    Some(list) => list,
+                    None => return Ok(0),
+                }
+            };
+            match end {
+                ListEnd::Left => {
+                    for value in values {
+                        list.push_front(value);
+                    }
+                }
+                ListEnd::Right => list.extend(values),
             }
+            list.len() as i64
         };
         self.handle_list_blocklist(key).await;
-        output
+        Ok(output)
     }
-    pub async fn range_list(&self, key: &Bytes, start: i64, stop: i64) -> Vec<Bytes> {
-        let lists = self.lists.read().await;
-        if let Some(list) = lists.get(key) {
+    pub async fn range_list(
+        &self,
+        key: &Bytes,
+        start: i64,
+        stop: i64,
+    ) -> Result<Vec<Bytes>, DatabaseError> {
+        let keyspace = self.keyspace.read().await;
+        if let Some(list) = keyspace.get_as::<VecDeque<Bytes>>(key)? {
             let mut start = start;
             let mut stop = stop;
             if start.is_negative() {
@@ -56,104 +130,463 @@ impl RedisDatabase {
                 stop = (list.len() as i64 + stop).max(0);
             }
             if start > stop || start as usize >= list.len() {
-                vec![]
+                Ok(vec![])
             } else {
-                list.range(start as usize..=(stop as usize).min(list.len() - 1))
+                Ok(list
+                    .range(start as usize..=(stop as usize).min(list.len() - 1))
                     .cloned()
-                    .collect()
+                    .collect())
             }
         } else {
-            vec![]
+            Ok(vec![])
         }
     }
-    pub async fn list_len(&self, key: &Bytes) -> i64 {
-        let lists = self.lists.read().await;
-        if let Some(list) = lists.get(key) {
-            list.len() as i64
+    pub async fn list_len(&self, key: &Bytes) -> Result<i64, DatabaseError> {
+        let keyspace = self.keyspace.read().await;
+        if let Some(list) = keyspace.get_as::<VecDeque<Bytes>>(key)? {
+            Ok(list.len() as i64)
         } else {
-            0
+            Ok(0)
         }
     }
 
-    pub async fn pop_list(&self, key: &Bytes, count: Option<u64>) -> Vec<Bytes> {
-        let mut lists = self.lists.write().await;
-        if let Some(list) = lists.get_mut(key) {
-            if let Some(count) = count {
-                let count = list.len().min(count as usize);
-                let mut result = vec![];
-                for _ in 0..count {
-                    if let Some(value) = list.pop_front() {
-                        result.push(value);
-                    } else {
-                        break;
-                    }
+    /// Pops one element, or up to `count` of them, deleting the key once
+    /// the list is empty. `None` when there's no list under the key.
+    pub async fn pop_list(
+        &self,
+        key: &Bytes,
+        end: ListEnd,
+        count: Option<u64>,
+    ) -> Result<Option<Vec<Bytes>>, DatabaseError> {
+        let mut keyspace = self.keyspace.write().await;
+        if keyspace.get_as::<VecDeque<Bytes>>(key)?.is_none() {
+            return Ok(None);
+        }
+        pop_elements(&mut keyspace, key, end, count.unwrap_or(1) as usize).map(Some)
+    }
+    /// Pops up to `count` elements from the first non-empty list among `keys`,
+    /// like `LMPOP`
+    pub async fn pop_lists(
+        &self,
+        keys: &[Bytes],
+        end: ListEnd,
+        count: usize,
+    ) -> Result<Option<BlpopResponse>, DatabaseError> {
+        let mut keyspace = self.keyspace.write().await;
+        for key in keys {
+            let values = pop_elements(&mut keyspace, key, end, count)?;
+            if !values.is_empty() {
+                return Ok(Some(BlpopResponse {
+                    key: key.clone(),
+                    values,
+                }));
+            }
+        }
+        Ok(None)
+    }
+    pub async fn list_index(
+        &self,
+        key: &Bytes,
+        index: i64,
+    ) -> Result<Option<Bytes>, DatabaseError> {
+        let keyspace = self.keyspace.read().await;
+        Ok(keyspace
+            .get_as::<VecDeque<Bytes>>(key)?
+            .and_then(|list| list.get(list_index(list, index)?))
+            .cloned())
+    }
+    pub async fn list_set(
+        &self,
+        key: &Bytes,
+        index: i64,
+        value: Bytes,
+    ) -> Result<(), DatabaseError> {
+        let mut keyspace = self.keyspace.write().await;
+        let list = keyspace
+            .get_as_mut::<VecDeque<Bytes>>(key)?
+            .ok_or(DatabaseError::NoSuchKey)?;
+        let index = list_index(list, index).ok_or(DatabaseError::IndexOutOfRange)?;
+        list[index] = value;
+        Ok(())
+    }
+    /// Returns the new length, `-1` when the pivot wasn't found and `0` when
+    /// the key is missing
+    pub async fn list_insert(
+        &self,
+        key: &Bytes,
+        position: InsertPosition,
+        pivot: &Bytes,
+        value: Bytes,
+    ) -> Result<i64, DatabaseError> {
+        let mut keyspace = self.keyspace.write().await;
+        let Some(list) = keyspace.get_as_mut::<VecDeque<Bytes>>(key)? else {
+            return Ok(0);
+        };
+        let Some(index) = list.iter().position(|element| element == pivot) else {
+            return Ok(-1);
+        };
+        match position {
+            InsertPosition::Before => list.insert(index, value),
+            InsertPosition::After => list.insert(index + 1, value),
+        }
+        Ok(list.len() as i64)
+    }
+    /// Removes up to `count` occurrences of `value`, from the tail when
+    /// `count` is negative and all of them when it's `0`
+    pub async fn list_remove(
+        &self,
+        key: &Bytes,
+        count: i64,
+        value: &Bytes,
+    ) -> Result<usize, DatabaseError> {
+        let mut keyspace = self.keyspace.write().await;
+        let Some(list) = keyspace.get_as_mut::<VecDeque<Bytes>>(key)? else {
+            return Ok(0);
+        };
+        let limit = if count == 0 {
+            usize::MAX
+        } else {
+            count.unsigned_abs() as usize
+        };
+        let mut removed = 0;
+        if count < 0 {
+            let mut index = list.len();
+            while index > 0 && removed < limit {
+                index -= 1;
+                if list[index] == value {
+                    list.remove(index);
+                    removed += 1;
                 }
-                result
-            } else if let Some(out) = list.pop_front() {
-                vec![out]
-            } else {
-                vec![]
             }
         } else {
-            vec![]
+            let mut index = 0;
+            while index < list.len() && removed < limit {
+                if list[index] == value {
+                    list.remove(index);
+                    removed += 1;
+                } else {
+                    index += 1;
+                }
+            }
         }
+        if list.is_empty() {
+            keyspace.remove(key);
+        }
+        Ok(removed)
     }
-    pub async fn blocking_pop_list(
+    /// Keeps only the elements between `start` and `stop`, inclusive
+    pub async fn list_trim(&self, key: &Bytes, start: i64, stop: i64) -> Result<(), DatabaseError> {
+        let mut keyspace = self.keyspace.write().await;
+        let Some(list) = keyspace.get_as_mut::<VecDeque<Bytes>>(key)? else {
+            return Ok(());
+        };
+        let len = list.len() as i64;
+        let start = if start < 0 {
+            (len + start).max(0)
+        } else {
+            start
+        };
+        let stop = if stop < 0 {
+            len + stop
+        } else {
+            stop.min(len - 1)
+        };
+        if start > stop || start >= len {
+            list.clear();
+        } else {
+            list.truncate(stop as usize + 1);
+            list.drain(..start as usize);
+        }
+        if list.is_empty() {
+            keyspace.remove(key);
+        }
+        Ok(())
+    }
+    /// The indexes of the elements matching `value`
+    pub async fn list_positions(
         &self,
         key: &Bytes,
+        value: &Bytes,
+        options: PositionOptions,
+    ) -> Result<Vec<usize>, DatabaseError> {
+        let keyspace = self.keyspace.read().await;
+        let Some(list) = keyspace.get_as::<VecDeque<Bytes>>(key)? else {
+            return Ok(vec![]);
+        };
+        let max_len = if options.max_len == 0 {
+            list.len()
+        } else {
+            options.max_len
+        };
+        let count = if options.count == 0 {
+            usize::MAX
+        } else {
+            options.count
+        };
+        let skip = options.rank.unsigned_abs() as usize - 1;
+        let matches = |(_, element): &(usize, &Bytes)| *element == value;
+        let positions = if options.rank > 0 {
+            list.iter()
+                .enumerate()
+                .take(max_len)
+                .filter(matches)
+                .skip(skip)
+                .take(count)
+                .map(|(index, _)| index)
+                .collect()
+        } else {
+            list.iter()
+                .enumerate()
+                .rev()
+                .take(max_len)
+                .filter(matches)
+                .skip(skip)
+                .take(count)
+                .map(|(index, _)| index)
+                .collect()
+        };
+        Ok(positions)
+    }
+    /// Moves an element from one list to another in one step, replying
+    /// `None` when `source` is empty. `destination` is served afterwards
+    /// since it may have clients of its own waiting.
+    pub async fn list_move(
+        &self,
+        source: &Bytes,
+        destination: &Bytes,
+        from: ListEnd,
+        to: ListEnd,
+    ) -> Result<Option<Bytes>, DatabaseError> {
+        let value = {
+            let mut keyspace = self.keyspace.write().await;
+            move_element(&mut keyspace, source, from, destination, to)?
+        };
+        if value.is_some() {
+            self.handle_list_blocklist(destination).await;
+        }
+        Ok(value)
+    }
+    /// Pops from the first of `keys` that has elements straight away,
+    /// otherwise queues the client up on every key to be served by whichever
+    /// is pushed to first. With a `destination` the element is moved there
+    /// instead, like `BLMOVE`.
+    pub async fn blocking_pop_list(
+        &self,
+        client: ClientId,
+        keys: &[Bytes],
         timeout: Option<Instant>,
-    ) -> Either<BlpopResponse, oneshot::Receiver<BlpopResponse>> {
-        let mut lists = self.lists.write().await;
-        if let Some(list) = lists.get_mut(key)
-            && let Some(value) = list.pop_front()
-        {
-            return Either::Left(BlpopResponse {
-                key: key.clone(),
-                value,
-            });
+        end: ListEnd,
+        count: usize,
+        destination: Option<(Bytes, ListEnd)>,
+    ) -> Result<Either<BlpopResponse, ListReceiver>, DatabaseError> {
+        let response = {
+            let mut keyspace = self.keyspace.write().await;
+            let mut response = None;
+            for key in keys {
+                let values = match &destination {
+                    Some((destination, to)) => {
+                        move_element(&mut keyspace, key, end, destination, *to)?
+                            .into_iter()
+                            .collect()
+                    }
+                    None => pop_elements(&mut keyspace, key, end, count)?,
+                };
+                if !values.is_empty() {
+                    response = Some(BlpopResponse {
+                        key: key.clone(),
+                        values,
+                    });
+                    break;
+                }
+            }
+            let Some(response) = response else {
+                let mut blocklist = self.list_blocklist.lock().await;
+                let (reply, receiver) = oneshot::channel();
+                let reply = Arc::new(Mutex::new(Some(reply)));
+                for key in keys {
+                    let waiter = ListWaiter {
+                        end,
+                        count,
+                        destination: destination.clone(),
+                        reply: reply.clone(),
+                    };
+                    blocklist.push(
+                        key.clone(),
+                        Blocker {
+                            client,
+                            waiter,
+                            timeout,
+                        },
+                    );
+                }
+                return Ok(Either::Right(receiver));
+            };
+            response
+        };
+        if let Some((destination, _)) = &destination {
+            self.handle_list_blocklist(destination).await;
         }
-        let mut blocklist = self.list_blocklist.lock().await;
-        let (sender, receiver) = oneshot::channel::<BlpopResponse>();
-        let waiter = Blocker { sender, timeout };
-        blocklist.entry(key.clone()).or_default().push(waiter);
-        Either::Right(receiver)
+        Ok(Either::Left(response))
     }
 
+    /// Hands elements of `key` to its waiters in the order they blocked.
+    /// Serving a `BLMOVE` pushes onto another list, whose waiters are then
+    /// served in turn.
     pub async fn handle_list_blocklist(&self, key: &Bytes) {
+        // Always take the keyspace before the blocklist, the same order as
+        // `blocking_pop_list`, so the two can't deadlock each other
+        let mut keyspace = self.keyspace.write().await;
         let mut blockers = self.list_blocklist.lock().await;
-        if let Some(waiters) = blockers.get_mut(key) {
-            while !waiters.is_empty() {
-                let waiter = waiters.remove(0);
-                if !waiter.timed_out() {
-                    let mut value = self.pop_list(key, None).await;
-                    if !value.is_empty() {
-                        if let Err(err) = waiter.sender.send(BlpopResponse {
+        let mut ready = vec![key.clone()];
+        while let Some(key) = ready.pop() {
+            while matches!(keyspace.get_as::<VecDeque<Bytes>>(&key), Ok(Some(_)))
+                && let Some(blocker) = blockers.pop_front(&key)
+            {
+                if blocker.timed_out() {
+                    continue;
+                }
+                // Whichever key gets to a client blocked on several first
+                // takes its reply, the other keys then skip it
+                let Some(reply) = blocker.waiter.take_reply() else {
+                    continue;
+                };
+                let ListWaiter {
+                    end,
+                    count,
+                    destination,
+                    ..
+                } = blocker.waiter;
+                let Some((destination, to)) = destination else {
+                    let Ok(values) = pop_elements(&mut keyspace, &key, end, count) else {
+                        break;
+                    };
+                    let response = BlpopResponse {
+                        key: key.clone(),
+                        values,
+                    };
+                    if let Err(Ok(response)) = reply.send(Ok(response)) {
+                        // The client went away in the meantime
+                        for value in response.values.into_iter().rev() {
+                            push_element(&mut keyspace, &key, end, value);
+                        }
+                    }
+                    continue;
+                };
+                match move_element(&mut keyspace, &key, end, &destination, to) {
+                    Ok(Some(value)) => {
+                        let response = BlpopResponse {
                             key: key.clone(),
-                            value: value.remove(0),
-                        }) {
-                            eprintln!("ERROR sending blocklist {err:#?}");
-                            break;
+                            values: vec![value],
+                        };
+                        if reply.send(Ok(response)).is_err() {
+                            // Undo the move for a client that went away
+                            if let Ok(Some(value)) = pop_element(&mut keyspace, &destination, to) {
+                                push_element(&mut keyspace, &key, end, value);
+                            }
                         } else {
-                            break;
+                            ready.push(destination);
                         }
                     }
-                } else {
-                    continue;
+                    Ok(None) => break,
+                    Err(err) => {
+                        let _ = reply.send(Err(err));
+                    }
                 }
             }
         }
     }
 }
 
-#[derive(Debug)]
-pub struct BlpopResponse {
-    pub key: Bytes,
-    pub value: Bytes,
+/// Pops from `key`, deleting it once the list is empty
+fn pop_element(
+    keyspace: &mut Keyspace,
+    key: &Bytes,
+    end: ListEnd,
+) -> Result<Option<Bytes>, DatabaseError> {
+    let Some(list) = keyspace.get_as_mut::<VecDeque<Bytes>>(key)? else {
+        return Ok(None);
+    };
+    let value = pop_end(list, end);
+    if list.is_empty() {
+        keyspace.remove(key);
+    }
+    Ok(value)
+}
+
+/// Pops up to `count` elements from `key`, deleting it once the list is empty
+fn pop_elements(
+    keyspace: &mut Keyspace,
+    key: &Bytes,
+    end: ListEnd,
+    count: usize,
+) -> Result<Vec<Bytes>, DatabaseError> {
+    let Some(list) = keyspace.get_as_mut::<VecDeque<Bytes>>(key)? else {
+        return Ok(vec![]);
+    };
+    let count = list.len().min(count);
+    let popped = (0..count).filter_map(|_| pop_end(list, end)).collect();
+    if list.is_empty() {
+        keyspace.remove(key);
+    }
+    Ok(popped)
+}
+
+/// Puts an element back where it was popped from
+fn push_element(keyspace: &mut Keyspace, key: &Bytes, end: ListEnd, value: Bytes) {
+    if let Ok(list) = keyspace.get_or_insert_as::<VecDeque<Bytes>>(key) {
+        match end {
+            ListEnd::Left => list.push_front(value),
+            ListEnd::Right => list.push_back(value),
+        }
+    }
+}
+
+/// Pops from `source` and pushes onto `destination`. The destination is type
+/// checked before anything is popped.
+fn move_element(
+    keyspace: &mut Keyspace,
+    source: &Bytes,
+    from: ListEnd,
+    destination: &Bytes,
+    to: ListEnd,
+) -> Result<Option<Bytes>, DatabaseError> {
+    keyspace.get_as::<VecDeque<Bytes>>(destination)?;
+    let Some(value) = pop_element(keyspace, source, from)? else {
+        return Ok(None);
+    };
+    push_element(keyspace, destination, to, value.clone());
+    Ok(Some(value))
+}
+
+pub type ListReceiver = oneshot::Receiver<Result<BlpopResponse, DatabaseError>>;
+type ListReply = oneshot::Sender<Result<BlpopResponse, DatabaseError>>;
+
+/// How a client blocked on a list wants to be served. A client blocked on
+/// several keys has a waiter on each, all sharing the one reply.
+pub struct ListWaiter {
+    end: ListEnd,
+    /// How many elements `BLMPOP` pops at most
+    count: usize,
+    /// Where `BLMOVE` pushes the popped element
+    destination: Option<(Bytes, ListEnd)>,
+    reply: Arc<Mutex<Option<ListReply>>>,
 }
 
-impl RedisWrite for BlpopResponse {
-    fn write_to_buf(&self, buf: &mut bytes::BytesMut) {
-        let response = vec![self.key.clone(), self.value.clone()];
-        response.write_to_buf(buf);
+impl ListWaiter {
+    /// Whether the client was already served through another key or went away
+    pub fn is_closed(&self) -> bool {
+        let reply = self.reply.lock().unwrap();
+        reply.as_ref().is_none_or(|reply| reply.is_closed())
     }
+    fn take_reply(&self) -> Option<ListReply> {
+        let mut reply = self.reply.lock().unwrap();
+        reply.take().filter(|reply| !reply.is_closed())
+    }
+}
+
+/// The key a blocked pop was served from and what it popped
+#[derive(Debug)]
+pub struct BlpopResponse {
+    pub key: Bytes,
+    pub values: Vec<Bytes>,
 }
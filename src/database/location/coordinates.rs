@@ -122,14 +122,14 @@ fn spread_int32_to_int64(v: u32) -> u64 {
     (result | (result << 1)) & 0x5555555555555555
 }
 
-fn interleave(x: u32, y: u32) -> u64 {
+pub(super) fn interleave(x: u32, y: u32) -> u64 {
     let x_spread = spread_int32_to_int64(x);
     let y_spread = spread_int32_to_int64(y);
     let y_shifted = y_spread << 1;
     x_spread | y_shifted
 }
 
-fn compact_int64_to_int32(v: u64) -> u32 {
+pub(super) fn compact_int64_to_int32(v: u64) -> u32 {
     let mut result = v & 0x5555555555555555;
     result = (result | (result >> 1)) & 0x3333333333333333;
     result = (result | (result >> 2)) & 0x0F0F0F0F0F0F0F0F;
@@ -1,7 +1,7 @@
 use crate::database::Coordinates;
 
 /// Earth's radius in metres
-const EARTH_RADIUS: f64 = 6372797.560856;
+pub(super) const EARTH_RADIUS: f64 = 6372797.560856;
 
 // https://en.wikipedia.org/wiki/Haversine_formula
 // The basic Haversine formula is d=r𝜃 where
@@ -12,20 +12,41 @@ const EARTH_RADIUS: f64 = 6372797.560856;
 
 impl Coordinates {
     pub fn distance(&self, other: &Self) -> f64 {
-        // Currently the longitudes are in degrees, need to get them to radians
+        get_distance(
+            self.longitude(),
+            self.latitude(),
+            other.longitude(),
+            other.latitude(),
+        )
+    }
+    /// Whether `self` lies in a `width` by `height` box in metres centred on
+    /// `centre`. As in Redis the east-west extent is measured along the
+    /// latitude of `self`.
+    pub fn in_box(&self, centre: &Self, width: f64, height: f64) -> bool {
+        let lat_distance = get_lat_distance(centre.latitude(), self.latitude());
+        let lon_distance = get_distance(
+            centre.longitude(),
+            self.latitude(),
+            self.longitude(),
+            self.latitude(),
+        );
+        lat_distance <= height / 2.0 && lon_distance <= width / 2.0
+    }
+}
 
-        let long1_rad = self.longitude().to_radians();
-        let long2_rad = other.longitude().to_radians();
-        let v = ((long2_rad - long1_rad) / 2.0).sin();
-        if v == 0.0 {
-            get_lat_distance(self.latitude(), other.latitude())
-        } else {
-            let lat1_rad = self.latitude().to_radians();
-            let lat2_rad = other.latitude().to_radians();
-            let u = ((lat2_rad - lat1_rad) / 2.0).sin();
-            let a = u * u + lat1_rad.cos() * lat2_rad.cos() * v * v;
-            2.0 * EARTH_RADIUS * a.sqrt().asin()
-        }
+fn get_distance(long1: f64, lat1: f64, long2: f64, lat2: f64) -> f64 {
+    // Currently the longitudes are in degrees, need to get them to radians
+    let long1_rad = long1.to_radians();
+    let long2_rad = long2.to_radians();
+    let v = ((long2_rad - long1_rad) / 2.0).sin();
+    if v == 0.0 {
+        get_lat_distance(lat1, lat2)
+    } else {
+        let lat1_rad = lat1.to_radians();
+        let lat2_rad = lat2.to_radians();
+        let u = ((lat2_rad - lat1_rad) / 2.0).sin();
+        let a = u * u + lat1_rad.cos() * lat2_rad.cos() * v * v;
+        2.0 * EARTH_RADIUS * a.sqrt().asin()
     }
 }
 
@@ -0,0 +1,233 @@
+use std::ops::Range;
+
+use super::{
+    coordinates::{compact_int64_to_int32, interleave},
+    distance::EARTH_RADIUS,
+};
+use crate::database::{Coordinates, GeoShape};
+
+/// Bits per axis in the score of a member
+const MAX_STEP: u32 = 26;
+/// Half the length of the equator on a Mercator projection, in metres
+const MERCATOR_MAX: f64 = 20037726.37;
+
+/// One cell of the geohash grid, `step` bits per axis wide
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Cell {
+    latitude: u32,
+    longitude: u32,
+    step: u32,
+}
+
+impl Cell {
+    fn containing(position: &Coordinates, step: u32) -> Self {
+        let hash = position.encode() >> (2 * (MAX_STEP - step));
+        // A position right on the northern or eastern edge would be
+        // truncated into a cell past the grid
+        let last = (1 << step) - 1;
+        Self {
+            latitude: compact_int64_to_int32(hash).min(last),
+            longitude: compact_int64_to_int32(hash >> 1).min(last),
+            step,
+        }
+    }
+    /// The cell `latitude` rows north and `longitude` columns east, wrapping
+    /// around the antimeridian but not past the poles
+    fn neighbour(&self, latitude: i64, longitude: i64) -> Option<Self> {
+        let cells = 1i64 << self.step;
+        let row = self.latitude as i64 + latitude;
+        if !(0..cells).contains(&row) {
+            return None;
+        }
+        Some(Self {
+            latitude: row as u32,
+            longitude: (self.longitude as i64 + longitude).rem_euclid(cells) as u32,
+            step: self.step,
+        })
+    }
+    /// The scores of the members that fall in the cell
+    fn scores(&self) -> Range<u64> {
+        let shift = 2 * (MAX_STEP - self.step);
+        let hash = interleave(self.latitude, self.longitude);
+        hash << shift..(hash + 1) << shift
+    }
+    fn height(&self) -> f64 {
+        Coordinates::LATITUDE_RANGE / (1u64 << self.step) as f64
+    }
+    fn width(&self) -> f64 {
+        Coordinates::LONGITUDE_RANGE / (1u64 << self.step) as f64
+    }
+    /// Whether the cell and its eight neighbours cover the box of latitudes
+    /// and longitudes
+    fn block_covers(&self, latitudes: Range<f64>, longitudes: Range<f64>) -> bool {
+        let south = Coordinates::MIN_LATITUDE + self.height() * (self.latitude as f64 - 1.0);
+        let west = Coordinates::MIN_LONGITUDE + self.width() * (self.longitude as f64 - 1.0);
+        south <= latitudes.start
+            && latitudes.end <= south + 3.0 * self.height()
+            && west <= longitudes.start
+            && longitudes.end <= west + 3.0 * self.width()
+    }
+}
+
+/// The coarsest step whose cells are still at least `radius` wide, as Redis
+/// estimates it. Cells are narrower towards the poles so they step down a
+/// level or two there.
+fn estimate_step(radius: f64, latitude: f64) -> u32 {
+    if radius == 0.0 {
+        return MAX_STEP;
+    }
+    let mut step = 1i32;
+    let mut range = radius;
+    while range < MERCATOR_MAX {
+        range *= 2.0;
+        step += 1;
+    }
+    step -= 2;
+    if latitude.abs() > 66.0 {
+        step -= 1;
+        if latitude.abs() > 80.0 {
+            step -= 1;
+        }
+    }
+    step.clamp(1, MAX_STEP as i32) as u32
+}
+
+/// The latitudes and longitudes the shape spans around `centre`
+fn bounding_box(centre: &Coordinates, shape: &GeoShape) -> (Range<f64>, Range<f64>) {
+    let half_height = match *shape {
+        GeoShape::Radius(radius) => radius,
+        GeoShape::Box { height, .. } => height / 2.0,
+    };
+    let latitude_delta = (half_height / EARTH_RADIUS).to_degrees();
+    let south = (centre.latitude() - latitude_delta).max(Coordinates::MIN_LATITUDE);
+    let north = (centre.latitude() + latitude_delta).min(Coordinates::MAX_LATITUDE);
+    // The sine of half the longitudes spanned, which is past 1 once the shape
+    // reaches all the way around
+    let sine = match *shape {
+        // The widest point of a circle on the sphere
+        GeoShape::Radius(radius) => {
+            let angle = (radius / EARTH_RADIUS).min(std::f64::consts::FRAC_PI_2);
+            angle.sin() / centre.latitude().to_radians().cos()
+        }
+        // A box is measured along each row, the one nearest a pole being the
+        // widest
+        GeoShape::Box { width, .. } => {
+            let widest = south.abs().max(north.abs()).to_radians();
+            (width / 4.0 / EARTH_RADIUS)
+                .min(std::f64::consts::FRAC_PI_2)
+                .sin()
+                / widest.cos()
+        }
+    };
+    let longitude_delta = match *shape {
+        _ if sine >= 1.0 => Coordinates::MAX_LONGITUDE,
+        GeoShape::Radius(_) => sine.asin().to_degrees(),
+        GeoShape::Box { .. } => 2.0 * sine.asin().to_degrees(),
+    };
+    (
+        south..north,
+        centre.longitude() - longitude_delta..centre.longitude() + longitude_delta,
+    )
+}
+
+/// The ranges of member scores that can fall within `shape` around `centre`:
+/// those of the cell holding the centre and its eight neighbours, at a step
+/// where the nine of them cover the whole shape. Members found in them still
+/// need their distance checked.
+pub fn score_ranges(centre: &Coordinates, shape: &GeoShape) -> Vec<Range<u64>> {
+    let radius = match *shape {
+        GeoShape::Radius(radius) => radius,
+        GeoShape::Box { width, height } => (width / 2.0).hypot(height / 2.0),
+    };
+    let (latitudes, longitudes) = bounding_box(centre, shape);
+    let mut step = estimate_step(radius, centre.latitude());
+    let mut cell = Cell::containing(centre, step);
+    while step > 1 && !cell.block_covers(latitudes.clone(), longitudes.clone()) {
+        step -= 1;
+        cell = Cell::containing(centre, step);
+    }
+    let mut ranges: Vec<Range<u64>> = (-1..=1)
+        .flat_map(|latitude| (-1..=1).map(move |longitude| (latitude, longitude)))
+        .filter_map(|(latitude, longitude)| cell.neighbour(latitude, longitude))
+        .map(|cell| cell.scores())
+        .collect();
+    // Near the poles and at coarse steps the neighbours can repeat, and
+    // adjacent cells often make one longer scan
+    ranges.sort_by_key(|range| range.start);
+    let mut merged: Vec<Range<u64>> = Vec::with_capacity(ranges.len());
+    for range in ranges {
+        match merged.last_mut() {
+            Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+            _ => merged.push(range),
+        }
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+    use rand::Rng;
+
+    use super::*;
+    use crate::database::{GeoOrder, GeoOrigin, GeoSearch};
+
+    #[test]
+    fn test_matches_full_scan() {
+        let mut rng = rand::rng();
+        let random_position = |rng: &mut rand::rngs::ThreadRng, latitudes: Range<f64>| {
+            Coordinates::new(
+                rng.random_range(latitudes),
+                rng.random_range(Coordinates::MIN_LONGITUDE..Coordinates::MAX_LONGITUDE),
+            )
+            .unwrap()
+        };
+        let mut members: Vec<(Bytes, f64)> = (0..3000)
+            .map(|idx| {
+                let position = random_position(&mut rng, -85.0..85.0);
+                (Bytes::from(format!("m{idx}")), position.encode() as f64)
+            })
+            .collect();
+        members.sort_by(|a, b| a.1.total_cmp(&b.1));
+        for idx in 0..200 {
+            // Some searches right by the poles and the antimeridian
+            let centre = match idx % 4 {
+                0 => random_position(&mut rng, 80.0..85.0),
+                1 => Coordinates::new(rng.random_range(-60.0..60.0), 179.9).unwrap(),
+                _ => random_position(&mut rng, -85.0..85.0),
+            };
+            let size = 10f64.powf(rng.random_range(3.0..7.0));
+            let shape = if idx % 2 == 0 {
+                GeoShape::Radius(size)
+            } else {
+                GeoShape::Box {
+                    width: size,
+                    height: size * rng.random_range(0.2..5.0),
+                }
+            };
+            let query = GeoSearch {
+                origin: GeoOrigin::Position(centre),
+                shape,
+                order: Some(GeoOrder::Asc),
+                count: None,
+                any: false,
+            };
+            let names = |found: Vec<crate::database::GeoMatch>| {
+                found
+                    .into_iter()
+                    .map(|found| found.member)
+                    .collect::<Vec<_>>()
+            };
+            let full_scan = names(query.select(&centre, members.iter().map(|(m, s)| (m, *s))));
+            let candidates = score_ranges(&centre, &shape)
+                .into_iter()
+                .flat_map(|scores| {
+                    members
+                        .iter()
+                        .filter(move |(_, score)| scores.contains(&(*score as u64)))
+                        .map(|(member, score)| (member, *score))
+                });
+            assert_eq!(names(query.select(&centre, candidates)), full_scan);
+        }
+    }
+}
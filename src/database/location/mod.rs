@@ -1,4 +1,4 @@
 use crate::mod_flat;
 
-mod_flat!(coordinates);
+mod_flat!(coordinates search geohash);
 mod distance;
@@ -0,0 +1,169 @@
+use bytes::Bytes;
+
+use crate::database::Coordinates;
+
+/// The units `GEOSEARCH` takes distances in and replies with
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum GeoUnit {
+    #[default]
+    Metres,
+    Kilometres,
+    Feet,
+    Miles,
+}
+
+impl GeoUnit {
+    /// How many metres one of the unit is
+    pub fn metres(self) -> f64 {
+        match self {
+            GeoUnit::Metres => 1.0,
+            GeoUnit::Kilometres => 1000.0,
+            GeoUnit::Feet => 0.3048,
+            GeoUnit::Miles => 1609.34,
+        }
+    }
+}
+
+/// Where a search is centred, either on a member of the set or on a position
+pub enum GeoOrigin {
+    Member(Bytes),
+    Position(Coordinates),
+}
+
+/// The area a search covers, in metres
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GeoShape {
+    Radius(f64),
+    Box { width: f64, height: f64 },
+}
+
+impl GeoShape {
+    /// How far `position` is from `centre`, if it falls within the shape
+    pub fn distance(&self, centre: &Coordinates, position: &Coordinates) -> Option<f64> {
+        let distance = centre.distance(position);
+        let within = match *self {
+            GeoShape::Radius(radius) => distance <= radius,
+            GeoShape::Box { width, height } => position.in_box(centre, width, height),
+        };
+        within.then_some(distance)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GeoOrder {
+    Asc,
+    Desc,
+}
+
+/// A `GEOSEARCH` query, the distances already in metres
+pub struct GeoSearch {
+    pub origin: GeoOrigin,
+    pub shape: GeoShape,
+    pub order: Option<GeoOrder>,
+    pub count: Option<usize>,
+    /// Stop at the first `count` matches rather than the nearest ones
+    pub any: bool,
+}
+
+/// A member found by a search
+pub struct GeoMatch {
+    pub member: Bytes,
+    /// From the centre of the search, in metres
+    pub distance: f64,
+    pub hash: u64,
+    pub position: Coordinates,
+}
+
+impl GeoSearch {
+    /// Keeps the members of `candidates` that fall in the shape around
+    /// `centre`, ordered and cut down to the count asked for
+    pub fn select<'a>(
+        &self,
+        centre: &Coordinates,
+        candidates: impl Iterator<Item = (&'a Bytes, f64)>,
+    ) -> Vec<GeoMatch> {
+        let mut matches = vec![];
+        for (member, score) in candidates {
+            let hash = score as u64;
+            let position = Coordinates::decode(hash);
+            let Some(distance) = self.shape.distance(centre, &position) else {
+                continue;
+            };
+            matches.push(GeoMatch {
+                member: member.clone(),
+                distance,
+                hash,
+                position,
+            });
+            if self.any && self.count.is_some_and(|count| matches.len() >= count) {
+                break;
+            }
+        }
+        // Without ANY a count means the nearest members
+        let order = match self.order {
+            None if self.count.is_some() && !self.any => Some(GeoOrder::Asc),
+            order => order,
+        };
+        match order {
+            Some(GeoOrder::Asc) => matches.sort_by(|a, b| a.distance.total_cmp(&b.distance)),
+            Some(GeoOrder::Desc) => matches.sort_by(|a, b| b.distance.total_cmp(&a.distance)),
+            None => {}
+        }
+        if let Some(count) = self.count {
+            matches.truncate(count);
+        }
+        matches
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_select() {
+        let centre = Coordinates::new(37.0, 15.0).unwrap();
+        let places = [
+            ("Palermo", 38.115556, 13.361389),
+            ("Catania", 37.502669, 15.087269),
+            ("Rome", 41.9028, 12.4964),
+        ]
+        .map(|(name, latitude, longitude)| {
+            let hash = Coordinates::new(latitude, longitude).unwrap().encode();
+            (Bytes::from(name), hash as f64)
+        });
+        let candidates = || places.iter().map(|(member, score)| (member, *score));
+        let members = |query: &GeoSearch| {
+            query
+                .select(&centre, candidates())
+                .into_iter()
+                .map(|found| found.member)
+                .collect::<Vec<_>>()
+        };
+        let mut query = GeoSearch {
+            origin: GeoOrigin::Position(centre),
+            shape: GeoShape::Radius(200_000.0),
+            order: Some(GeoOrder::Desc),
+            count: None,
+            any: false,
+        };
+        assert_eq!(members(&query), ["Palermo", "Catania"]);
+        // A count without an order picks the nearest
+        query.order = None;
+        query.count = Some(1);
+        assert_eq!(members(&query), ["Catania"]);
+        // Palermo is about 143km west and 124km north of the centre
+        query.count = None;
+        query.order = Some(GeoOrder::Asc);
+        query.shape = GeoShape::Box {
+            width: 300_000.0,
+            height: 240_000.0,
+        };
+        assert_eq!(members(&query), ["Catania"]);
+        query.shape = GeoShape::Box {
+            width: 300_000.0,
+            height: 260_000.0,
+        };
+        assert_eq!(members(&query), ["Catania", "Palermo"]);
+    }
+}
@@ -1,5 +1,5 @@
 use crate::mod_flat;
 
-mod_flat!(db key_values lists streams location);
+mod_flat!(db blocking keyspace expiry key_values bitmaps lists streams location hashes sets sorted_sets);
 mod channels;
-mod sorted_sets;
+mod hyperloglog;
@@ -0,0 +1,436 @@
+use bytes::Bytes;
+use either::Either;
+use indexmap::IndexSet;
+use rand::Rng;
+
+use crate::database::{
+    DatabaseError, DatabaseValue, Keyspace, RedisDatabase, RedisValue, string_value,
+};
+
+/// Sets of integers up to this size stay in the compact encoding
+const MAX_INTSET_ENTRIES: usize = 512;
+
+/// An unordered set of members.
+///
+/// Like Redis' intset, small sets holding only integers are kept as a sorted
+/// array of them. Anything else converts the set to a hash set for good.
+#[derive(Debug, Clone)]
+pub enum Set {
+    Ints(Vec<i64>),
+    Members(IndexSet<Bytes>),
+}
+
+impl Default for Set {
+    fn default() -> Self {
+        Set::Ints(vec![])
+    }
+}
+
+/// The integer a member reads as, as long as it reads back unchanged
+fn int_member(member: &Bytes) -> Option<i64> {
+    string_value(member.clone()).right()
+}
+
+impl Set {
+    /// Returns whether the member is new
+    pub fn insert(&mut self, member: Bytes) -> bool {
+        if let Set::Ints(ints) = self {
+            match int_member(&member) {
+                Some(num) => match ints.binary_search(&num) {
+                    Ok(_) => return false,
+                    Err(idx) if ints.len() < MAX_INTSET_ENTRIES => {
+                        ints.insert(idx, num);
+                        return true;
+                    }
+                    Err(_) => self.convert(),
+                },
+                None => self.convert(),
+            }
+        }
+        let Set::Members(members) = self else {
+            unreachable!("converted to a hash set");
+        };
+        members.insert(member)
+    }
+    pub fn contains(&self, member: &Bytes) -> bool {
+        match self {
+            Set::Ints(ints) => {
+                int_member(member).is_some_and(|num| ints.binary_search(&num).is_ok())
+            }
+            Set::Members(members) => members.contains(member),
+        }
+    }
+    /// Returns whether the member was there
+    pub fn remove(&mut self, member: &Bytes) -> bool {
+        match self {
+            Set::Ints(ints) => match int_member(member).map(|num| ints.binary_search(&num)) {
+                Some(Ok(idx)) => {
+                    ints.remove(idx);
+                    true
+                }
+                _ => false,
+            },
+            Set::Members(members) => members.swap_remove(member),
+        }
+    }
+    pub fn len(&self) -> usize {
+        match self {
+            Set::Ints(ints) => ints.len(),
+            Set::Members(members) => members.len(),
+        }
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    pub fn iter(&self) -> impl Iterator<Item = Bytes> + '_ {
+        match self {
+            Set::Ints(ints) => Either::Left(ints.iter().map(|num| Bytes::from(num.to_string()))),
+            Set::Members(members) => Either::Right(members.iter().cloned()),
+        }
+    }
+    fn get_index(&self, idx: usize) -> Option<Bytes> {
+        match self {
+            Set::Ints(ints) => ints.get(idx).map(|num| Bytes::from(num.to_string())),
+            Set::Members(members) => members.get_index(idx).cloned(),
+        }
+    }
+    fn random_member(&self, rng: &mut impl Rng) -> Option<Bytes> {
+        if self.is_empty() {
+            None
+        } else {
+            self.get_index(rng.random_range(0..self.len()))
+        }
+    }
+    fn convert(&mut self) {
+        if let Set::Ints(ints) = self {
+            *self = Set::Members(
+                ints.iter()
+                    .map(|num| Bytes::from(num.to_string()))
+                    .collect(),
+            );
+        }
+    }
+}
+
+/// The set algebra behind `SINTER`, `SUNION` and `SDIFF`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SetOperation {
+    Inter,
+    Union,
+    Diff,
+}
+
+/// Applies `operation` to the sets at `keys`, a missing key counting as an
+/// empty set. An intersection walks the smallest set, checking the others,
+/// and stops once it reaches `limit` members.
+fn combine_sets(
+    keyspace: &Keyspace,
+    keys: &[Bytes],
+    operation: SetOperation,
+    limit: Option<usize>,
+) -> Result<Set, DatabaseError> {
+    let sets = keys
+        .iter()
+        .map(|key| keyspace.get_as::<Set>(key))
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut result = Set::default();
+    match operation {
+        SetOperation::Inter => {
+            let Some(mut sets) = sets.into_iter().collect::<Option<Vec<_>>>() else {
+                return Ok(result);
+            };
+            sets.sort_by_key(|set| set.len());
+            let Some((smallest, rest)) = sets.split_first() else {
+                return Ok(result);
+            };
+            for member in smallest.iter() {
+                if limit.is_some_and(|limit| result.len() >= limit) {
+                    break;
+                }
+                if rest.iter().all(|set| set.contains(&member)) {
+                    result.insert(member);
+                }
+            }
+        }
+        SetOperation::Union => {
+            for member in sets.iter().flatten().flat_map(|set| set.iter()) {
+                result.insert(member);
+            }
+        }
+        SetOperation::Diff => {
+            let Some((Some(first), rest)) = sets.split_first() else {
+                return Ok(result);
+            };
+            for member in first.iter() {
+                if !rest.iter().flatten().any(|set| set.contains(&member)) {
+                    result.insert(member);
+                }
+            }
+        }
+    }
+    Ok(result)
+}
+
+/// Like Redis, the random picks refuse counts whose magnitude could never
+/// be replied to rather than trying to gather that many
+pub(super) fn check_rand_count(count: i64) -> Result<(), DatabaseError> {
+    if (-i64::MAX / 2..=i64::MAX / 2).contains(&count) {
+        Ok(())
+    } else {
+        Err(DatabaseError::OutOfRange)
+    }
+}
+
+impl RedisDatabase {
+    /// Returns how many of the members were new
+    pub async fn set_add(&self, key: &Bytes, members: &[Bytes]) -> Result<usize, DatabaseError> {
+        let mut keyspace = self.keyspace.write().await;
+        let set = keyspace.get_or_insert_as::<Set>(key)?;
+        Ok(members
+            .iter()
+            .filter(|member| set.insert((*member).clone()))
+            .count())
+    }
+    /// Removes the members, deleting the key once the set is empty
+    pub async fn set_remove(&self, key: &Bytes, members: &[Bytes]) -> Result<usize, DatabaseError> {
+        let mut keyspace = self.keyspace.write().await;
+        let Some(set) = keyspace.get_as_mut::<Set>(key)? else {
+            return Ok(0);
+        };
+        let removed = members.iter().filter(|member| set.remove(member)).count();
+        if set.is_empty() {
+            keyspace.remove(key);
+        }
+        Ok(removed)
+    }
+    pub async fn set_members(&self, key: &Bytes) -> Result<Vec<Bytes>, DatabaseError> {
+        let keyspace = self.keyspace.read().await;
+        Ok(keyspace
+            .get_as::<Set>(key)?
+            .map(|set| set.iter().collect())
+            .unwrap_or_default())
+    }
+    pub async fn set_contains(
+        &self,
+        key: &Bytes,
+        members: &[Bytes],
+    ) -> Result<Vec<bool>, DatabaseError> {
+        let keyspace = self.keyspace.read().await;
+        let set = keyspace.get_as::<Set>(key)?;
+        Ok(members
+            .iter()
+            .map(|member| set.is_some_and(|set| set.contains(member)))
+            .collect())
+    }
+    pub async fn set_len(&self, key: &Bytes) -> Result<usize, DatabaseError> {
+        let keyspace = self.keyspace.read().await;
+        Ok(keyspace.get_as::<Set>(key)?.map(Set::len).unwrap_or(0))
+    }
+    /// Removes up to `count` random members, deleting the key once the set
+    /// is empty
+    pub async fn set_pop(&self, key: &Bytes, count: usize) -> Result<Vec<Bytes>, DatabaseError> {
+        let mut keyspace = self.keyspace.write().await;
+        let Some(set) = keyspace.get_as_mut::<Set>(key)? else {
+            return Ok(vec![]);
+        };
+        let mut rng = rand::rng();
+        let mut popped = Vec::with_capacity(count.min(set.len()));
+        while popped.len() < count
+            && let Some(member) = set.random_member(&mut rng)
+        {
+            set.remove(&member);
+            popped.push(member);
+        }
+        if set.is_empty() {
+            keyspace.remove(key);
+        }
+        Ok(popped)
+    }
+    /// Picks `count` random members: distinct ones when positive, possibly
+    /// repeated ones when negative
+    pub async fn set_rand_members(
+        &self,
+        key: &Bytes,
+        count: i64,
+    ) -> Result<Vec<Bytes>, DatabaseError> {
+        check_rand_count(count)?;
+        let keyspace = self.keyspace.read().await;
+        let Some(set) = keyspace.get_as::<Set>(key)? else {
+            return Ok(vec![]);
+        };
+        let mut rng = rand::rng();
+        if count < 0 {
+            Ok((0..count.unsigned_abs())
+                .filter_map(|_| set.random_member(&mut rng))
+                .collect())
+        } else if count as usize >= set.len() {
+            Ok(set.iter().collect())
+        } else {
+            Ok(
+                rand::seq::index::sample(&mut rng, set.len(), count as usize)
+                    .into_iter()
+                    .filter_map(|idx| set.get_index(idx))
+                    .collect(),
+            )
+        }
+    }
+    /// Moves `member` between the sets in one step, returning whether it was
+    /// in `source`
+    pub async fn set_move(
+        &self,
+        source: &Bytes,
+        destination: &Bytes,
+        member: &Bytes,
+    ) -> Result<bool, DatabaseError> {
+        let mut keyspace = self.keyspace.write().await;
+        // A missing source is a no-op whatever the destination holds,
+        // otherwise both keys are type checked before anything changes
+        if keyspace.get_as::<Set>(source)?.is_none() {
+            return Ok(false);
+        }
+        keyspace.get_as::<Set>(destination)?;
+        let Some(set) = keyspace.get_as_mut::<Set>(source)? else {
+            return Ok(false);
+        };
+        if source == destination {
+            return Ok(set.contains(member));
+        }
+        if !set.remove(member) {
+            return Ok(false);
+        }
+        if set.is_empty() {
+            keyspace.remove(source);
+        }
+        keyspace
+            .get_or_insert_as::<Set>(destination)?
+            .insert(member.clone());
+        Ok(true)
+    }
+    pub async fn set_combine(
+        &self,
+        keys: &[Bytes],
+        operation: SetOperation,
+    ) -> Result<Vec<Bytes>, DatabaseError> {
+        let keyspace = self.keyspace.read().await;
+        Ok(combine_sets(&keyspace, keys, operation, None)?
+            .iter()
+            .collect())
+    }
+    /// Replaces `destination` with the result under a single lock, deleting
+    /// it when the result is empty. Returns the size of the result.
+    pub async fn set_combine_store(
+        &self,
+        destination: &Bytes,
+        keys: &[Bytes],
+        operation: SetOperation,
+    ) -> Result<usize, DatabaseError> {
+        let mut keyspace = self.keyspace.write().await;
+        let result = combine_sets(&keyspace, keys, operation, None)?;
+        let len = result.len();
+        if result.is_empty() {
+            keyspace.remove(destination);
+        } else {
+            keyspace.insert(
+                destination.clone(),
+                DatabaseValue::new(RedisValue::Set(result), None),
+            );
+        }
+        Ok(len)
+    }
+    /// The size of the intersection, counting no further than `limit`
+    pub async fn set_inter_card(
+        &self,
+        keys: &[Bytes],
+        limit: Option<usize>,
+    ) -> Result<usize, DatabaseError> {
+        let keyspace = self.keyspace.read().await;
+        Ok(combine_sets(&keyspace, keys, SetOperation::Inter, limit)?.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_intset_conversion() {
+        let mut set = Set::default();
+        assert!(set.insert(Bytes::from("3")));
+        assert!(set.insert(Bytes::from("-1")));
+        assert!(!set.insert(Bytes::from("3")));
+        assert!(matches!(&set, Set::Ints(ints) if ints == &[-1, 3]));
+        // Not canonical so it's kept as bytes
+        assert!(!set.contains(&Bytes::from("03")));
+        assert!(set.insert(Bytes::from("03")));
+        assert!(matches!(set, Set::Members(_)));
+        assert!(set.contains(&Bytes::from("-1")));
+        assert!(set.remove(&Bytes::from("3")));
+        assert_eq!(set.len(), 2);
+
+        let mut set = Set::default();
+        for num in 0..=MAX_INTSET_ENTRIES {
+            set.insert(Bytes::from(num.to_string()));
+        }
+        assert!(matches!(set, Set::Members(_)));
+        assert_eq!(set.len(), MAX_INTSET_ENTRIES + 1);
+    }
+
+    #[test]
+    fn test_combine_sets() {
+        let mut keyspace = Keyspace::default();
+        fn members(members: &[&str]) -> Vec<Bytes> {
+            members.iter().map(|m| Bytes::from(m.to_string())).collect()
+        }
+        for (key, values) in [("a", &["1", "2", "3", "x"][..]), ("b", &["2", "3", "4"])] {
+            let set = keyspace.get_or_insert_as::<Set>(&Bytes::from(key)).unwrap();
+            for member in members(values) {
+                set.insert(member);
+            }
+        }
+        let keys = members(&["a", "b"]);
+        let sorted = |set: Set| {
+            let mut members: Vec<Bytes> = set.iter().collect();
+            members.sort();
+            members
+        };
+        let combine = |keys: &[Bytes], operation, limit| {
+            sorted(combine_sets(&keyspace, keys, operation, limit).unwrap())
+        };
+        assert_eq!(
+            combine(&keys, SetOperation::Inter, None),
+            members(&["2", "3"])
+        );
+        assert_eq!(combine(&keys, SetOperation::Inter, Some(1)).len(), 1);
+        assert_eq!(
+            combine(&keys, SetOperation::Diff, None),
+            members(&["1", "x"])
+        );
+        assert_eq!(combine(&keys, SetOperation::Union, None).len(), 5);
+        let missing = members(&["a", "missing"]);
+        assert!(combine(&missing, SetOperation::Inter, None).is_empty());
+        assert_eq!(combine(&missing, SetOperation::Diff, None).len(), 4);
+    }
+
+    #[tokio::test]
+    async fn test_set_rand_count_out_of_range() {
+        let db = RedisDatabase::default();
+        let key = Bytes::from("key");
+        db.set_add(&key, &[Bytes::from("a")]).await.unwrap();
+        assert!(matches!(
+            db.set_rand_members(&key, i64::MIN).await,
+            Err(DatabaseError::OutOfRange)
+        ));
+        assert_eq!(db.set_rand_members(&key, -3).await.unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_set_move_missing_source() {
+        let db = RedisDatabase::default();
+        let (source, destination) = (Bytes::from("missing"), Bytes::from("list"));
+        db.push_list(&destination, vec![Bytes::from("a")])
+            .await
+            .unwrap();
+        let member = Bytes::from("a");
+        assert!(!db.set_move(&source, &destination, &member).await.unwrap());
+    }
+}
@@ -1,126 +1,1423 @@
+use std::{
+    ops::Range,
+    sync::{Arc, Mutex},
+};
+
 use bytes::Bytes;
+use either::Either;
+use hashbrown::HashMap;
+use rand::Rng;
+use tokio::{sync::oneshot, time::Instant};
 
-use crate::database::{Coordinates, RedisDatabase};
+use crate::database::{
+    Blocker, ClientId, Coordinates, DatabaseError, DatabaseValue, GeoMatch, GeoOrigin, GeoSearch,
+    Keyspace, RedisDatabase, RedisValue, Set, SetOperation, check_rand_count, score_ranges,
+};
 
-impl RedisDatabase {
-    pub async fn insert_set_member(&self, key: Bytes, member: Bytes, score: f64) -> usize {
-        let mut sets = self.sets.write().await;
-        let set = sets.entry(key).or_default();
-        if let Some(current_score) = set.get_mut(&member) {
-            *current_score = score;
-            // set.sort_by(|curr_mem, curr_score, other_mem, other_score| {
-            //     curr_score
-            //         .total_cmp(other_score)
-            //         .then(curr_mem.cmp(other_mem))
-            // });
-            0
-        } else {
-            set.insert_sorted_by(
-                member,
-                score,
-                |curr_mem, curr_score, other_mem, other_score| {
-                    curr_score
-                        .total_cmp(other_score)
-                        .then(curr_mem.cmp(other_mem))
-                },
-            );
-            1
+/// Enough levels for 2^64 members with a quarter of the nodes reaching
+/// each next level
+const MAX_LEVEL: usize = 32;
+const LEVEL_PROBABILITY: f64 = 0.25;
+/// The arena slot of the header node, which holds no member
+const HEAD: usize = 0;
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Link {
+    next: Option<usize>,
+    /// How many nodes the link moves forward, up to the end of the set when
+    /// there's no next node
+    span: usize,
+}
+
+#[derive(Debug, Clone)]
+struct Node {
+    member: Bytes,
+    score: f64,
+    backward: Option<usize>,
+    levels: Vec<Link>,
+}
+
+impl Node {
+    fn is_before(&self, score: f64, member: &Bytes) -> bool {
+        self.score < score || (self.score == score && self.member < *member)
+    }
+}
+
+/// Members ordered by score, then lexicographically.
+///
+/// Like Redis' zset encoding, a hash index holds the score of every member
+/// and a skiplist keeps them in order. Each link records how many members it
+/// skips so ranks are found in O(log n) as well. Nodes live in an arena and
+/// refer to each other by slot.
+#[derive(Clone)]
+pub struct SortedSet {
+    scores: HashMap<Bytes, f64>,
+    nodes: Vec<Node>,
+    /// Arena slots of removed nodes, reused by later inserts
+    free: Vec<usize>,
+    level: usize,
+}
+
+impl Default for SortedSet {
+    fn default() -> Self {
+        let head = Node {
+            member: Bytes::new(),
+            score: 0.0,
+            backward: None,
+            levels: vec![Link::default(); MAX_LEVEL],
+        };
+        Self {
+            scores: HashMap::new(),
+            nodes: vec![head],
+            free: vec![],
+            level: 1,
         }
     }
-    pub async fn get_set_member_rank(&self, key: &Bytes, member: &Bytes) -> Option<usize> {
-        let sets = self.sets.read().await;
-        if let Some(set) = sets.get(key) {
-            set.get_index_of(member)
-        } else {
-            None
+}
+
+impl std::fmt::Debug for SortedSet {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+fn random_level() -> usize {
+    let mut rng = rand::rng();
+    let mut level = 1;
+    while level < MAX_LEVEL && rng.random::<f64>() < LEVEL_PROBABILITY {
+        level += 1;
+    }
+    level
+}
+
+impl SortedSet {
+    pub fn len(&self) -> usize {
+        self.scores.len()
+    }
+    pub fn score(&self, member: &Bytes) -> Option<f64> {
+        self.scores.get(member).copied()
+    }
+    /// Sets the score of `member`, moving it to keep the set ordered. Returns
+    /// whether the member is new.
+    pub fn insert(&mut self, member: Bytes, score: f64) -> bool {
+        let current = self.score(&member);
+        match current {
+            Some(current) if current == score => return false,
+            Some(current) => {
+                self.scores.remove(&member);
+                self.unlink(current, &member);
+            }
+            None => {}
         }
+        self.link(member.clone(), score);
+        self.scores.insert(member, score);
+        current.is_none()
     }
-    pub async fn range_sorted_set(&self, key: &Bytes, start: i64, end: i64) -> Vec<Bytes> {
-        let sets = self.sets.read().await;
-        if let Some(set) = sets.get(key) {
-            let end = if end.is_negative() {
-                (set.len()).saturating_sub(end.unsigned_abs() as usize)
+    pub fn remove(&mut self, member: &Bytes) -> Option<f64> {
+        let score = self.scores.remove(member)?;
+        self.unlink(score, member);
+        Some(score)
+    }
+    /// The 0-based position of `member` in the set
+    pub fn rank(&self, member: &Bytes) -> Option<usize> {
+        let score = self.score(member)?;
+        Some(self.partition_point(|other, other_score| {
+            other_score < score || (other_score == score && other < member)
+        }))
+    }
+    /// How many members come before the first one `pred` is false for. Like
+    /// the slice method, `pred` must hold for a prefix of the set.
+    pub fn partition_point(&self, mut pred: impl FnMut(&Bytes, f64) -> bool) -> usize {
+        let mut rank = 0;
+        let mut x = HEAD;
+        for i in (0..self.level).rev() {
+            while let Some(next) = self.nodes[x].levels[i].next
+                && pred(&self.nodes[next].member, self.nodes[next].score)
+            {
+                rank += self.nodes[x].levels[i].span;
+                x = next;
+            }
+        }
+        rank
+    }
+    /// The members at the 0-based ranks in `range`, in order
+    pub fn range(&self, range: Range<usize>) -> Iter<'_> {
+        let end = range.end.min(self.len());
+        let start = range.start.min(end);
+        Iter {
+            set: self,
+            front: self.node_at(start),
+            back: end.checked_sub(1).and_then(|last| self.node_at(last)),
+            remaining: end - start,
+        }
+    }
+    pub fn iter(&self) -> Iter<'_> {
+        self.range(0..self.len())
+    }
+
+    fn node_at(&self, rank: usize) -> Option<usize> {
+        if rank >= self.len() {
+            return None;
+        }
+        let target = rank + 1;
+        let mut traversed = 0;
+        let mut x = HEAD;
+        for i in (0..self.level).rev() {
+            while let Some(next) = self.nodes[x].levels[i].next
+                && traversed + self.nodes[x].levels[i].span <= target
+            {
+                traversed += self.nodes[x].levels[i].span;
+                x = next;
+            }
+            if traversed == target {
+                return Some(x);
+            }
+        }
+        None
+    }
+    /// Adds a node for a member that isn't in the skiplist yet
+    fn link(&mut self, member: Bytes, score: f64) {
+        // The last node before the new one on every level, and its rank
+        let mut update = [HEAD; MAX_LEVEL];
+        let mut rank = [0; MAX_LEVEL];
+        let mut x = HEAD;
+        for i in (0..self.level).rev() {
+            rank[i] = if i + 1 == self.level { 0 } else { rank[i + 1] };
+            while let Some(next) = self.nodes[x].levels[i].next
+                && self.nodes[next].is_before(score, &member)
+            {
+                rank[i] += self.nodes[x].levels[i].span;
+                x = next;
+            }
+            update[i] = x;
+        }
+        let level = random_level();
+        if level > self.level {
+            for i in self.level..level {
+                self.nodes[HEAD].levels[i].span = self.len();
+            }
+            self.level = level;
+        }
+        let node = Node {
+            member,
+            score,
+            backward: (update[0] != HEAD).then_some(update[0]),
+            levels: vec![Link::default(); level],
+        };
+        let new = match self.free.pop() {
+            Some(slot) => {
+                self.nodes[slot] = node;
+                slot
+            }
+            None => {
+                self.nodes.push(node);
+                self.nodes.len() - 1
+            }
+        };
+        for i in 0..level {
+            let prev = update[i];
+            let Link { next, span } = self.nodes[prev].levels[i];
+            let skipped = rank[0] - rank[i];
+            self.nodes[new].levels[i] = Link {
+                next,
+                span: span - skipped,
+            };
+            self.nodes[prev].levels[i] = Link {
+                next: Some(new),
+                span: skipped + 1,
+            };
+        }
+        for (i, prev) in update.iter().enumerate().take(self.level).skip(level) {
+            self.nodes[*prev].levels[i].span += 1;
+        }
+        if let Some(next) = self.nodes[new].levels[0].next {
+            self.nodes[next].backward = Some(new);
+        }
+    }
+    fn unlink(&mut self, score: f64, member: &Bytes) {
+        let mut update = [HEAD; MAX_LEVEL];
+        let mut x = HEAD;
+        for i in (0..self.level).rev() {
+            while let Some(next) = self.nodes[x].levels[i].next
+                && self.nodes[next].is_before(score, member)
+            {
+                x = next;
+            }
+            update[i] = x;
+        }
+        let Some(target) = self.nodes[x].levels[0]
+            .next
+            .filter(|next| self.nodes[*next].member == *member)
+        else {
+            return;
+        };
+        for (i, &prev) in update.iter().enumerate().take(self.level) {
+            if self.nodes[prev].levels[i].next == Some(target) {
+                let Link { next, span } = self.nodes[target].levels[i];
+                let link = &mut self.nodes[prev].levels[i];
+                link.next = next;
+                link.span = link.span + span - 1;
             } else {
-                (set.len() - 1).min(end as usize)
+                self.nodes[prev].levels[i].span -= 1;
+            }
+        }
+        if let Some(next) = self.nodes[target].levels[0].next {
+            self.nodes[next].backward = self.nodes[target].backward;
+        }
+        while self.level > 1 && self.nodes[HEAD].levels[self.level - 1].next.is_none() {
+            self.level -= 1;
+        }
+        let node = &mut self.nodes[target];
+        node.member = Bytes::new();
+        node.levels = vec![];
+        self.free.push(target);
+    }
+}
+
+/// Walks a range of a [`SortedSet`] from either end
+pub struct Iter<'a> {
+    set: &'a SortedSet,
+    front: Option<usize>,
+    back: Option<usize>,
+    remaining: usize,
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = (&'a Bytes, f64);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let node = &self.set.nodes[self.front?];
+        self.front = node.levels[0].next;
+        self.remaining -= 1;
+        Some((&node.member, node.score))
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl DoubleEndedIterator for Iter<'_> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let node = &self.set.nodes[self.back?];
+        self.back = node.backward;
+        self.remaining -= 1;
+        Some((&node.member, node.score))
+    }
+}
+
+impl ExactSizeIterator for Iter<'_> {}
+
+/// One end of a score range, `(` makes it exclusive
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScoreBound {
+    pub value: f64,
+    pub exclusive: bool,
+}
+
+/// One end of a lex range
+#[derive(Debug, Clone, PartialEq)]
+pub enum LexBound {
+    /// `-`, before every member
+    Min,
+    /// `+`, after every member
+    Max,
+    /// `[member`
+    Inclusive(Bytes),
+    /// `(member`
+    Exclusive(Bytes),
+}
+
+/// Which members a range query picks
+#[derive(Debug, Clone, PartialEq)]
+pub enum ZrangeBy {
+    /// Ranks from `start` to `stop`, negative ones counting from the end
+    Rank(i64, i64),
+    Score(ScoreBound, ScoreBound),
+    Lex(LexBound, LexBound),
+}
+
+/// The range of members `ZRANGE` and friends reply with
+#[derive(Debug, Clone, PartialEq)]
+pub struct ZrangeQuery {
+    pub by: ZrangeBy,
+    /// Highest scores first, ranks then count from the end as well
+    pub rev: bool,
+    /// How many matches to skip and how many to return, a negative count
+    /// returning all of them
+    pub limit: Option<(i64, i64)>,
+}
+
+impl SortedSet {
+    /// The ranks of the members `by` picks. Rank ranges count from the end
+    /// of the set with `rev`.
+    fn rank_range(&self, by: &ZrangeBy, rev: bool) -> Range<usize> {
+        let len = self.len() as i64;
+        match by {
+            ZrangeBy::Rank(start, stop) => {
+                let start = if *start < 0 { len + start } else { *start }.max(0);
+                let stop = if *stop < 0 { len + stop } else { *stop }.min(len - 1);
+                if start > stop {
+                    return 0..0;
+                }
+                if rev {
+                    (len - 1 - stop) as usize..(len - start) as usize
+                } else {
+                    start as usize..stop as usize + 1
+                }
+            }
+            ZrangeBy::Score(min, max) => {
+                let start = self.partition_point(|_, score| {
+                    score < min.value || (min.exclusive && score == min.value)
+                });
+                let end = self.partition_point(|_, score| {
+                    score < max.value || (!max.exclusive && score == max.value)
+                });
+                start..end.max(start)
+            }
+            ZrangeBy::Lex(min, max) => {
+                let start = self.partition_point(|member, _| match min {
+                    LexBound::Min => false,
+                    LexBound::Max => true,
+                    LexBound::Inclusive(min) => member < min,
+                    LexBound::Exclusive(min) => member <= min,
+                });
+                let end = self.partition_point(|member, _| match max {
+                    LexBound::Min => false,
+                    LexBound::Max => true,
+                    LexBound::Inclusive(max) => member <= max,
+                    LexBound::Exclusive(max) => member < max,
+                });
+                start..end.max(start)
+            }
+        }
+    }
+    /// The members `query` picks, in the order they're replied with
+    pub fn select(&self, query: &ZrangeQuery) -> Vec<(Bytes, f64)> {
+        let Range { mut start, mut end } = self.rank_range(&query.by, query.rev);
+        if let Some((offset, count)) = query.limit {
+            let Ok(offset) = usize::try_from(offset) else {
+                return vec![];
             };
-            let start = if start.is_negative() {
-                (set.len()).saturating_sub((start.unsigned_abs()) as usize)
+            let count = usize::try_from(count).unwrap_or(usize::MAX);
+            // Skip from the end the reply starts at
+            if query.rev {
+                end = end.saturating_sub(offset).max(start);
+                start = start.max(end.saturating_sub(count));
             } else {
-                start as usize
+                start = start.saturating_add(offset).min(end);
+                end = end.min(start.saturating_add(count));
+            }
+        }
+        let members = self.range(start..end);
+        let members: Box<dyn Iterator<Item = _>> = if query.rev {
+            Box::new(members.rev())
+        } else {
+            Box::new(members)
+        };
+        members
+            .map(|(member, score)| (member.clone(), score))
+            .collect()
+    }
+    /// How many members `by` picks
+    pub fn count(&self, by: &ZrangeBy) -> usize {
+        self.rank_range(by, false).len()
+    }
+}
+
+/// How the scores of a member found in several sorted sets combine
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Aggregate {
+    #[default]
+    Sum,
+    Min,
+    Max,
+}
+
+impl Aggregate {
+    fn apply(self, score: f64, other: f64) -> f64 {
+        match self {
+            // inf + -inf is NaN, which Redis turns into 0
+            Aggregate::Sum => zero_nan(score + other),
+            Aggregate::Min => score.min(other),
+            Aggregate::Max => score.max(other),
+        }
+    }
+}
+
+fn zero_nan(score: f64) -> f64 {
+    if score.is_nan() { 0.0 } else { score }
+}
+
+/// An input of a sorted set operation. Plain sets take part too, every
+/// member scoring 1.
+enum ZsetInput<'a> {
+    Sorted(&'a SortedSet),
+    Plain(&'a Set),
+}
+
+impl ZsetInput<'_> {
+    fn len(&self) -> usize {
+        match self {
+            ZsetInput::Sorted(set) => set.len(),
+            ZsetInput::Plain(set) => set.len(),
+        }
+    }
+    fn score(&self, member: &Bytes) -> Option<f64> {
+        match self {
+            ZsetInput::Sorted(set) => set.score(member),
+            ZsetInput::Plain(set) => set.contains(member).then_some(1.0),
+        }
+    }
+    fn members(&self) -> Box<dyn Iterator<Item = (Bytes, f64)> + '_> {
+        match self {
+            ZsetInput::Sorted(set) => {
+                Box::new(set.iter().map(|(member, score)| (member.clone(), score)))
+            }
+            ZsetInput::Plain(set) => Box::new(set.iter().map(|member| (member, 1.0))),
+        }
+    }
+}
+
+fn zset_input<'a>(
+    keyspace: &'a Keyspace,
+    key: &Bytes,
+) -> Result<Option<ZsetInput<'a>>, DatabaseError> {
+    match keyspace.get_as::<SortedSet>(key) {
+        Ok(set) => Ok(set.map(ZsetInput::Sorted)),
+        Err(DatabaseError::WrongType) => Ok(keyspace.get_as::<Set>(key)?.map(ZsetInput::Plain)),
+        Err(err) => Err(err),
+    }
+}
+
+/// Combines the sorted sets under `keys`, multiplying each one's scores by
+/// its weight first. `ZDIFF` keeps the scores of the first set as they are.
+fn combine_sorted_sets(
+    keyspace: &Keyspace,
+    keys: &[Bytes],
+    weights: &[f64],
+    operation: SetOperation,
+    aggregate: Aggregate,
+    limit: Option<usize>,
+) -> Result<SortedSet, DatabaseError> {
+    let inputs = keys
+        .iter()
+        .map(|key| zset_input(keyspace, key))
+        .collect::<Result<Vec<_>, _>>()?;
+    let weight = |idx: usize| weights.get(idx).copied().unwrap_or(1.0);
+    let mut result = SortedSet::default();
+    match operation {
+        SetOperation::Inter => {
+            let Some(inputs) = inputs.into_iter().collect::<Option<Vec<_>>>() else {
+                return Ok(result);
             };
-            if start > end {
-                return vec![];
+            let mut inputs: Vec<_> = inputs.into_iter().enumerate().collect();
+            inputs.sort_by_key(|(_, input)| input.len());
+            let Some(((first_idx, smallest), rest)) = inputs.split_first() else {
+                return Ok(result);
+            };
+            'members: for (member, score) in smallest.members() {
+                if limit.is_some_and(|limit| result.len() >= limit) {
+                    break;
+                }
+                let mut score = zero_nan(score * weight(*first_idx));
+                for (idx, input) in rest {
+                    let Some(other) = input.score(&member) else {
+                        continue 'members;
+                    };
+                    score = aggregate.apply(score, zero_nan(other * weight(*idx)));
+                }
+                result.insert(member, score);
+            }
+        }
+        SetOperation::Union => {
+            let mut scores: HashMap<Bytes, f64> = HashMap::new();
+            for (idx, input) in inputs.iter().enumerate() {
+                let Some(input) = input else {
+                    continue;
+                };
+                for (member, score) in input.members() {
+                    let score = zero_nan(score * weight(idx));
+                    scores
+                        .entry(member)
+                        .and_modify(|current| *current = aggregate.apply(*current, score))
+                        .or_insert(score);
+                }
             }
-            if let Some(value) = set.get_range(start..=end) {
-                value.keys().cloned().collect()
+            for (member, score) in scores {
+                result.insert(member, score);
+            }
+        }
+        SetOperation::Diff => {
+            let Some((Some(first), rest)) = inputs.split_first() else {
+                return Ok(result);
+            };
+            for (member, score) in first.members() {
+                if !rest
+                    .iter()
+                    .flatten()
+                    .any(|input| input.score(&member).is_some())
+                {
+                    result.insert(member, score);
+                }
+            }
+        }
+    }
+    Ok(result)
+}
+
+/// Whether `ZADD` only adds new members or only updates existing ones
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ZaddCondition {
+    Nx,
+    Xx,
+}
+
+/// Which way `ZADD` may move the score of an existing member
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScoreComparison {
+    Gt,
+    Lt,
+}
+
+/// The flags of `ZADD`
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ZaddOptions {
+    pub condition: Option<ZaddCondition>,
+    pub comparison: Option<ScoreComparison>,
+    /// Count updated members in the reply as well as added ones
+    pub changed: bool,
+    /// Add to the score instead of setting it, like `ZINCRBY`
+    pub incr: bool,
+}
+
+/// What `ZADD` replies with
+#[derive(Debug, PartialEq)]
+pub enum ZaddResult {
+    /// How many members were added, or changed with `CH`
+    Count(usize),
+    /// The new score with `INCR`, `None` when a flag stopped the update
+    Score(Option<f64>),
+}
+
+/// Which end of a sorted set the pop commands take members from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ZsetEnd {
+    Min,
+    Max,
+}
+
+/// Pops up to `count` members from `key`, deleting it once the set is empty
+fn pop_members(
+    keyspace: &mut Keyspace,
+    key: &Bytes,
+    end: ZsetEnd,
+    count: usize,
+) -> Result<Vec<(Bytes, f64)>, DatabaseError> {
+    let popped = take_members(keyspace, key, end, count)?;
+    remove_if_empty(keyspace, key);
+    Ok(popped)
+}
+
+/// Like [`pop_members`] but leaves an emptied set under the key, so the
+/// members can still be put back without losing its TTL
+fn take_members(
+    keyspace: &mut Keyspace,
+    key: &Bytes,
+    end: ZsetEnd,
+    count: usize,
+) -> Result<Vec<(Bytes, f64)>, DatabaseError> {
+    let Some(set) = keyspace.get_as_mut::<SortedSet>(key)? else {
+        return Ok(vec![]);
+    };
+    let count = set.len().min(count);
+    let popped: Vec<(Bytes, f64)> = match end {
+        ZsetEnd::Min => set
+            .range(0..count)
+            .map(|(member, score)| (member.clone(), score))
+            .collect(),
+        ZsetEnd::Max => set
+            .range(set.len() - count..set.len())
+            .rev()
+            .map(|(member, score)| (member.clone(), score))
+            .collect(),
+    };
+    for (member, _) in &popped {
+        set.remove(member);
+    }
+    Ok(popped)
+}
+
+fn remove_if_empty(keyspace: &mut Keyspace, key: &Bytes) {
+    if matches!(keyspace.get_as::<SortedSet>(key), Ok(Some(set)) if set.len() == 0) {
+        keyspace.remove(key);
+    }
+}
+
+pub type ZsetReceiver = oneshot::Receiver<ZpopResponse>;
+type ZsetReply = oneshot::Sender<ZpopResponse>;
+
+/// How a client blocked on a sorted set wants to be served. As with lists a
+/// client blocked on several keys shares the one reply between them.
+pub struct ZsetWaiter {
+    end: ZsetEnd,
+    /// How many members `BZMPOP` pops at most
+    count: usize,
+    reply: Arc<Mutex<Option<ZsetReply>>>,
+}
+
+impl ZsetWaiter {
+    /// Whether the client was already served through another key or went away
+    pub fn is_closed(&self) -> bool {
+        let reply = self.reply.lock().unwrap();
+        reply.as_ref().is_none_or(|reply| reply.is_closed())
+    }
+    fn take_reply(&self) -> Option<ZsetReply> {
+        let mut reply = self.reply.lock().unwrap();
+        reply.take().filter(|reply| !reply.is_closed())
+    }
+}
+
+/// The key a pop was served from and the members it popped, in pop order
+#[derive(Debug)]
+pub struct ZpopResponse {
+    pub key: Bytes,
+    pub members: Vec<(Bytes, f64)>,
+}
+
+impl RedisDatabase {
+    pub async fn insert_set_member(
+        &self,
+        key: Bytes,
+        member: Bytes,
+        score: f64,
+    ) -> Result<usize, DatabaseError> {
+        let added = {
+            let mut keyspace = self.keyspace.write().await;
+            let set = keyspace.get_or_insert_as::<SortedSet>(&key)?;
+            set.insert(member, score) as usize
+        };
+        self.handle_zset_blocklist(&key).await;
+        Ok(added)
+    }
+    pub async fn zadd(
+        &self,
+        key: &Bytes,
+        members: Vec<(f64, Bytes)>,
+        options: ZaddOptions,
+    ) -> Result<ZaddResult, DatabaseError> {
+        let result = self.zadd_members(key, members, options).await?;
+        self.handle_zset_blocklist(key).await;
+        Ok(result)
+    }
+    async fn zadd_members(
+        &self,
+        key: &Bytes,
+        members: Vec<(f64, Bytes)>,
+        options: ZaddOptions,
+    ) -> Result<ZaddResult, DatabaseError> {
+        let mut keyspace = self.keyspace.write().await;
+        // XX never adds anything, so it mustn't create the key either
+        let set = if options.condition == Some(ZaddCondition::Xx) {
+            match keyspace.get_as_mut::<SortedSet>(key)? {
+                Some(set) => set,
+                None if options.incr => return Ok(ZaddResult::Score(None)),
+                None => return Ok(ZaddResult::Count(0)),
+            }
+        } else {
+            keyspace.get_or_insert_as::<SortedSet>(key)?
+        };
+        let mut added = 0;
+        let mut updated = 0;
+        let mut last_score = None;
+        for (score, member) in members {
+            let current = set.score(&member);
+            match (options.condition, current) {
+                (Some(ZaddCondition::Nx), Some(_)) | (Some(ZaddCondition::Xx), None) => {
+                    last_score = None;
+                    continue;
+                }
+                _ => {}
+            }
+            let score = match (options.incr, current) {
+                (true, Some(current)) => current + score,
+                _ => score,
+            };
+            if score.is_nan() {
+                return Err(DatabaseError::ScoreNan);
+            }
+            if let Some(current) = current {
+                let allowed = match options.comparison {
+                    Some(ScoreComparison::Gt) => score > current,
+                    Some(ScoreComparison::Lt) => score < current,
+                    None => true,
+                };
+                if !allowed {
+                    last_score = None;
+                    continue;
+                }
+                if score != current {
+                    updated += 1;
+                }
             } else {
-                vec![]
+                added += 1;
             }
+            set.insert(member, score);
+            last_score = Some(score);
+        }
+        if options.incr {
+            Ok(ZaddResult::Score(last_score))
+        } else if options.changed {
+            Ok(ZaddResult::Count(added + updated))
         } else {
-            vec![]
+            Ok(ZaddResult::Count(added))
         }
     }
-    pub async fn count_sorted_set(&self, key: &Bytes) -> usize {
-        let sets = self.sets.read().await;
-        if let Some(set) = sets.get(key) {
-            set.len()
+    /// The rank of `member` along with its score, counting from the highest
+    /// score with `rev`
+    pub async fn get_set_member_rank(
+        &self,
+        key: &Bytes,
+        member: &Bytes,
+        rev: bool,
+    ) -> Result<Option<(usize, f64)>, DatabaseError> {
+        let keyspace = self.keyspace.read().await;
+        let Some(set) = keyspace.get_as::<SortedSet>(key)? else {
+            return Ok(None);
+        };
+        Ok(set
+            .rank(member)
+            .zip(set.score(member))
+            .map(|(rank, score)| {
+                if rev {
+                    (set.len() - 1 - rank, score)
+                } else {
+                    (rank, score)
+                }
+            }))
+    }
+    pub async fn range_sorted_set(
+        &self,
+        key: &Bytes,
+        query: &ZrangeQuery,
+    ) -> Result<Vec<(Bytes, f64)>, DatabaseError> {
+        let keyspace = self.keyspace.read().await;
+        Ok(keyspace
+            .get_as::<SortedSet>(key)?
+            .map(|set| set.select(query))
+            .unwrap_or_default())
+    }
+    /// Stores the members `query` picks from `source` as a new sorted set,
+    /// like `ZRANGESTORE`. An empty result deletes `destination`.
+    pub async fn store_sorted_set_range(
+        &self,
+        destination: &Bytes,
+        source: &Bytes,
+        query: &ZrangeQuery,
+    ) -> Result<usize, DatabaseError> {
+        let len = {
+            let mut keyspace = self.keyspace.write().await;
+            let members = keyspace
+                .get_as::<SortedSet>(source)?
+                .map(|set| set.select(query))
+                .unwrap_or_default();
+            let len = members.len();
+            if members.is_empty() {
+                keyspace.remove(destination);
+            } else {
+                let mut set = SortedSet::default();
+                for (member, score) in members {
+                    set.insert(member, score);
+                }
+                keyspace.insert(
+                    destination.clone(),
+                    DatabaseValue::new(RedisValue::SortedSet(set), None),
+                );
+            }
+            len
+        };
+        self.handle_zset_blocklist(destination).await;
+        Ok(len)
+    }
+    /// The members of the combined sorted sets with their scores, like
+    /// `ZUNION`
+    pub async fn sorted_set_combine(
+        &self,
+        keys: &[Bytes],
+        weights: &[f64],
+        operation: SetOperation,
+        aggregate: Aggregate,
+    ) -> Result<Vec<(Bytes, f64)>, DatabaseError> {
+        let keyspace = self.keyspace.read().await;
+        let result = combine_sorted_sets(&keyspace, keys, weights, operation, aggregate, None)?;
+        Ok(result
+            .iter()
+            .map(|(member, score)| (member.clone(), score))
+            .collect())
+    }
+    /// Replaces `destination` with the combined sorted sets in one go, like
+    /// `ZUNIONSTORE`. An empty result deletes it.
+    pub async fn sorted_set_combine_store(
+        &self,
+        destination: &Bytes,
+        keys: &[Bytes],
+        weights: &[f64],
+        operation: SetOperation,
+        aggregate: Aggregate,
+    ) -> Result<usize, DatabaseError> {
+        let len = {
+            let mut keyspace = self.keyspace.write().await;
+            let result = combine_sorted_sets(&keyspace, keys, weights, operation, aggregate, None)?;
+            let len = result.len();
+            if len == 0 {
+                keyspace.remove(destination);
+            } else {
+                keyspace.insert(
+                    destination.clone(),
+                    DatabaseValue::new(RedisValue::SortedSet(result), None),
+                );
+            }
+            len
+        };
+        self.handle_zset_blocklist(destination).await;
+        Ok(len)
+    }
+    /// The size of the intersection, counting no further than `limit`
+    pub async fn sorted_set_inter_card(
+        &self,
+        keys: &[Bytes],
+        limit: Option<usize>,
+    ) -> Result<usize, DatabaseError> {
+        let keyspace = self.keyspace.read().await;
+        let result = combine_sorted_sets(
+            &keyspace,
+            keys,
+            &[],
+            SetOperation::Inter,
+            Aggregate::Sum,
+            limit,
+        )?;
+        Ok(result.len())
+    }
+    /// How many members of `key` fall in a score or lex range
+    pub async fn count_sorted_set_range(
+        &self,
+        key: &Bytes,
+        by: &ZrangeBy,
+    ) -> Result<usize, DatabaseError> {
+        let keyspace = self.keyspace.read().await;
+        Ok(keyspace
+            .get_as::<SortedSet>(key)?
+            .map_or(0, |set| set.count(by)))
+    }
+    pub async fn count_sorted_set(&self, key: &Bytes) -> Result<usize, DatabaseError> {
+        let keyspace = self.keyspace.read().await;
+        if let Some(set) = keyspace.get_as::<SortedSet>(key)? {
+            Ok(set.len())
         } else {
-            0
+            Ok(0)
         }
     }
 
-    pub async fn get_set_member_score(&self, key: &Bytes, member: &Bytes) -> Option<f64> {
-        let sets = self.sets.read().await;
-        if let Some(set) = sets.get(key) {
-            set.get(member).copied()
+    pub async fn get_set_member_score(
+        &self,
+        key: &Bytes,
+        member: &Bytes,
+    ) -> Result<Option<f64>, DatabaseError> {
+        let keyspace = self.keyspace.read().await;
+        if let Some(set) = keyspace.get_as::<SortedSet>(key)? {
+            Ok(set.score(member))
         } else {
-            None
+            Ok(None)
         }
     }
-    pub async fn remove_set_member(&self, key: &Bytes, member: &Bytes) -> usize {
-        let mut sets = self.sets.write().await;
-        if let Some(set) = sets.get_mut(key) {
-            if set.shift_remove(member).is_some() {
-                1
-            } else {
-                0
-            }
+    pub async fn get_set_member_scores(
+        &self,
+        key: &Bytes,
+        members: &[Bytes],
+    ) -> Result<Vec<Option<f64>>, DatabaseError> {
+        let keyspace = self.keyspace.read().await;
+        let set = keyspace.get_as::<SortedSet>(key)?;
+        Ok(members
+            .iter()
+            .map(|member| set.and_then(|set| set.score(member)))
+            .collect())
+    }
+    /// Removes `members`, deleting the key once the set is empty
+    pub async fn remove_set_members(
+        &self,
+        key: &Bytes,
+        members: &[Bytes],
+    ) -> Result<usize, DatabaseError> {
+        let mut keyspace = self.keyspace.write().await;
+        let Some(set) = keyspace.get_as_mut::<SortedSet>(key)? else {
+            return Ok(0);
+        };
+        let removed = members
+            .iter()
+            .filter(|member| set.remove(member).is_some())
+            .count();
+        if set.len() == 0 {
+            keyspace.remove(key);
+        }
+        Ok(removed)
+    }
+    /// Removes the members in a rank, score or lex range, like
+    /// `ZREMRANGEBYSCORE`, deleting the key once the set is empty
+    pub async fn remove_sorted_set_range(
+        &self,
+        key: &Bytes,
+        by: &ZrangeBy,
+    ) -> Result<usize, DatabaseError> {
+        let mut keyspace = self.keyspace.write().await;
+        let Some(set) = keyspace.get_as_mut::<SortedSet>(key)? else {
+            return Ok(0);
+        };
+        let members: Vec<Bytes> = set
+            .range(set.rank_range(by, false))
+            .map(|(member, _)| member.clone())
+            .collect();
+        for member in &members {
+            set.remove(member);
+        }
+        if set.len() == 0 {
+            keyspace.remove(key);
+        }
+        Ok(members.len())
+    }
+    /// Picks `count` random members with their scores: distinct ones when
+    /// positive, possibly repeated ones when negative
+    pub async fn sorted_set_rand_members(
+        &self,
+        key: &Bytes,
+        count: i64,
+    ) -> Result<Vec<(Bytes, f64)>, DatabaseError> {
+        check_rand_count(count)?;
+        let keyspace = self.keyspace.read().await;
+        let Some(set) = keyspace.get_as::<SortedSet>(key)? else {
+            return Ok(vec![]);
+        };
+        let member_at = |idx: usize| {
+            set.range(idx..idx + 1)
+                .next()
+                .map(|(member, score)| (member.clone(), score))
+        };
+        let mut rng = rand::rng();
+        if set.len() == 0 {
+            Ok(vec![])
+        } else if count < 0 {
+            Ok((0..count.unsigned_abs())
+                .filter_map(|_| member_at(rng.random_range(0..set.len())))
+                .collect())
+        } else if count as usize >= set.len() {
+            Ok(set
+                .iter()
+                .map(|(member, score)| (member.clone(), score))
+                .collect())
         } else {
-            0
+            Ok(
+                rand::seq::index::sample(&mut rng, set.len(), count as usize)
+                    .into_iter()
+                    .filter_map(member_at)
+                    .collect(),
+            )
         }
     }
-    pub async fn get_distance(&self, key: &Bytes, first: &Bytes, second: &Bytes) -> Option<f64> {
-        let sets = self.sets.read().await;
-        if let Some(set) = sets.get(key) {
-            if let Some(first_geo) = set.get(first)
-                && let Some(second_geo) = set.get(second)
+    pub async fn get_distance(
+        &self,
+        key: &Bytes,
+        first: &Bytes,
+        second: &Bytes,
+    ) -> Result<Option<f64>, DatabaseError> {
+        let keyspace = self.keyspace.read().await;
+        if let Some(set) = keyspace.get_as::<SortedSet>(key)? {
+            if let Some(first_geo) = set.score(first)
+                && let Some(second_geo) = set.score(second)
             {
-                let first_coord = Coordinates::decode(*first_geo as u64);
-                let second_coord = Coordinates::decode(*second_geo as u64);
-                Some(first_coord.distance(&second_coord))
+                let first_coord = Coordinates::decode(first_geo as u64);
+                let second_coord = Coordinates::decode(second_geo as u64);
+                Ok(Some(first_coord.distance(&second_coord)))
             } else {
-                None
+                Ok(None)
             }
         } else {
-            None
-        }
-    }
-
-    pub async fn search_area(&self, key: &Bytes, coord: Coordinates, radius: f64) -> Vec<Bytes> {
-        let sets = self.sets.read().await;
-        if let Some(set) = sets.get(key) {
-            set.iter()
-                .filter_map(|(member, score)| {
-                    let location = Coordinates::decode(*score as u64);
-                    if coord.distance(&location) < radius {
-                        Some(member.clone())
-                    } else {
-                        None
-                    }
-                })
-                .collect()
-        } else {
-            vec![]
+            Ok(None)
+        }
+    }
+
+    /// The members of `key` in the area `query` covers
+    pub async fn geo_search(
+        &self,
+        key: &Bytes,
+        query: &GeoSearch,
+    ) -> Result<Vec<GeoMatch>, DatabaseError> {
+        let keyspace = self.keyspace.read().await;
+        let Some(set) = keyspace.get_as::<SortedSet>(key)? else {
+            return Ok(vec![]);
+        };
+        let centre = match &query.origin {
+            GeoOrigin::Position(position) => *position,
+            GeoOrigin::Member(member) => {
+                let score = set.score(member).ok_or(DatabaseError::UnknownMember)?;
+                Coordinates::decode(score as u64)
+            }
+        };
+        // Only members in the cells around the centre can be in range
+        let candidates = score_ranges(&centre, &query.shape)
+            .into_iter()
+            .flat_map(|scores| {
+                let start = set.partition_point(|_, score| score < scores.start as f64);
+                let end = set.partition_point(|_, score| score < scores.end as f64);
+                set.range(start..end)
+            });
+        Ok(query.select(&centre, candidates))
+    }
+    /// Pops up to `count` members, deleting the key once the set is empty
+    pub async fn pop_sorted_set(
+        &self,
+        key: &Bytes,
+        end: ZsetEnd,
+        count: usize,
+    ) -> Result<Vec<(Bytes, f64)>, DatabaseError> {
+        let mut keyspace = self.keyspace.write().await;
+        pop_members(&mut keyspace, key, end, count)
+    }
+    /// Pops up to `count` members from the first non-empty set among `keys`,
+    /// like `ZMPOP`
+    pub async fn pop_sorted_sets(
+        &self,
+        keys: &[Bytes],
+        end: ZsetEnd,
+        count: usize,
+    ) -> Result<Option<ZpopResponse>, DatabaseError> {
+        let mut keyspace = self.keyspace.write().await;
+        for key in keys {
+            let members = pop_members(&mut keyspace, key, end, count)?;
+            if !members.is_empty() {
+                return Ok(Some(ZpopResponse {
+                    key: key.clone(),
+                    members,
+                }));
+            }
+        }
+        Ok(None)
+    }
+    /// Pops from the first non-empty set among `keys`, or else queues the
+    /// client on every one of them
+    pub async fn blocking_pop_sorted_set(
+        &self,
+        client: ClientId,
+        keys: &[Bytes],
+        timeout: Option<Instant>,
+        end: ZsetEnd,
+        count: usize,
+    ) -> Result<Either<ZpopResponse, ZsetReceiver>, DatabaseError> {
+        let mut keyspace = self.keyspace.write().await;
+        for key in keys {
+            let members = pop_members(&mut keyspace, key, end, count)?;
+            if !members.is_empty() {
+                return Ok(Either::Left(ZpopResponse {
+                    key: key.clone(),
+                    members,
+                }));
+            }
+        }
+        let mut blocklist = self.zset_blocklist.lock().await;
+        let (reply, receiver) = oneshot::channel();
+        let reply = Arc::new(Mutex::new(Some(reply)));
+        for key in keys {
+            let waiter = ZsetWaiter {
+                end,
+                count,
+                reply: reply.clone(),
+            };
+            blocklist.push(
+                key.clone(),
+                Blocker {
+                    client,
+                    waiter,
+                    timeout,
+                },
+            );
+        }
+        Ok(Either::Right(receiver))
+    }
+    /// Serves the clients blocked on `key` for as long as it has members
+    pub async fn handle_zset_blocklist(&self, key: &Bytes) {
+        // Keyspace before blocklist, like `blocking_pop_sorted_set`
+        let mut keyspace = self.keyspace.write().await;
+        let mut blockers = self.zset_blocklist.lock().await;
+        while matches!(keyspace.get_as::<SortedSet>(key), Ok(Some(_)))
+            && let Some(blocker) = blockers.pop_front(key)
+        {
+            if blocker.timed_out() {
+                continue;
+            }
+            let Some(reply) = blocker.waiter.take_reply() else {
+                continue;
+            };
+            let ZsetWaiter { end, count, .. } = blocker.waiter;
+            if serve_waiter(&mut keyspace, key, reply, end, count).is_err() {
+                break;
+            }
+        }
+    }
+}
+
+/// Pops for one blocked client and sends it the members, putting them back
+/// if it went away in the meantime. The key is only deleted once the client
+/// has the members, so putting them back keeps its TTL.
+fn serve_waiter(
+    keyspace: &mut Keyspace,
+    key: &Bytes,
+    reply: ZsetReply,
+    end: ZsetEnd,
+    count: usize,
+) -> Result<(), DatabaseError> {
+    let members = take_members(keyspace, key, end, count)?;
+    let response = ZpopResponse {
+        key: key.clone(),
+        members,
+    };
+    if let Err(response) = reply.send(response)
+        && let Some(set) = keyspace.get_as_mut::<SortedSet>(key)?
+    {
+        for (member, score) in response.members {
+            set.insert(member, score);
+        }
+    }
+    remove_if_empty(keyspace, key);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_skiplist_matches_sorted_vec() {
+        let mut rng = rand::rng();
+        let mut set = SortedSet::default();
+        let mut model: Vec<(f64, Bytes)> = vec![];
+        for _ in 0..5000 {
+            let member = Bytes::from(rng.random_range(0..300).to_string());
+            if rng.random_bool(0.3) {
+                let removed = set.remove(&member);
+                let index = model.iter().position(|(_, other)| *other == member);
+                assert_eq!(removed, index.map(|index| model.remove(index).0));
+            } else {
+                let score = rng.random_range(0..50) as f64;
+                let index = model.iter().position(|(_, other)| *other == member);
+                assert_eq!(set.insert(member.clone(), score), index.is_none());
+                if let Some(index) = index {
+                    model.remove(index);
+                }
+                let at = model.partition_point(|other| *other < (score, member.clone()));
+                model.insert(at, (score, member));
+            }
+        }
+        assert_eq!(set.len(), model.len());
+        let members: Vec<(f64, Bytes)> = set
+            .iter()
+            .map(|(member, score)| (score, member.clone()))
+            .collect();
+        assert_eq!(members, model);
+        for (rank, (_, member)) in model.iter().enumerate() {
+            assert_eq!(set.rank(member), Some(rank));
         }
+        let middle: Vec<&Bytes> = set.range(10..20).rev().map(|(member, _)| member).collect();
+        let expected: Vec<&Bytes> = model[10..20]
+            .iter()
+            .rev()
+            .map(|(_, member)| member)
+            .collect();
+        assert_eq!(middle, expected);
+        let below = set.partition_point(|_, score| score < 25.0);
+        assert_eq!(below, model.partition_point(|(score, _)| *score < 25.0));
+    }
+
+    #[test]
+    fn test_select_ranges() {
+        let mut set = SortedSet::default();
+        for (score, member) in ["a", "b", "c", "d", "e"].iter().enumerate() {
+            set.insert(Bytes::from(member.to_string()), score as f64);
+        }
+        let select = |by, rev, limit| {
+            let query = ZrangeQuery { by, rev, limit };
+            let members = set.select(&query);
+            members
+                .into_iter()
+                .map(|(member, _)| String::from_utf8(member.to_vec()).unwrap())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(select(ZrangeBy::Rank(0, 1), true, None), ["e", "d"]);
+        assert_eq!(select(ZrangeBy::Rank(-2, 10), false, None), ["d", "e"]);
+        let bound = |value, exclusive| ScoreBound { value, exclusive };
+        let scores = ZrangeBy::Score(bound(1.0, true), bound(f64::INFINITY, false));
+        assert_eq!(select(scores.clone(), false, Some((1, 2))), ["d", "e"]);
+        assert_eq!(select(scores.clone(), true, Some((1, -1))), ["d", "c"]);
+        assert_eq!(set.count(&scores), 3);
+        let lex = ZrangeBy::Lex(LexBound::Exclusive(Bytes::from("a")), LexBound::Max);
+        assert_eq!(select(lex, false, Some((3, 5))), ["e"]);
+    }
+
+    #[tokio::test]
+    async fn test_zadd_options() {
+        let db = RedisDatabase::default();
+        let key = Bytes::from("zset");
+        let pairs = |pairs: &[(f64, &str)]| {
+            pairs
+                .iter()
+                .map(|(score, member)| (*score, Bytes::from(member.to_string())))
+                .collect::<Vec<_>>()
+        };
+        let added = db.zadd(
+            &key,
+            pairs(&[(1.0, "a"), (2.0, "b")]),
+            ZaddOptions::default(),
+        );
+        assert_eq!(added.await.unwrap(), ZaddResult::Count(2));
+        // Updating a score moves the member
+        let changed = ZaddOptions {
+            changed: true,
+            ..Default::default()
+        };
+        let updated = db.zadd(&key, pairs(&[(3.0, "a"), (0.5, "c")]), changed);
+        assert_eq!(updated.await.unwrap(), ZaddResult::Count(2));
+        let all = ZrangeQuery {
+            by: ZrangeBy::Rank(0, -1),
+            rev: false,
+            limit: None,
+        };
+        let range = db.range_sorted_set(&key, &all).await.unwrap();
+        let members: Vec<&Bytes> = range.iter().map(|(member, _)| member).collect();
+        assert_eq!(members, vec!["c", "b", "a"]);
+
+        let greater = ZaddOptions {
+            comparison: Some(ScoreComparison::Gt),
+            incr: true,
+            ..Default::default()
+        };
+        let lowered = db.zadd(&key, pairs(&[(-1.0, "a")]), greater);
+        assert_eq!(lowered.await.unwrap(), ZaddResult::Score(None));
+        let raised = db.zadd(&key, pairs(&[(1.0, "a")]), greater);
+        assert_eq!(raised.await.unwrap(), ZaddResult::Score(Some(4.0)));
+    }
+
+    #[test]
+    fn test_combine_sorted_sets() {
+        let mut keyspace = Keyspace::default();
+        let (a, b) = (Bytes::from("a"), Bytes::from("b"));
+        let zset = keyspace.get_or_insert_as::<SortedSet>(&a).unwrap();
+        zset.insert(Bytes::from("x"), 1.0);
+        zset.insert(Bytes::from("y"), f64::INFINITY);
+        let set = keyspace.get_or_insert_as::<Set>(&b).unwrap();
+        set.insert(Bytes::from("y"));
+        set.insert(Bytes::from("z"));
+        let keys = [a, b];
+        let scores = |operation, weights: &[f64], aggregate| {
+            let result =
+                combine_sorted_sets(&keyspace, &keys, weights, operation, aggregate, None).unwrap();
+            result
+                .iter()
+                .map(|(member, score)| (member.clone(), score))
+                .collect::<Vec<_>>()
+        };
+        let member = |member: &'static str, score: f64| (Bytes::from(member), score);
+        assert_eq!(
+            scores(SetOperation::Union, &[1.0, -2.0], Aggregate::Min),
+            [member("y", -2.0), member("z", -2.0), member("x", 1.0)]
+        );
+        // inf + -inf scores 0 rather than NaN
+        assert_eq!(
+            scores(
+                SetOperation::Union,
+                &[1.0, f64::NEG_INFINITY],
+                Aggregate::Sum
+            ),
+            [
+                member("z", f64::NEG_INFINITY),
+                member("y", 0.0),
+                member("x", 1.0)
+            ]
+        );
+        assert_eq!(
+            scores(SetOperation::Inter, &[1.0, 1.0], Aggregate::Sum),
+            [member("y", f64::INFINITY)]
+        );
+        assert_eq!(
+            scores(SetOperation::Diff, &[], Aggregate::Sum),
+            [member("x", 1.0)]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rand_count_out_of_range() {
+        let db = RedisDatabase::default();
+        let key = Bytes::from("key");
+        let added = db.zadd(&key, vec![(1.0, Bytes::from("a"))], ZaddOptions::default());
+        added.await.unwrap();
+        assert!(matches!(
+            db.sorted_set_rand_members(&key, -i64::MAX).await,
+            Err(DatabaseError::OutOfRange)
+        ));
+        assert_eq!(db.sorted_set_rand_members(&key, -3).await.unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_blocking_pop_sorted_set() {
+        let db = RedisDatabase::default();
+        let keys = [Bytes::from("first"), Bytes::from("second")];
+        let popped = db.blocking_pop_sorted_set(1, &keys, None, ZsetEnd::Max, 2);
+        let Ok(Either::Right(receiver)) = popped.await else {
+            panic!("expected the client to block");
+        };
+        // Members added together are all there by the time the client is served
+        let members = [(1.0, "a"), (2.0, "b"), (3.0, "c")]
+            .map(|(score, member)| (score, Bytes::from(member)))
+            .to_vec();
+        let added = db.zadd(&keys[1], members, ZaddOptions::default());
+        assert_eq!(added.await.unwrap(), ZaddResult::Count(3));
+        let response = receiver.await.unwrap();
+        assert_eq!(response.key, keys[1]);
+        assert_eq!(
+            response.members,
+            [(Bytes::from("c"), 3.0), (Bytes::from("b"), 2.0)]
+        );
+        let left = db.pop_sorted_set(&keys[1], ZsetEnd::Min, 5).await.unwrap();
+        assert_eq!(left, [(Bytes::from("a"), 1.0)]);
+        // The waiter left on the other key shares the reply that was used up
+        let mut blocklist = db.zset_blocklist.lock().await;
+        let waiting = blocklist.pop_front(&keys[0]).unwrap();
+        assert!(waiting.waiter.is_closed());
+    }
+
+    #[test]
+    fn test_serve_waiter_gone() {
+        let mut keyspace = Keyspace::default();
+        let key = Bytes::from("key");
+        let mut set = SortedSet::default();
+        set.insert(Bytes::from("a"), 1.0);
+        let expiry = Some(Either::Left(
+            Instant::now() + std::time::Duration::from_secs(60),
+        ));
+        keyspace.insert(
+            key.clone(),
+            DatabaseValue::new(RedisValue::SortedSet(set), expiry),
+        );
+        let (reply, receiver) = oneshot::channel();
+        drop(receiver);
+        serve_waiter(&mut keyspace, &key, reply, ZsetEnd::Min, 1).unwrap();
+        let value = keyspace.get(&key).expect("the member was put back");
+        assert_eq!(value.expiry, expiry);
+        let RedisValue::SortedSet(set) = &value.value else {
+            panic!("expected a sorted set");
+        };
+        assert_eq!(set.len(), 1);
     }
 }
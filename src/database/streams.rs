@@ -3,7 +3,7 @@ use std::time::SystemTime;
 use crate::{
     Pair,
     command::{XrangeIdInput, macros::Symbol},
-    database::{Blocker, RedisDatabase},
+    database::{Blocker, ClientId, DatabaseError, RedisDatabase, Stream},
     id::{Id, WildcardID},
     resp::RedisWrite,
 };
@@ -47,8 +47,8 @@ impl RedisDatabase {
         values: HashMap<Bytes, Bytes>,
     ) -> Result<Id, DbStreamAddError> {
         let result = {
-            let mut streams = self.streams.write().await;
-            let stream = streams.entry(key.clone()).or_default();
+            let mut keyspace = self.keyspace.write().await;
+            let stream = keyspace.get_or_insert_as::<Stream>(&key)?;
             if let Some(id) = Id::from_wildcard(id) {
                 if id.is_zero_zero() {
                     return Err(DbStreamAddError::IdZeroZero);
@@ -98,9 +98,9 @@ impl RedisDatabase {
         key: &Bytes,
         start: Option<&XrangeIdInput>,
         end: Option<&XrangeIdInput>,
-    ) -> Vec<DatabaseStreamEntry> {
-        let streams = self.streams.read().await;
-        if let Some(stream) = streams.get(key) {
+    ) -> Result<Vec<DatabaseStreamEntry>, DatabaseError> {
+        let keyspace = self.keyspace.read().await;
+        if let Some(stream) = keyspace.get_as::<Stream>(key)? {
             let first = match start {
                 Some(XrangeIdInput::MsTime(ms_time)) => {
                     let id = Id {
@@ -137,30 +137,33 @@ impl RedisDatabase {
             };
 
             if let Some(range) = stream.get_range(first..=last) {
-                range
+                Ok(range
                     .iter()
                     .map(|(key, value)| DatabaseStreamEntry {
                         id: *key,
                         values: value.clone(),
                     })
-                    .collect()
+                    .collect())
             } else {
-                vec![]
+                Ok(vec![])
             }
         } else {
-            vec![]
+            Ok(vec![])
         }
     }
-    pub async fn read_stream(&self, query: &StreamQuery) -> Option<ReadStreamResult> {
-        let streams = self.streams.read().await;
-        if let Some(stream) = streams.get(&query.key) {
+    pub async fn read_stream(
+        &self,
+        query: &StreamQuery,
+    ) -> Result<Option<ReadStreamResult>, DatabaseError> {
+        let keyspace = self.keyspace.read().await;
+        if let Some(stream) = keyspace.get_as::<Stream>(&query.key)? {
             let id = match &query.id {
                 Either::Left(id) => id,
                 Either::Right(_) => {
                     if let Some((id, _)) = stream.last() {
                         id
                     } else {
-                        return None;
+                        return Ok(None);
                     }
                 }
             };
@@ -170,7 +173,7 @@ impl RedisDatabase {
             // };
             let idx = stream.partition_point(|key, _| id >= key);
             if idx >= stream.len() {
-                None
+                Ok(None)
             } else if let Some(values) = stream.get_range(std::ops::RangeFrom { start: idx }) {
                 let results = values
                     .iter()
@@ -179,34 +182,80 @@ impl RedisDatabase {
                         values: value.clone(),
                     })
                     .collect();
-                Some(Pair::new(query.key.clone(), results))
+                Ok(Some(Pair::new(query.key.clone(), results)))
             } else {
-                None
+                Ok(None)
             }
         } else {
-            None
+            Ok(None)
         }
     }
 
     pub async fn block_read_stream(
         &self,
+        client: ClientId,
         queries: &[StreamQuery],
         timeout: Option<Instant>,
     ) -> mpsc::Receiver<ReadStreamResult> {
         let (sender, receiver) = mpsc::channel::<ReadStreamResult>(10);
+        let keyspace = self.keyspace.read().await;
         let mut blocklist = self.stream_blocklist.lock().await;
         for StreamQuery { key, id } in queries {
-            blocklist.entry(key.clone()).or_default().push(Pair::new(
-                *id,
+            // Pin '$' to the last id right now so a stream moved into the key
+            // later is still read from the right place
+            let id = match id {
+                Either::Left(id) => *id,
+                Either::Right(_) => match keyspace.get_as::<Stream>(key) {
+                    Ok(Some(stream)) => stream.last().map(|(id, _)| *id).unwrap_or_default(),
+                    _ => Id::default(),
+                },
+            };
+            let waiter = StreamWaiter {
+                after: id,
+                sender: sender.clone(),
+            };
+            blocklist.push(
+                key.clone(),
                 Blocker {
-                    sender: sender.clone(),
+                    client,
+                    waiter,
                     timeout,
                 },
-            ));
+            );
         }
         receiver
     }
 
+    /// Sends every waiter on `key` the entries newer than the id it is
+    /// waiting on, used when a whole stream appears under the key at once
+    pub async fn handle_stream_ready(&self, key: &Bytes) {
+        let keyspace = self.keyspace.read().await;
+        let Ok(Some(stream)) = keyspace.get_as::<Stream>(key) else {
+            return;
+        };
+        let mut blockers = self.stream_blocklist.lock().await;
+        blockers.retain(key, |blocker| {
+            let StreamWaiter { after, sender } = &blocker.waiter;
+            if blocker.timed_out() || sender.is_closed() {
+                return false;
+            }
+            let idx = stream.partition_point(|key, _| after >= key);
+            let entries: Vec<DatabaseStreamEntry> = stream
+                .get_range(idx..)
+                .map(|values| {
+                    values
+                        .iter()
+                        .map(|(key, value)| DatabaseStreamEntry {
+                            id: *key,
+                            values: value.clone(),
+                        })
+                        .collect()
+                })
+                .unwrap_or_default();
+            entries.is_empty() || sender.try_send(Pair::new(key.clone(), entries)).is_err()
+        });
+    }
+
     pub async fn handle_stream_blocklist(
         &self,
         key: &Bytes,
@@ -214,29 +263,39 @@ impl RedisDatabase {
         values: HashMap<Bytes, Bytes>,
     ) {
         let mut blockers = self.stream_blocklist.lock().await;
-        if let Some(waiters) = blockers.get_mut(key) {
-            for Pair {
-                left: _,
-                right: blocker,
-            } in waiters
-            {
-                let value = Pair::new(
-                    key.clone(),
-                    vec![DatabaseStreamEntry {
-                        id,
-                        values: values.clone(),
-                    }],
-                );
-                if !blocker.timed_out() && !blocker.sender.is_closed() {
-                    if let Err(err) = blocker.sender.send(value).await {
-                        eprintln!("ERROR sending blocklist {err:#?}");
-                        break;
-                    } else {
-                        continue;
-                    }
+        // Never wait on a waiter's channel with the blocklist held, a waiter
+        // that can't take the entry right away is dropped instead
+        blockers.retain(key, |blocker| {
+            if blocker.timed_out() || blocker.waiter.is_closed() {
+                return false;
+            }
+            let value = Pair::new(
+                key.clone(),
+                vec![DatabaseStreamEntry {
+                    id,
+                    values: values.clone(),
+                }],
+            );
+            match blocker.waiter.sender.try_send(value) {
+                Ok(()) => true,
+                Err(err) => {
+                    tracing::debug!("dropping stream waiter on {key:?}: {err}");
+                    false
                 }
             }
-        }
+        });
+    }
+}
+
+/// A client blocked in `XREAD`, waiting for entries after `after`
+pub struct StreamWaiter {
+    after: Id,
+    sender: mpsc::Sender<ReadStreamResult>,
+}
+
+impl StreamWaiter {
+    pub fn is_closed(&self) -> bool {
+        self.sender.is_closed()
     }
 }
 
@@ -253,4 +312,6 @@ pub enum DbStreamAddError {
     IdZeroZero,
     #[error("Couldn't generate UNIX time: {0}")]
     TimeError(#[from] std::time::SystemTimeError),
+    #[error(transparent)]
+    Database(#[from] DatabaseError),
 }
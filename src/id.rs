@@ -43,7 +43,7 @@ impl WildcardID {
     }
 }
 
-#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash, Default)]
 pub struct Id {
     pub ms_time: usize,
     pub sequence: usize,
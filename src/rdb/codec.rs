@@ -10,8 +10,9 @@ use hashbrown::HashMap;
 use tokio_stream::StreamExt;
 use tokio_util::codec::{Decoder, Encoder, FramedRead};
 
+use crate::rdb::lzf_decompress;
+
 pub const RDB_KV_STR: u8 = 0;
-pub const RDB_KV_LIST: u8 = 1;
 
 const RDB_CODE_EOF: u8 = 0xFF;
 const RDB_CODE_SELECT_DB: u8 = 0xFE;
@@ -79,34 +80,17 @@ impl Decoder for RdbCodec {
         //         )
         //     })?;
         self.cursor += 4;
-        let metadata = if src[self.cursor] == RDB_CODE_AUX {
-            self.cursor += 1;
+        let metadata = if src.get(self.cursor) == Some(&RDB_CODE_AUX) {
             let mut map = HashMap::new();
-            loop {
-                let backup = self.cursor;
-
-                if src.get(self.cursor) == Some(&RDB_CODE_SELECT_DB) {
-                    break;
-                }
-                if let Some(key) = RdbLenStr::decode_stream(self, src)? {
-                    match key.to_ascii_lowercase().as_slice() {
-                        b"redis-ver" => {
-                            let Some(value) = RdbLenStr::decode_stream(self, src)? else {
-                                return Ok(None);
-                            };
-                            map.insert(key, value);
-                        }
-                        b"redis-bits" => {
-                            let Some(_) = LenEncoding::decode_stream(self, src)? else {
-                                return Ok(None);
-                            };
-                        }
-                        _ => {
-                            self.cursor = backup;
-                            break;
-                        }
-                    }
-                }
+            while src.get(self.cursor) == Some(&RDB_CODE_AUX) {
+                self.cursor += 1;
+                let Some(key) = RdbLenStr::decode_stream(self, src)? else {
+                    return Ok(None);
+                };
+                let Some(value) = RdbLenStr::decode_stream(self, src)? else {
+                    return Ok(None);
+                };
+                map.insert(key, value);
             }
             map
         } else {
@@ -119,46 +103,35 @@ impl Decoder for RdbCodec {
 
         let mut databases = DatabaseSection(vec![]);
 
-        loop {
-            if src[self.cursor] == RDB_CODE_SELECT_DB {
+        while src.get(self.cursor) == Some(&RDB_CODE_SELECT_DB) {
+            self.cursor += 1;
+            // IDX
+            if LenEncoding::decode_stream(self, src)?.is_none() {
+                return Ok(None);
+            }
+            if src.get(self.cursor) == Some(&RDB_CODE_RESIZE_DB) {
                 self.cursor += 1;
-                // IDX SIZE
-                if LenEncoding::decode_stream(self, src)?.is_some() {
-                    self.cursor += 1;
-                    if src[self.cursor] == RDB_CODE_RESIZE_DB {
-                        self.cursor += 1;
-                        // Hash table size
-                        if let Some(size) = LenEncoding::decode_stream(self, src)? {
-                            // Expiry size
-                            if LenEncoding::decode_stream(self, src)?.is_some() {
-                                let mut keys = vec![];
-                                for _ in 0..size {
-                                    if let Some(kv) = RdbKeyValue::decode_stream(self, src)? {
-                                        keys.push(kv);
-                                    } else {
-                                        return Ok(None);
-                                    }
-                                }
-                                databases.add_database(keys);
-                            } else {
-                                return Ok(None);
-                            }
-                        } else {
+                // Hash table and expiry sizes, only used as hints
+                for _ in 0..2 {
+                    if LenEncoding::decode_stream(self, src)?.is_none() {
+                        return Ok(None);
+                    }
+                }
+            }
+            let mut keys = vec![];
+            loop {
+                match src.get(self.cursor) {
+                    Some(&RDB_CODE_SELECT_DB) | Some(&RDB_CODE_EOF) => break,
+                    Some(_) => {
+                        let Some(kv) = RdbKeyValue::decode_stream(self, src)? else {
                             return Ok(None);
-                        }
-                    } else {
-                        return Err(std::io::Error::new(
-                            std::io::ErrorKind::InvalidInput,
-                            "missing the Resize DB Op Code",
-                        )
-                        .into());
+                        };
+                        keys.push(kv);
                     }
-                } else {
-                    return Ok(None);
+                    None => return Ok(None),
                 }
-            } else {
-                break;
             }
+            databases.add_database(keys);
         }
 
         if let Some(code) = src.get(self.cursor)
@@ -255,6 +228,32 @@ impl RdbKeyValue {
         codec: &mut RdbCodec,
         src: &mut BytesMut,
     ) -> Result<Option<Self>, std::io::Error> {
+        // The expiry comes before the value type
+        let expiry: Option<Either<u64, u32>> = match src.get(codec.cursor) {
+            Some(&RDB_CODE_EXPIRY) => {
+                codec.cursor += 1;
+                let Some(seconds) = src.get(codec.cursor..(codec.cursor + 4)) else {
+                    return Ok(None);
+                };
+                let out = Some(Either::Right(u32::from_le_bytes(
+                    seconds.try_into().unwrap(),
+                )));
+                codec.cursor += 4;
+                out
+            }
+            Some(&RDB_CODE_EXPIRY_MS) => {
+                codec.cursor += 1;
+                let Some(milliseconds) = src.get(codec.cursor..(codec.cursor + 8)) else {
+                    return Ok(None);
+                };
+                let out = Some(Either::Left(u64::from_le_bytes(
+                    milliseconds.try_into().unwrap(),
+                )));
+                codec.cursor += 8;
+                out
+            }
+            _ => None,
+        };
         if let Some(code) = src.get(codec.cursor) {
             match *code {
                 RDB_KV_STR => {
@@ -265,36 +264,6 @@ impl RdbKeyValue {
                     let Some(value) = RdbLenStr::decode_stream(codec, src)? else {
                         return Ok(None);
                     };
-                    let expiry: Option<Either<u64, u32>> = if let Some(code) = src.get(codec.cursor)
-                    {
-                        if code == &RDB_CODE_EXPIRY {
-                            codec.cursor += 1;
-                            let Some(seconds) = src.get(codec.cursor..(codec.cursor + 4)) else {
-                                return Ok(None);
-                            };
-                            let out = Some(Either::Right(u32::from_le_bytes(
-                                seconds.try_into().unwrap(),
-                            )));
-                            codec.cursor += 4;
-                            out
-                        } else if code == &RDB_CODE_EXPIRY_MS {
-                            codec.cursor += 1;
-                            let Some(milliseconds) = src.get(codec.cursor..(codec.cursor + 8))
-                            else {
-                                return Ok(None);
-                            };
-                            let out = Some(Either::Left(u64::from_le_bytes(
-                                milliseconds.try_into().unwrap(),
-                            )));
-                            codec.cursor += 8;
-                            out
-                        } else {
-                            None
-                        }
-                    } else {
-                        None
-                    };
-
                     Ok(Some(RdbKeyValue {
                         key: key.into(),
                         value: value.into(),
@@ -302,8 +271,10 @@ impl RdbKeyValue {
                         expiry,
                     }))
                 }
-                RDB_KV_LIST => todo!(),
-                _ => Ok(None),
+                kind => Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    format!("unsupported value type: {kind}"),
+                )),
             }
         } else {
             Ok(None)
@@ -314,19 +285,21 @@ impl RdbKeyValue {
 impl Encoder<RdbKeyValue> for RdbCodec {
     type Error = std::io::Error;
     fn encode(&mut self, item: RdbKeyValue, dst: &mut BytesMut) -> Result<(), Self::Error> {
-        dst.put_u8(item.kind);
-        Encoder::encode(self, item.key, dst)?;
-        Encoder::encode(self, item.value, dst)?;
         if let Some(expiry) = item.expiry {
             match expiry {
                 Either::Left(milliseconds) => {
+                    dst.put_u8(RDB_CODE_EXPIRY_MS);
                     dst.put_u64_le(milliseconds);
                 }
                 Either::Right(seconds) => {
+                    dst.put_u8(RDB_CODE_EXPIRY);
                     dst.put_u32_le(seconds);
                 }
             }
         }
+        dst.put_u8(item.kind);
+        Encoder::encode(self, item.key, dst)?;
+        Encoder::encode(self, item.value, dst)?;
         Ok(())
     }
 }
@@ -346,6 +319,12 @@ impl RdbLenStr {
         codec: &mut RdbCodec,
         src: &mut BytesMut,
     ) -> Result<Option<Bytes>, std::io::Error> {
+        let Some(first) = src.get(codec.cursor) else {
+            return Ok(None);
+        };
+        if let LenEncoding::Special = LenEncoding::check_val(*first) {
+            return Self::decode_special(codec, src);
+        }
         if let Some(len) = LenEncoding::decode_stream(codec, src)? {
             let Some(out) = &src.get(codec.cursor..(codec.cursor + len)) else {
                 return Ok(None);
@@ -359,6 +338,54 @@ impl RdbLenStr {
     }
 }
 
+impl RdbLenStr {
+    /// Strings stored as integers or compressed with LZF
+    fn decode_special(
+        codec: &mut RdbCodec,
+        src: &mut BytesMut,
+    ) -> Result<Option<Bytes>, std::io::Error> {
+        let format = src[codec.cursor] & 0b0011_1111;
+        codec.cursor += 1;
+        let int_len = match format {
+            0 => 1,
+            1 => 2,
+            2 => 4,
+            3 => {
+                let Some(compressed_len) = LenEncoding::decode_stream(codec, src)? else {
+                    return Ok(None);
+                };
+                let Some(len) = LenEncoding::decode_stream(codec, src)? else {
+                    return Ok(None);
+                };
+                let Some(compressed) = src.get(codec.cursor..(codec.cursor + compressed_len))
+                else {
+                    return Ok(None);
+                };
+                let out = lzf_decompress(compressed, len)?;
+                codec.cursor += compressed_len;
+                return Ok(Some(Bytes::from(out)));
+            }
+            _ => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    format!("invalid value for Integer as string: {format}"),
+                ));
+            }
+        };
+        let Some(value) = src.get(codec.cursor..(codec.cursor + int_len)) else {
+            return Ok(None);
+        };
+        // Integers are little endian and signed
+        let value = match int_len {
+            1 => value[0] as i8 as i64,
+            2 => i16::from_le_bytes(value.try_into().unwrap()) as i64,
+            _ => i32::from_le_bytes(value.try_into().unwrap()) as i64,
+        };
+        codec.cursor += int_len;
+        Ok(Some(Bytes::from(value.to_string())))
+    }
+}
+
 impl From<Bytes> for RdbLenStr {
     fn from(value: Bytes) -> Self {
         Self(value)
@@ -438,58 +465,29 @@ impl LenEncoding {
                     let Some(len) = src.get(codec.cursor..(codec.cursor + 2)) else {
                         return Ok(None);
                     };
-                    let first_bit = len[0] << 2;
-                    let first_bit = first_bit >> 2;
-                    let out = usize::from_be_bytes(vec![first_bit, len[1]].try_into().unwrap());
+                    let out = ((len[0] & 0b0011_1111) as usize) << 8 | len[1] as usize;
                     codec.cursor += 2;
                     Ok(Some(out))
                 }
                 LenEncoding::Discard => {
+                    // 0x80 is followed by a 32 bit length, 0x81 by a 64 bit one
+                    let width = if *first == 0x81 { 8 } else { 4 };
                     codec.cursor += 1;
-                    let Some(len) = src.get(codec.cursor..(codec.cursor + 4)) else {
+                    let Some(len) = src.get(codec.cursor..(codec.cursor + width)) else {
                         return Ok(None);
                     };
-                    let out = usize::from_be_bytes(len.try_into().unwrap());
-                    codec.cursor += 4;
+                    let out = if width == 8 {
+                        u64::from_be_bytes(len.try_into().unwrap()) as usize
+                    } else {
+                        u32::from_be_bytes(len.try_into().unwrap()) as usize
+                    };
+                    codec.cursor += width;
                     Ok(Some(out))
                 }
-                LenEncoding::Special => {
-                    let len = src[codec.cursor] << 2;
-                    let len = len >> 2;
-                    println!("LEN: {len:#010b}");
-                    codec.cursor += 1;
-                    match len {
-                        0 => {
-                            if let Some(first) = src.get(codec.cursor) {
-                                let out = Ok(Some(*first as usize));
-                                codec.cursor += 1;
-                                out
-                            } else {
-                                Ok(None)
-                            }
-                        }
-                        1 => {
-                            let Some(value) = src.get(codec.cursor..(codec.cursor + 2)) else {
-                                return Ok(None);
-                            };
-                            let out = usize::from_be_bytes(value.try_into().unwrap());
-                            codec.cursor += 2;
-                            Ok(Some(out))
-                        }
-                        2 => {
-                            let Some(len) = src.get(codec.cursor..(codec.cursor + 4)) else {
-                                return Ok(None);
-                            };
-                            let out = usize::from_be_bytes(len.try_into().unwrap());
-                            codec.cursor += 4;
-                            Ok(Some(out))
-                        }
-                        _ => Err(std::io::Error::new(
-                            std::io::ErrorKind::InvalidInput,
-                            format!("invalid value for Integer as string: {len}"),
-                        )),
-                    }
-                }
+                LenEncoding::Special => Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    "expected a length, found a special string encoding",
+                )),
             }
         } else {
             Ok(None)
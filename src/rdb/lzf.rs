@@ -0,0 +1,53 @@
+use std::io;
+
+/// Decompresses LZF data as written by Redis for compressed RDB strings,
+/// `len` being the uncompressed length stored alongside it
+pub fn lzf_decompress(input: &[u8], len: usize) -> io::Result<Vec<u8>> {
+    let corrupt = || io::Error::new(io::ErrorKind::InvalidData, "corrupt LZF compressed string");
+    let mut out = Vec::with_capacity(len);
+    let mut idx = 0;
+    while idx < input.len() {
+        let ctrl = input[idx] as usize;
+        idx += 1;
+        if ctrl < 1 << 5 {
+            // A run of ctrl + 1 literal bytes
+            let literal = input.get(idx..idx + ctrl + 1).ok_or_else(corrupt)?;
+            out.extend_from_slice(literal);
+            idx += ctrl + 1;
+        } else {
+            // A back reference, the length is stored minus 2
+            let mut run = ctrl >> 5;
+            if run == 7 {
+                run += *input.get(idx).ok_or_else(corrupt)? as usize;
+                idx += 1;
+            }
+            let offset = ((ctrl & 0x1f) << 8) + *input.get(idx).ok_or_else(corrupt)? as usize + 1;
+            idx += 1;
+            let start = out.len().checked_sub(offset).ok_or_else(corrupt)?;
+            // The reference can overlap the bytes it produces
+            for pos in start..start + run + 2 {
+                out.push(out[pos]);
+            }
+        }
+    }
+    if out.len() != len {
+        return Err(corrupt());
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lzf_decompress() {
+        // A literal `a` followed by an overlapping back reference of 9 bytes
+        let out = lzf_decompress(&[0x00, b'a', 0xe0, 0x00, 0x00], 10).unwrap();
+        assert_eq!(out, b"aaaaaaaaaa");
+        // Literal `abc`, then `abc` again from 3 bytes back, then a literal `d`
+        let out = lzf_decompress(&[0x02, b'a', b'b', b'c', 0x20, 0x02, 0x00, b'd'], 7).unwrap();
+        assert_eq!(out, b"abcabcd");
+        assert!(lzf_decompress(&[0x20, 0x00], 3).is_err());
+    }
+}
@@ -1,3 +1,3 @@
 use crate::mod_flat;
 
-mod_flat!(codec file);
+mod_flat!(codec file lzf);
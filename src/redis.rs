@@ -1,4 +1,4 @@
-use std::{path::PathBuf, sync::Arc};
+use std::{path::PathBuf, sync::Arc, time::Duration};
 
 use either::Either;
 use tokio::{net::TcpListener, sync::RwLock};
@@ -8,10 +8,11 @@ use crate::{
     command::CommandError,
     connection::Connection,
     context::{AppData, Config},
-    database::{LocationError, RedisDatabase},
+    database::{DatabaseError, LocationError, RedisDatabase},
     rdb::RdbFile,
     redis_stream::StreamParseError,
     replica::{MainServer, Replica, ReplicaError, ReplicationInfo},
+    resp::RespType,
 };
 
 pub async fn run(
@@ -42,6 +43,9 @@ pub async fn run(
     if let Either::Right(ref replica) = role {
         replica.handshake(&mut info).await?;
     }
+    if let Either::Left(ref main) = role {
+        tokio::spawn(active_expire(db.clone(), main.clone()));
+    }
     let replication = Arc::new(RwLock::new(info));
     let app_data = AppData {
         db,
@@ -49,6 +53,7 @@ pub async fn run(
         config,
         replication,
         role: role.clone(),
+        client_ids: Default::default(),
     };
     if let Either::Right(ref replica) = app_data.role {
         replica.conn.handle(true, app_data.clone()).await;
@@ -60,12 +65,49 @@ pub async fn run(
     }
 }
 
+/// Runs the active expire cycle forever, sending a `DEL` to the replicas for
+/// every key it removes and an `HDEL` for reclaimed hash fields. Replicas
+/// leave expiring to the main server.
+async fn active_expire(db: Arc<RedisDatabase>, main: MainServer) {
+    // Same as Redis' default of 10 cycles a second, each allowed a quarter of it
+    const CYCLE_INTERVAL: Duration = Duration::from_millis(100);
+    const CYCLE_BUDGET: Duration = Duration::from_millis(25);
+    // Used instead of the interval when the last cycle ran out of time
+    const FAST_CYCLE_INTERVAL: Duration = Duration::from_millis(10);
+
+    loop {
+        let cycle = db.active_expire_cycle(CYCLE_BUDGET).await;
+        if !cycle.keys.is_empty() || !cycle.fields.is_empty() {
+            *main.need_offset.write().await = true;
+            for key in cycle.keys {
+                main.write_to_replicas(RespType::Array(vec![
+                    RespType::bulk_string("DEL"),
+                    RespType::BulkString(key),
+                ]))
+                .await;
+            }
+            for (key, fields) in cycle.fields {
+                let mut command = vec![RespType::bulk_string("HDEL"), RespType::BulkString(key)];
+                command.extend(fields.into_iter().map(RespType::BulkString));
+                main.write_to_replicas(RespType::Array(command)).await;
+            }
+        }
+        if cycle.time_cap_reached {
+            tokio::time::sleep(FAST_CYCLE_INTERVAL).await;
+        } else {
+            tokio::time::sleep(CYCLE_INTERVAL).await;
+        }
+    }
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum RedisError {
     #[error("{0}")]
     Account(#[from] AccountError),
     #[error("ERR {0}")]
     Command(#[from] CommandError),
+    #[error("{0}")]
+    Database(#[from] DatabaseError),
     #[error("ERR {0}")]
     StreamParse(#[from] StreamParseError),
     #[error("ERR {0}")]
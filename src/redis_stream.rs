@@ -90,7 +90,7 @@ pub enum StreamParseError {
     IntParseError(#[from] std::num::ParseIntError),
     #[error("{0}")]
     NumParseError(#[from] std::num::ParseFloatError),
-    #[error("{}", 0.to_string())]
+    #[error("{0}")]
     Other(String),
     #[error("Invalid number of arguments")]
     EmptyArg,
@@ -239,7 +239,7 @@ where
 {
     fn parse_stream(stream: &mut RedisStream) -> Result<Self, StreamParseError> {
         let mut map = HashMap::new();
-        while stream.remaining() > 1 {
+        while stream.remaining() > 0 {
             let key = K::parse_stream(stream)?;
             let value = V::parse_stream(stream)?;
             map.insert(key, value);
@@ -265,7 +265,7 @@ where
 {
     fn parse_stream(stream: &mut RedisStream) -> Result<Self, StreamParseError> {
         let mut index = IndexMap::new();
-        while stream.remaining() > 1 {
+        while stream.remaining() > 0 {
             let key = K::parse_stream(stream)?;
             let value = V::parse_stream(stream)?;
             index.insert(key, value);
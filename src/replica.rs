@@ -48,14 +48,19 @@ impl ReplicationInfo {
     }
 }
 
-impl RedisWrite for ReplicationInfo {
-    fn write_to_buf(&self, buf: &mut bytes::BytesMut) {
-        let output = format!(
+impl ReplicationInfo {
+    /// The body of `INFO replication`
+    pub fn info(&self) -> String {
+        format!(
             "role:{}\nmaster_replid:{}\nmaster_repl_offset:{}\n",
             self.role, self.replication_id, self.offset
-        );
+        )
+    }
+}
 
-        RespType::BulkString(Bytes::from(output)).write_to_buf(buf);
+impl RedisWrite for ReplicationInfo {
+    fn write_to_buf(&self, buf: &mut bytes::BytesMut) {
+        RespType::BulkString(Bytes::from(self.info())).write_to_buf(buf);
     }
 }
 